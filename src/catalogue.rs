@@ -0,0 +1,199 @@
+// OpenRouter model catalogue - cached in ~/.cache/aicommit/openrouter_models.json so Simple Free
+// mode doesn't wait for /models on every commit. A fresh cache is used as is, a stale one is used
+// immediately while a background task revalidates it with the stored ETag.
+
+use std::fs;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::http::http_client;
+use crate::models::{response_retry_after, Failure};
+use crate::retry::{retry, RetryPolicy};
+use crate::utils::{with_file_lock, write_private_file};
+use crate::CATALOGUE_TTL_HOURS;
+
+const MODELS_URL: &str = "https://openrouter.ai/api/v1/models";
+
+/// The cached `/models` response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogueCache {
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub etag: Option<String>,
+    pub models: Vec<Value>,
+}
+
+impl CatalogueCache {
+    pub fn is_fresh(&self) -> bool {
+        chrono::Utc::now() - self.fetched_at < chrono::Duration::hours(CATALOGUE_TTL_HOURS)
+    }
+}
+
+enum Fetched {
+    NotModified,
+    Models { etag: Option<String>, models: Vec<Value> },
+}
+
+/// ~/.cache/aicommit/openrouter_models.json
+pub fn cache_path() -> Result<PathBuf, String> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("aicommit").join("openrouter_models.json"))
+        .ok_or_else(|| "Could not find cache directory".to_string())
+}
+
+/// Load the cached catalogue, if any
+pub fn load_cache() -> Option<CatalogueCache> {
+    let content = fs::read_to_string(cache_path().ok()?).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// The model catalogue: from the cache when it's fresh (or stale, refreshing it in the background),
+/// from the network (retried per `retry_policy`) when there is no cache or `refresh` is set. If the
+/// network fails, any cached copy is used regardless of its age.
+pub async fn load_catalogue(api_key: Option<&str>, refresh: bool, retry_policy: &RetryPolicy) -> Result<Vec<Value>, String> {
+    let cache = load_cache();
+
+    if let (Some(cache), false) = (&cache, refresh) {
+        if !cache.is_fresh() {
+            let api_key = api_key.map(str::to_string);
+            let etag = cache.etag.clone();
+            // Best effort: if the process exits first, the next run tries again
+            tokio::spawn(async move {
+                let _ = refresh_cache(api_key.as_deref(), etag).await;
+            });
+        }
+        return Ok(cache.models.clone());
+    }
+
+    let etag = cache.as_ref().and_then(|cache| cache.etag.clone());
+    match retry(retry_policy, "fetch the OpenRouter model list", || refresh_cache(api_key, etag.clone())).await {
+        Ok(Some(models)) => Ok(models),
+        Ok(None) => Ok(cache.map(|cache| cache.models).unwrap_or_default()),
+        Err(e) => match cache {
+            Some(cache) => {
+                eprintln!("Warning: {}. Using the model list cached at {}.", e, cache.fetched_at.format("%Y-%m-%d %H:%M UTC"));
+                Ok(cache.models)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Fetch /models and update the cache. Returns the new models, or `None` when the server says
+/// the cached copy (matching `etag`) is still current.
+async fn refresh_cache(api_key: Option<&str>, etag: Option<String>) -> Result<Option<Vec<Value>>, Failure> {
+    let fetched = fetch_catalogue(api_key, etag.as_deref()).await?;
+    let path = cache_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+
+    let models = with_file_lock(&path, || {
+        let (cache, models) = match fetched {
+            Fetched::NotModified => match load_cache() {
+                Some(cache) => (CatalogueCache { fetched_at: chrono::Utc::now(), ..cache }, None),
+                None => return Ok(None),
+            },
+            Fetched::Models { etag, models } => (
+                CatalogueCache { fetched_at: chrono::Utc::now(), etag, models: models.clone() },
+                Some(models),
+            ),
+        };
+        let content = serde_json::to_string(&cache)
+            .map_err(|e| format!("Failed to serialize model cache: {}", e))?;
+        write_private_file(&path, &content)?;
+        Ok(models)
+    })?;
+    Ok(models)
+}
+
+async fn fetch_catalogue(api_key: Option<&str>, etag: Option<&str>) -> Result<Fetched, Failure> {
+    let mut request = http_client().map_err(Failure::fatal)?.get(MODELS_URL)
+        .header("HTTP-Referer", "https://suenot.github.io/aicommit/")
+        .header("X-Title", "aicommit");
+    if let Some(api_key) = api_key {
+        request = request.header("Authorization", format!("Bearer {}", api_key));
+    }
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+
+    // Limited by the client's http.timeout_secs
+    let response = request.send().await.map_err(|e| match e.is_timeout() {
+        true => format!("Request to OpenRouter API timed out: {}", e),
+        false => format!("Failed to connect to OpenRouter API: {}", e),
+    })?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let retry_after = response_retry_after(&response);
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(Failure::http(status, &error_text, retry_after, format!("OpenRouter API returned status code: {}", status)));
+    }
+
+    let etag = response.headers().get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut models_response: Value = response.json().await
+        .map_err(|e| format!("Failed to parse OpenRouter API response: {}", e))?;
+    match models_response["data"].take() {
+        Value::Array(models) => Ok(Fetched::Models { etag, models }),
+        _ => Err("OpenRouter API response has no model list".into()),
+    }
+}
+
+/// Price per token of a catalogue entry (`prompt` or `completion`); OpenRouter sends prices as strings
+pub fn model_price(model: &Value, kind: &str) -> Option<f64> {
+    match &model["pricing"][kind] {
+        Value::String(price) => price.parse().ok(),
+        price => price.as_f64(),
+    }
+}
+
+/// `aicommit models list`: one line per model with context length and price per million tokens
+pub fn print_catalogue(models: &[Value], free_models: Option<&[String]>) {
+    let mut rows: Vec<&Value> = models.iter()
+        .filter(|model| match (free_models, model["id"].as_str()) {
+            (Some(free), Some(id)) => free.iter().any(|free_id| free_id == id),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        })
+        .collect();
+    rows.sort_by_key(|model| model["id"].as_str().unwrap_or_default().to_string());
+
+    let format_price = |price: Option<f64>| price.map_or("?".to_string(), |price| {
+        if price == 0.0 { "free".to_string() } else { format!("${:.2}", price * 1_000_000.0) }
+    });
+
+    println!("{:<60} {:>10} {:>10} {:>10}", "MODEL", "CONTEXT", "PROMPT/1M", "OUTPUT/1M");
+    for model in &rows {
+        let context = model["context_length"].as_u64().map_or("?".to_string(), |n| n.to_string());
+        println!("{:<60} {:>10} {:>10} {:>10}",
+            model["id"].as_str().unwrap_or_default(),
+            context,
+            format_price(model_price(model, "prompt")),
+            format_price(model_price(model, "completion")));
+    }
+
+    match load_cache() {
+        Some(cache) => println!("\n{} models, catalogue fetched {}", rows.len(), cache.fetched_at.format("%Y-%m-%d %H:%M UTC")),
+        None => println!("\n{} models", rows.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_model_price_accepts_strings_and_numbers() {
+        assert_eq!(model_price(&json!({"pricing": {"prompt": "0.0000015"}}), "prompt"), Some(0.0000015));
+        assert_eq!(model_price(&json!({"pricing": {"completion": 0}}), "completion"), Some(0.0));
+        assert_eq!(model_price(&json!({"pricing": {}}), "prompt"), None);
+    }
+}
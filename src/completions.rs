@@ -0,0 +1,160 @@
+// Shell completions - the static part is generated from the clap definition, provider IDs and
+// jailed models are looked up at completion time through the hidden `aicommit __complete` command
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use clap::CommandFactory;
+use clap_complete::{generate, Shell};
+use crate::types::{Cli, CompletionKind, CompletionShell, Config, ProviderConfig};
+
+// Each wrapper checks the words before the cursor: `provider use|remove|rename|show|edit|test` and
+// the legacy `--set` complete provider IDs, `models unjail` and the legacy `--unjail` complete
+// jailed models. Everything else falls through to the generated completion.
+
+const BASH_DYNAMIC: &str = r#"_aicommit() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" kind=""
+    case "${COMP_WORDS[COMP_CWORD-2]} ${COMP_WORDS[COMP_CWORD-1]}" in
+        "provider use"|"provider remove"|"provider rename"|"provider show"|"provider edit"|"provider test") kind=providers ;;
+        "models unjail") kind=jailed-models ;;
+    esac
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        --set) kind=providers ;;
+        --unjail) kind=jailed-models ;;
+    esac
+    if [[ -n "${kind}" && "${cur}" != -* ]]; then
+        COMPREPLY=( $(compgen -W "$(aicommit __complete ${kind} 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _aicommit_static "$@"
+}
+
+"#;
+
+const ZSH_DYNAMIC: &str = r#"_aicommit() {
+    local kind
+    case "${words[CURRENT-2]} ${words[CURRENT-1]}" in
+        'provider '(use|remove|rename|show|edit|test)) kind=providers ;;
+        'models unjail') kind=jailed-models ;;
+    esac
+    case "${words[CURRENT-1]}" in
+        --set) kind=providers ;;
+        --unjail) kind=jailed-models ;;
+    esac
+    if [[ -n $kind && $PREFIX != -* ]]; then
+        local -a values
+        values=(${(f)"$(aicommit __complete $kind 2>/dev/null)"})
+        compadd -a values
+        return
+    fi
+    _aicommit_static "$@"
+}
+
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c aicommit -n "__fish_seen_subcommand_from provider; and __fish_prev_arg_in use remove rename show edit test" -f -a "(aicommit __complete providers 2>/dev/null)"
+complete -c aicommit -n "__fish_seen_subcommand_from models; and __fish_prev_arg_in unjail" -f -a "(aicommit __complete jailed-models 2>/dev/null)"
+complete -c aicommit -n "__fish_prev_arg_in --set" -f -a "(aicommit __complete providers 2>/dev/null)"
+complete -c aicommit -n "__fish_prev_arg_in --unjail" -f -a "(aicommit __complete jailed-models 2>/dev/null)"
+"#;
+
+const ELVISH_DYNAMIC: &str = r#"
+var aicommit-static-completer = $edit:completion:arg-completer[aicommit]
+set edit:completion:arg-completer[aicommit] = {|@words|
+    var n = (count $words)
+    if (and (> $n 3) (eq $words[-3] provider) (has-value [use remove rename show edit test] $words[-2])) {
+        aicommit __complete providers
+    } elif (and (> $n 3) (eq $words[-3] models) (eq $words[-2] unjail)) {
+        aicommit __complete jailed-models
+    } elif (and (> $n 2) (eq $words[-2] --set)) {
+        aicommit __complete providers
+    } elif (and (> $n 2) (eq $words[-2] --unjail)) {
+        aicommit __complete jailed-models
+    } else {
+        $aicommit-static-completer $@words
+    }
+}
+"#;
+
+/// Print the completion script for `shell` to stdout
+pub fn print_completions(shell: CompletionShell) -> Result<(), String> {
+    let script = completion_script(shell)?;
+    std::io::stdout().write_all(script.as_bytes())
+        .map_err(|e| format!("Failed to write completion script: {}", e))
+}
+
+/// Print the values for `aicommit __complete <kind>`, one per line.
+/// Runs on every TAB press, so a missing or broken config just completes nothing.
+pub fn print_complete_values(kind: CompletionKind) {
+    let Ok(config) = Config::load_read_only() else {
+        return;
+    };
+    for value in complete_values(&config, kind) {
+        println!("{}", value);
+    }
+}
+
+fn complete_values(config: &Config, kind: CompletionKind) -> Vec<String> {
+    match kind {
+        CompletionKind::Providers => config.providers.iter().map(|p| p.id().to_string()).collect(),
+        CompletionKind::JailedModels => {
+            let now = chrono::Utc::now();
+            let mut models = BTreeSet::new();
+            for provider in &config.providers {
+                if let ProviderConfig::SimpleFreeOpenRouter(simple) = provider {
+                    for (model, stats) in &simple.model_stats {
+                        if stats.blacklisted || stats.jail_until.max(stats.cooldown_until).is_some_and(|until| until > now) {
+                            models.insert(model.clone());
+                        }
+                    }
+                }
+            }
+            models.into_iter().collect()
+        }
+    }
+}
+
+fn completion_script(shell: CompletionShell) -> Result<String, String> {
+    let mut buf = Vec::new();
+    let clap_shell = match shell {
+        CompletionShell::Bash => Shell::Bash,
+        CompletionShell::Zsh => Shell::Zsh,
+        CompletionShell::Fish => Shell::Fish,
+        CompletionShell::Elvish => Shell::Elvish,
+    };
+    generate(clap_shell, &mut Cli::command(), "aicommit", &mut buf);
+    let script = String::from_utf8(buf)
+        .map_err(|e| format!("Generated completion script is not UTF-8: {}", e))?;
+
+    match shell {
+        // Rename the generated entry point and put the dynamic wrapper in its place, so the
+        // script's own registration (complete -F / compdef / autoload) picks up the wrapper
+        CompletionShell::Bash | CompletionShell::Zsh => {
+            let wrapper = if shell == CompletionShell::Bash { BASH_DYNAMIC } else { ZSH_DYNAMIC };
+            if !script.contains("_aicommit() {") {
+                return Err("Unexpected completion script layout: _aicommit() not found".to_string());
+            }
+            Ok(script.replacen("_aicommit() {", &format!("{}_aicommit_static() {{", wrapper), 1))
+        }
+        CompletionShell::Fish => Ok(script + FISH_DYNAMIC),
+        CompletionShell::Elvish => Ok(script + ELVISH_DYNAMIC),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_complete_dynamic_values_through_helper() {
+        for shell in [CompletionShell::Bash, CompletionShell::Zsh, CompletionShell::Fish, CompletionShell::Elvish] {
+            let script = completion_script(shell).unwrap();
+            assert!(script.contains("aicommit __complete"), "{:?}", shell);
+            assert!(script.contains("jailed-models"), "{:?}", shell);
+            if matches!(shell, CompletionShell::Bash | CompletionShell::Zsh) {
+                assert!(script.contains("_aicommit_static() {"), "{:?}", shell);
+                assert!(script.contains("_aicommit_static \"$@\""), "{:?}", shell);
+            }
+        }
+    }
+}
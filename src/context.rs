@@ -0,0 +1,279 @@
+// Repository context for the prompt - branch name and ticket ID, changed files, recent commit
+// subjects and a README excerpt, each toggled in the [context] section of the settings
+
+use std::process::Command;
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::ignore::STUB_HEADER;
+use crate::settings::{repository_root, Settings};
+use crate::CONTEXT_MAX_CHARS;
+
+const DEFAULT_TICKET_PATTERN: &str = r"\b[A-Z][A-Z0-9]+-\d+\b";
+
+/// Where a ticket ID found in the branch name goes in the generated message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TicketPlacement {
+    /// A `Refs: PROJ-123` footer
+    Footer,
+    /// The scope of a Conventional Commits subject without one (`feat(PROJ-123): ...`),
+    /// otherwise a footer
+    Scope,
+    Off,
+}
+
+/// Which collectors run, configurable in the [context] section of the layered settings
+#[derive(Debug, Clone)]
+pub struct ContextOptions {
+    pub branch: bool,
+    pub ticket_pattern: Option<Regex>,
+    pub ticket_placement: TicketPlacement,
+    /// Subjects of this many recent commits (0 disables)
+    pub recent_commits: usize,
+    pub files: bool,
+    /// Lines of the README (0 disables)
+    pub readme_lines: usize,
+    /// Budget for all of the context in characters
+    pub max_chars: usize,
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            branch: true,
+            ticket_pattern: Some(default_ticket_pattern().clone()),
+            ticket_placement: TicketPlacement::Footer,
+            recent_commits: 5,
+            files: true,
+            readme_lines: 0,
+            max_chars: CONTEXT_MAX_CHARS,
+        }
+    }
+}
+
+fn default_ticket_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(DEFAULT_TICKET_PATTERN).unwrap())
+}
+
+impl ContextOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let flag = |key: &str, default: bool| settings.get(key).map_or(default, |_| settings.get_bool(key));
+        let count = |key: &str, default: usize| settings.get_int(key).map_or(default, |n| n.max(0) as usize);
+        Self {
+            branch: flag("context.branch", defaults.branch),
+            ticket_pattern: match settings.get_str("context.ticket_pattern") {
+                Some(pattern) => Regex::new(pattern)
+                    .map_err(|e| eprintln!("Warning: ignoring invalid context.ticket_pattern '{}': {}", pattern, e))
+                    .ok(),
+                None => defaults.ticket_pattern,
+            },
+            ticket_placement: match settings.get_str("context.ticket_placement").map(str::to_lowercase).as_deref() {
+                Some("scope") => TicketPlacement::Scope,
+                Some("off") | Some("none") => TicketPlacement::Off,
+                _ => defaults.ticket_placement,
+            },
+            recent_commits: count("context.recent_commits", defaults.recent_commits),
+            files: flag("context.files", defaults.files),
+            readme_lines: count("context.readme_lines", defaults.readme_lines),
+            max_chars: count("context.max_chars", defaults.max_chars),
+        }
+    }
+
+    /// The ticket ID in the current branch name
+    pub fn ticket(&self) -> Option<String> {
+        if !self.branch {
+            return None;
+        }
+        ticket_in(&current_branch()?, self.ticket_pattern.as_ref()?)
+    }
+}
+
+/// Context collected for one prompt
+#[derive(Debug, Default)]
+pub struct RepositoryContext {
+    pub branch: Option<String>,
+    pub ticket: Option<String>,
+    /// Status letter (A, M, D, R) and path of each file in the diff
+    pub files: Vec<(char, String)>,
+    pub recent_subjects: Vec<String>,
+    pub readme: Vec<String>,
+}
+
+impl RepositoryContext {
+    pub fn collect(options: &ContextOptions, diff: &str) -> Self {
+        let branch = if options.branch { current_branch() } else { None };
+        let ticket = branch.as_deref().zip(options.ticket_pattern.as_ref()).and_then(|(branch, pattern)| ticket_in(branch, pattern));
+        Self {
+            branch,
+            ticket,
+            files: if options.files { changed_files(diff) } else { Vec::new() },
+            recent_subjects: if options.recent_commits > 0 { recent_subjects(options.recent_commits) } else { Vec::new() },
+            readme: if options.readme_lines > 0 { readme_excerpt(options.readme_lines) } else { Vec::new() },
+        }
+    }
+
+    /// The context as prompt text, most useful parts first, cut off at `max_chars`
+    pub fn render(&self, max_chars: usize) -> String {
+        let branch = self.branch.iter().map(|branch| match &self.ticket {
+            Some(ticket) => format!("Branch: {} (ticket {})", branch, ticket),
+            None => format!("Branch: {}", branch),
+        });
+        let sections: Vec<(Option<&str>, Vec<String>)> = vec![
+            (None, branch.collect()),
+            (Some("Changed files:"), self.files.iter().map(|(status, path)| format!("{} {}", status, path)).collect()),
+            (Some("Recent commit subjects (match their style):"), self.recent_subjects.iter().map(|subject| format!("- {}", subject)).collect()),
+            (Some("README excerpt:"), self.readme.clone()),
+        ];
+
+        let mut rendered = String::new();
+        for (title, lines) in sections.into_iter().filter(|(_, lines)| !lines.is_empty()) {
+            let mut block = title.map(|title| format!("{}\n", title)).unwrap_or_default();
+            for line in lines {
+                if rendered.len() + block.len() + line.len() + 1 > max_chars {
+                    break;
+                }
+                block.push_str(&line);
+                block.push('\n');
+            }
+            // A title without any of its lines isn't worth its characters
+            if block.lines().count() > usize::from(title.is_some()) {
+                rendered.push_str(&block);
+            }
+        }
+        rendered.trim_end().to_string()
+    }
+}
+
+/// Context for the prompt of `diff`, empty when every collector is off or found nothing
+pub fn prompt_context(diff: &str, settings: &Settings) -> String {
+    let options = ContextOptions::from_settings(settings);
+    RepositoryContext::collect(&options, diff).render(options.max_chars)
+}
+
+/// Add the ticket ID from the branch name to a generated message, unless it's already there
+pub fn apply_ticket(message: &str, ticket: &str, placement: TicketPlacement) -> String {
+    if placement == TicketPlacement::Off || message.contains(ticket) {
+        return message.to_string();
+    }
+    if placement == TicketPlacement::Scope {
+        static UNSCOPED: OnceLock<Regex> = OnceLock::new();
+        let unscoped = UNSCOPED.get_or_init(|| Regex::new(r"^([a-z]+)(!?): ").unwrap());
+        if let Some(captures) = unscoped.captures(message) {
+            let prefix = captures.get(0).unwrap().as_str();
+            return format!("{}({}){}: {}", &captures[1], ticket, &captures[2], &message[prefix.len()..]);
+        }
+    }
+    format!("{}\n\nRefs: {}", message.trim_end(), ticket)
+}
+
+/// `apply_ticket` with the options from the settings and the current branch
+pub fn apply_branch_ticket(message: &str, settings: &Settings) -> String {
+    let options = ContextOptions::from_settings(settings);
+    match options.ticket() {
+        Some(ticket) => apply_ticket(message, &ticket, options.ticket_placement),
+        None => message.to_string(),
+    }
+}
+
+fn ticket_in(branch: &str, pattern: &Regex) -> Option<String> {
+    pattern.find(branch).map(|ticket| ticket.as_str().to_string())
+}
+
+/// The checked out branch; in a detached checkout (as in GitHub Actions) the branch that
+/// triggered the workflow
+pub fn current_branch() -> Option<String> {
+    git(&["rev-parse", "--abbrev-ref", "HEAD"])
+        .filter(|branch| branch != "HEAD")
+        .or_else(|| ["GITHUB_HEAD_REF", "GITHUB_REF_NAME"].iter().find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty())))
+}
+
+/// Files in a diff with their status, from the summaries of files left out of it and the
+/// `diff --git` headers
+pub fn changed_files(diff: &str) -> Vec<(char, String)> {
+    let status = |change: &str| match change.trim_start_matches("binary ") {
+        change if change.starts_with("added") => 'A',
+        change if change.starts_with("deleted") => 'D',
+        change if change.starts_with("renamed") => 'R',
+        _ => 'M',
+    };
+    let summarised = diff.strip_prefix(STUB_HEADER).into_iter()
+        .flat_map(|stubs| stubs.lines().skip(1).take_while(|line| !line.is_empty()))
+        .filter_map(|line| {
+            let (path, change) = line.strip_prefix("- `")?.split_once("`: ")?;
+            Some((status(change), path.to_string()))
+        });
+
+    let shown = diff.split("diff --git ").skip(1).filter_map(|section| {
+        let header = section.lines().next()?;
+        let path = header.rsplit_once(" b/")?.1.trim().to_string();
+        let change = section.lines().take(6).find_map(|line| {
+            ["new file mode", "deleted file mode", "rename from"].iter()
+                .zip(["added", "deleted", "renamed"])
+                .find_map(|(marker, change)| line.starts_with(marker).then_some(change))
+        }).unwrap_or("modified");
+        Some((status(change), path))
+    });
+
+    summarised.chain(shown).collect()
+}
+
+fn recent_subjects(count: usize) -> Vec<String> {
+    git(&["log", "-n", &count.to_string(), "--no-merges", "--format=%s"])
+        .map(|log| log.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn readme_excerpt(lines: usize) -> Vec<String> {
+    let root = repository_root().unwrap_or_else(|| ".".into());
+    ["README.md", "readme.md", "README.rst", "README.txt", "README"].iter()
+        .find_map(|name| std::fs::read_to_string(root.join(name)).ok())
+        .map(|readme| readme.lines().skip_while(|line| line.trim().is_empty()).take(lines).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticket_placement() {
+        let pattern = Regex::new(DEFAULT_TICKET_PATTERN).unwrap();
+        let ticket = ticket_in("feature/PROJ-123-login-form", &pattern).unwrap();
+        assert_eq!(ticket, "PROJ-123");
+        assert_eq!(ticket_in("main", &pattern), None);
+
+        assert_eq!(apply_ticket("feat: add login form", &ticket, TicketPlacement::Scope), "feat(PROJ-123): add login form");
+        assert_eq!(apply_ticket("feat!: drop v1 API", &ticket, TicketPlacement::Scope), "feat(PROJ-123)!: drop v1 API");
+        assert_eq!(apply_ticket("feat(auth): add login form", &ticket, TicketPlacement::Scope), "feat(auth): add login form\n\nRefs: PROJ-123");
+        assert_eq!(apply_ticket("fix: PROJ-123 crash", &ticket, TicketPlacement::Footer), "fix: PROJ-123 crash");
+        assert_eq!(apply_ticket("fix: crash", &ticket, TicketPlacement::Off), "fix: crash");
+    }
+
+    #[test]
+    fn test_render_respects_the_budget() {
+        let diff = "diff --git a/src/new.rs b/src/new.rs\nnew file mode 100644\n+x\n\
+                    diff --git a/src/main.rs b/src/main.rs\n-a\n+b\n";
+        let with_stubs = format!("{}\n- `logo.png`: binary added\n- `Cargo.lock`: 2 lines changed (lockfile)\n  - serde 1.0.1 -> 1.0.2\n\n{}", STUB_HEADER, diff);
+        assert_eq!(changed_files(&with_stubs)[..2], [('A', "logo.png".to_string()), ('M', "Cargo.lock".to_string())]);
+
+        let context = RepositoryContext {
+            branch: Some("feature/PROJ-7-cache".to_string()),
+            ticket: Some("PROJ-7".to_string()),
+            files: changed_files(diff),
+            recent_subjects: vec!["feat(cache): add TTL".to_string(), "fix(cache): evict on error".to_string()],
+            readme: Vec::new(),
+        };
+        assert_eq!(context.render(1000), "Branch: feature/PROJ-7-cache (ticket PROJ-7)\nChanged files:\nA src/new.rs\nM src/main.rs\n\
+                                           Recent commit subjects (match their style):\n- feat(cache): add TTL\n- fix(cache): evict on error");
+        assert_eq!(context.render(90), "Branch: feature/PROJ-7-cache (ticket PROJ-7)\nChanged files:\nA src/new.rs\nM src/main.rs");
+    }
+}
@@ -0,0 +1,135 @@
+// Data egress policy - which providers may receive a diff, configured per repository in
+// .aicommit.toml and enforced before any provider is called
+
+use std::path::Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use crate::ignore::extract_file_path_from_diff_header;
+use crate::scan::is_local_endpoint;
+use crate::settings::{repository_root, Settings};
+use crate::types::ProviderConfig;
+
+/// Configurable in the [egress] section of the layered settings
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    /// Whether diffs may leave the machine at all
+    pub allow_remote: bool,
+    /// Provider ids or types (`ollama`, `openrouter`, ...) allowed to receive diffs; empty allows all
+    pub only_providers: Vec<String>,
+    /// Gitignore-style patterns of paths whose changes only local providers may see
+    pub local_only_paths: Vec<String>,
+}
+
+impl Default for EgressPolicy {
+    fn default() -> Self {
+        Self { allow_remote: true, only_providers: Vec::new(), local_only_paths: Vec::new() }
+    }
+}
+
+impl EgressPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        Self {
+            allow_remote: settings.get("egress.allow_remote").map_or(defaults.allow_remote, |_| settings.get_bool("egress.allow_remote")),
+            only_providers: settings.get_list("egress.only_providers"),
+            local_only_paths: settings.get_list("egress.local_only_paths"),
+        }
+    }
+
+    /// Fail closed: an error unless every rule allows sending `diff` to `provider`
+    pub fn check(&self, provider: &ProviderConfig, diff: &str) -> Result<(), String> {
+        let name = format!("{} provider {}", provider.kind_name(), provider.id());
+
+        if !self.only_providers.is_empty()
+            && !self.only_providers.iter().any(|allowed| allowed == provider.id() || allowed.eq_ignore_ascii_case(provider.provider())) {
+            return Err(format!("Egress policy: {} is not in egress.only_providers ({})", name, self.only_providers.join(", ")));
+        }
+
+        if is_local_endpoint(provider.endpoint()) {
+            return Ok(());
+        }
+        if !self.allow_remote {
+            return Err(format!(
+                "Egress policy: egress.allow_remote is false, so the diff can't be sent to {} at {}. Use a provider on localhost, such as Ollama",
+                name, provider.endpoint()));
+        }
+        if let Some((path, pattern)) = self.local_only_path(diff) {
+            return Err(format!(
+                "Egress policy: {} matches egress.local_only_paths \"{}\", so the diff can't be sent to {} at {}. Use a provider on localhost, or commit that change separately",
+                path, pattern, name, provider.endpoint()));
+        }
+        Ok(())
+    }
+
+    /// The first changed file that only local providers may see, with the matching pattern
+    fn local_only_path(&self, diff: &str) -> Option<(String, String)> {
+        if self.local_only_paths.is_empty() {
+            return None;
+        }
+        let root = repository_root().unwrap_or_else(|| Path::new(".").to_path_buf());
+        let matcher = build_matcher(&root, &self.local_only_paths);
+
+        diff.lines()
+            .filter(|line| line.starts_with("diff --git "))
+            .filter_map(extract_file_path_from_diff_header)
+            .find_map(|path| match matcher.matched_path_or_any_parents(root.join(&path), false) {
+                ignore::Match::Ignore(glob) => Some((path, glob.original().to_string())),
+                _ => None,
+            })
+    }
+}
+
+fn build_matcher(root: &Path, patterns: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        // An invalid pattern must not silently let the paths it was meant to protect through
+        if builder.add_line(None, pattern).is_err() {
+            let _ = builder.add_line(None, "*");
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Check the policy from the settings before `provider` gets `diff`
+pub fn check_egress(provider: &ProviderConfig, diff: &str, settings: &Settings) -> Result<(), String> {
+    EgressPolicy::from_settings(settings).check(provider, diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::OllamaConfig;
+
+    fn ollama(id: &str, url: &str) -> ProviderConfig {
+        ProviderConfig::Ollama(OllamaConfig {
+            id: id.to_string(),
+            provider: "ollama".to_string(),
+            model: "llama3".to_string(),
+            url: url.to_string(),
+            max_tokens: 200,
+            temperature: 0.2,
+        })
+    }
+
+    #[test]
+    fn test_egress_rules() {
+        let local = ollama("ollama-local", "http://localhost:11434");
+        let remote = ollama("gpu-box", "http://gpu.internal:11434");
+        let diff = "diff --git a/src/main.rs b/src/main.rs\n+x\ndiff --git a/secret/keys.rs b/secret/keys.rs\n+y\n";
+
+        let no_remote = EgressPolicy { allow_remote: false, ..EgressPolicy::default() };
+        assert!(no_remote.check(&local, diff).is_ok());
+        assert!(no_remote.check(&remote, diff).unwrap_err().contains("egress.allow_remote is false"));
+
+        let only = EgressPolicy { only_providers: vec!["ollama-local".to_string()], ..EgressPolicy::default() };
+        assert!(only.check(&local, diff).is_ok());
+        assert!(only.check(&remote, diff).unwrap_err().contains("not in egress.only_providers"));
+        let by_type = EgressPolicy { only_providers: vec!["ollama".to_string()], ..EgressPolicy::default() };
+        assert!(by_type.check(&remote, diff).is_ok());
+
+        let paths = EgressPolicy { local_only_paths: vec!["secret/".to_string()], ..EgressPolicy::default() };
+        assert!(paths.check(&local, diff).is_ok());
+        let error = paths.check(&remote, diff).unwrap_err();
+        assert!(error.starts_with("Egress policy: secret/keys.rs matches egress.local_only_paths \"secret/\""), "{}", error);
+        assert!(paths.check(&remote, "diff --git a/src/main.rs b/src/main.rs\n+x\n").is_ok());
+    }
+}
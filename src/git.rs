@@ -5,10 +5,23 @@ use tracing::info;
 use serde_json::json;
 use crate::types::*;
 use crate::{MAX_DIFF_CHARS, MAX_FILE_DIFF_CHARS};
-use crate::utils::{get_safe_slice_length, parse_duration, save_simple_free_config};
+use crate::utils::{get_safe_slice_length, parse_duration};
+use crate::state::{update_model_stats, update_provider_state};
 use crate::version::{update_version_file, update_cargo_version, update_npm_version, update_github_version};
-use crate::models::{get_available_free_models, fallback_to_preferred_models, find_best_available_model, record_model_failure, record_model_success};
-use crate::ignore::filter_diff_by_ignore_patterns;
+use crate::models::{get_available_free_models, fallback_to_preferred_models, record_model_failure, record_model_success, lint_score, response_retry_after, model_status_label, Failure, FailureKind, FailoverPolicy, FailoverRun, FailoverStep, JailPolicy};
+use crate::ignore::{filter_diff_by_ignore_patterns, print_excluded_summary};
+use crate::secrets::{resolve_secret, redact_secret};
+use crate::settings::Settings;
+use crate::preset::configured_preset;
+use crate::prompt::{build_commit_prompt, build_translation_prompt, PromptStyle};
+use crate::lint::{lint_message, LintRules};
+use crate::retry::{retry, RetryPolicy};
+use crate::http::http_client;
+use crate::scan::guard_diff;
+use crate::egress::check_egress;
+use crate::context::apply_branch_ticket;
+use crate::language::{english_type_keywords, Language};
+use crate::scope::{diff_for_paths, discard_patch, enforce_scope, restage_paths, scope_choice, split_groups, stage_paths, unstage_all};
 
 /// Get the HTTPS URL of a git remote. Converts SSH URLs to HTTPS format.
 /// Returns None if the remote URL cannot be determined.
@@ -55,9 +68,13 @@ fn convert_git_url_to_https(url: &str) -> String {
 }
 
 // From: 020_function_process_git_diff_output.rs
-pub fn process_git_diff_output(diff: &str, skip_aicommitignore: bool) -> String {
+pub fn process_git_diff_output(diff: &str, cli: &Cli, settings: &Settings) -> String {
     // First, filter out ignored files based on .aicommitignore patterns
-    let diff = filter_diff_by_ignore_patterns(diff, skip_aicommitignore);
+    let filtered = filter_diff_by_ignore_patterns(diff, cli.no_aicommitignore, settings);
+    if cli.verbose {
+        print_excluded_summary(&filtered.excluded);
+    }
+    let diff = filtered.diff;
 
     // Early return if diff is small enough
     if diff.len() <= MAX_DIFF_CHARS {
@@ -126,8 +143,8 @@ pub fn process_git_diff_output(diff: &str, skip_aicommitignore: bool) -> String
 }
 
 // From: 028_function_watch_and_commit.rs
-pub async fn watch_and_commit(config: &Config, cli: &Cli) -> Result<(), String> {
-    let wait_for_edit = cli.wait_for_edit.as_ref()
+pub async fn watch_and_commit(config: &Config, cli: &Cli, settings: &Settings, args: &WatchArgs) -> Result<(), String> {
+    let wait_for_edit = args.wait_for_edit.as_ref()
         .map(|w| parse_duration(w))
         .transpose()?;
 
@@ -224,7 +241,7 @@ pub async fn watch_and_commit(config: &Config, cli: &Cli) -> Result<(), String>
                                 // If there are changes to commit, do it immediately
                                 match get_git_diff(cli) {
                                     Ok(diff) if !diff.is_empty() => {
-                                        match run_commit(config, cli).await {
+                                        match run_commit(config, cli, settings).await {
                                             Ok(_) => {
                                                 println!("\nCommitted changes.");
                                                 println!("Continuing to watch for changes...");
@@ -271,7 +288,7 @@ pub async fn watch_and_commit(config: &Config, cli: &Cli) -> Result<(), String>
                             
                             match get_git_diff(cli) {
                                 Ok(diff) if !diff.is_empty() => {
-                                    match run_commit(config, cli).await {
+                                    match run_commit(config, cli, settings).await {
                                         Ok(_) => {
                                             println!("\nCommitted changes.");
                                             println!("Continuing to watch for changes...");
@@ -319,7 +336,7 @@ pub async fn watch_and_commit(config: &Config, cli: &Cli) -> Result<(), String>
                 // Commit the changes
                 match get_git_diff(cli) {
                     Ok(diff) if !diff.is_empty() => {
-                        match run_commit(config, cli).await {
+                        match run_commit(config, cli, settings).await {
                             Ok(_) => {
                                 println!("\nCommitted changes for stable files.");
                                 println!("Continuing to watch for changes...");
@@ -373,7 +390,7 @@ pub fn get_git_diff(cli: &Cli) -> Result<String, String> {
     let status = String::from_utf8_lossy(&status_output.stdout).to_string();
     
     // If --add flag is set and there are unstaged changes, add them
-    if cli.add && status.lines().any(|line| {
+    if cli.commit_args().add && status.lines().any(|line| {
         line.starts_with(" M") || // Modified but not staged
         line.starts_with("MM") || // Modified and staged with new modifications
         line.starts_with("??")    // Untracked files
@@ -390,7 +407,7 @@ pub fn get_git_diff(cli: &Cli) -> Result<String, String> {
     }
 
     // Try to get diff of staged changes
-    let diff_cmd = if cli.dry_run {
+    let diff_cmd = if cli.commit_args().dry_run {
         // For dry run, try to get changes in a more robust way
         // First try --cached, and if it fails, try without --cached
         match Command::new("sh")
@@ -446,13 +463,18 @@ pub fn create_git_commit(message: &str) -> Result<(), String> {
 }
 
 // From: 034_function_run_commit.rs
-pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
+pub async fn run_commit(config: &Config, cli: &Cli, settings: &Settings) -> Result<(), String> {
+    let pull = settings.get_bool("git.pull");
+    let push = settings.get_bool("git.push");
+    let push_all = settings.get_bool("git.push_all");
+
     // Update versions if specified
+    let versioning = cli.version_args();
     let mut new_version = String::new();
 
     // Update version in version file
-    if let Some(version_file) = cli.version_file.as_ref() {
-        if cli.version_iterate {
+    if let Some(version_file) = versioning.version_file.as_ref() {
+        if versioning.version_iterate {
             update_version_file(version_file).await?;
         }
 
@@ -464,7 +486,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
     }
 
     // Update version in Cargo.toml
-    if cli.version_cargo {
+    if versioning.version_cargo {
         if new_version.is_empty() {
             return Err("Error: --version-file must be specified when using --version-cargo".to_string());
         }
@@ -472,7 +494,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
     }
 
     // Update version in package.json
-    if cli.version_npm {
+    if versioning.version_npm {
         if new_version.is_empty() {
             return Err("Error: --version-file must be specified when using --version-npm".to_string());
         }
@@ -480,7 +502,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
     }
 
     // Update version on GitHub
-    if cli.version_github {
+    if versioning.version_github {
         if new_version.is_empty() {
             return Err("Error: --version-file must be specified when using --version-github".to_string());
         }
@@ -488,7 +510,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
     }
 
     // Stage version changes if any version flags were used
-    if cli.version_iterate || cli.version_cargo || cli.version_npm || cli.version_github {
+    if versioning.version_iterate || versioning.version_cargo || versioning.version_npm || versioning.version_github {
         let add_output = Command::new("sh")
             .arg("-c")
             .arg("git add .")
@@ -508,77 +530,14 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
         println!("\n=== Git Diff ===\n{}", diff);
     }
 
-    // Generate commit message based on the active provider
-    let (message, usage_info) = {
-        let active_provider = config.providers.iter().find(|p| match p {
-            ProviderConfig::OpenRouter(c) => c.id == config.active_provider,
-            ProviderConfig::Ollama(c) => c.id == config.active_provider,
-            ProviderConfig::OpenAICompatible(c) => c.id == config.active_provider,
-            ProviderConfig::SimpleFreeOpenRouter(c) => c.id == config.active_provider,
-            ProviderConfig::ClaudeCode(c) => c.id == config.active_provider,
-            ProviderConfig::OpenCode(c) => c.id == config.active_provider,
-        }).ok_or("No active provider found")?;
-
-        let mut attempt_count = 0;
-        loop {
-            if attempt_count > 0 {
-                println!("Retry attempt {} of {}", attempt_count + 1, config.retry_attempts);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            }
-
-            let result = match active_provider {
-                ProviderConfig::OpenRouter(c) => generate_openrouter_commit_message(c, &diff, cli).await,
-                ProviderConfig::Ollama(c) => generate_ollama_commit_message(c, &diff, cli).await,
-                ProviderConfig::OpenAICompatible(c) => generate_openai_compatible_commit_message(c, &diff, cli).await,
-                ProviderConfig::SimpleFreeOpenRouter(c) => {
-                    // We need to mutate the config to track failed models, so we need to load it again to update later
-                    let mut c_clone = c.clone();
-                    let result = generate_simple_free_commit_message(&mut c_clone, &diff, cli).await;
-
-                    // We've already saved the model information in the result
-                    result
-                },
-                ProviderConfig::ClaudeCode(c) => generate_claude_code_commit_message(c, &diff, cli).await,
-                ProviderConfig::OpenCode(c) => generate_opencode_commit_message(c, &diff, cli).await,
-            };
-
-            match result {
-                Ok(result) => {
-                    if attempt_count > 0 {
-                        println!("Successfully generated commit message after {} attempts", attempt_count + 1);
-                    }
-                    break result;
-                }
-                Err(e) => {
-                    println!("Attempt {} failed: {}", attempt_count + 1, e);
-                    attempt_count += 1;
-                    if attempt_count >= config.retry_attempts {
-                        return Err(format!("Failed to generate commit message after {} attempts. Last error: {}", config.retry_attempts, e));
-                    }
-                }
-            }
-        }
-    };
-
-    // Final validation before committing
-    if message.trim().is_empty() {
-        return Err("Aborting commit due to empty commit message.".to_string());
+    // Changes spanning several scopes become one commit per scope with scope.multiple = "split"
+    match split_groups(&diff, settings).filter(|_| cli.commit_args().msg.is_none()) {
+        Some(groups) => commit_per_scope(config, cli, settings, &diff, &groups).await?,
+        None => generate_and_commit(config, cli, settings, &diff).await?,
     }
 
-    println!("Generated commit message: \"{}\"\n", message);
-    println!("Tokens: {}↑ {}↓", usage_info.input_tokens, usage_info.output_tokens);
-    println!("API Cost: ${:.4}", usage_info.total_cost);
-
-    // Display which model was used for Simple Free mode if applicable
-    if let Some(model) = &usage_info.model_used {
-        println!("Model used: {}", model);
-    }
-
-    create_git_commit(&message)?;
-    println!("Commit successfully created.");
-
-    // Pull changes if --pull flag is set
-    if cli.pull {
+    // Pull changes if --pull flag (or git.pull setting) is set
+    if pull {
         // Проверяем, имеет ли текущая ветка upstream
         let check_upstream = Command::new("sh")
             .arg("-c")
@@ -650,8 +609,8 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
         println!("Successfully pulled changes.");
     }
 
-    // Push changes if --push or --push-all flag is set
-    if cli.push || cli.push_all {
+    // Push changes if --push or --push-all flag (or git.push / git.push_all setting) is set
+    if push || push_all {
         // Получаем имя текущей ветки
         let branch_output = Command::new("sh")
             .arg("-c")
@@ -662,7 +621,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
         let branch_name = String::from_utf8_lossy(&branch_output.stdout).trim().to_string();
 
         // Determine which remotes to push to
-        let remotes: Vec<String> = if cli.push_all {
+        let remotes: Vec<String> = if push_all {
             let remotes_output = Command::new("sh")
                 .arg("-c")
                 .arg("git remote")
@@ -712,7 +671,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
             if !push_output.status.success() {
                 let stderr = String::from_utf8_lossy(&push_output.stderr).to_string();
                 eprintln!("Failed to push to {}{}: {}", remote, remote_url_info, stderr.trim());
-                if !cli.push_all {
+                if !push_all {
                     return Err(stderr);
                 }
                 // For --push-all, continue to next remote even if one fails
@@ -722,7 +681,7 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
             println!("Changes successfully pushed to {}/{}{}.", remote, branch_name, remote_url_info);
         }
 
-        if cli.push_all {
+        if push_all {
             println!("Push to all remotes completed ({} remote(s)).", remotes.len());
         }
     }
@@ -730,31 +689,217 @@ pub async fn run_commit(config: &Config, cli: &Cli) -> Result<(), String> {
     Ok(())
 }
 
-// From: 039_function_generate_openrouter_commit_message.rs
-pub async fn generate_openrouter_commit_message(config: &OpenRouterConfig, diff: &str, cli: &Cli) -> Result<(String, UsageInfo), String> {
-    let client = reqwest::Client::new();
+/// Generate a message for `diff` (unless --msg was given) and commit what is staged
+async fn generate_and_commit(config: &Config, cli: &Cli, settings: &Settings, diff: &str) -> Result<(), String> {
+    // Generate commit message based on the active provider, unless --msg was given
+    let (message, usage_info) = if let Some(msg) = &cli.commit_args().msg {
+        (msg.clone(), UsageInfo { input_tokens: 0, output_tokens: 0, total_cost: 0.0, model_used: None })
+    } else {
+        let active_provider = config.active_provider_config(settings)
+            .ok_or("No active provider found")?;
+
+        let retry_policy = RetryPolicy::from_settings(settings).with_attempts(config.retry_attempts).for_provider(active_provider);
+        retry(&retry_policy, "generate commit message", || generate_with_provider(active_provider, diff, cli, settings)).await?
+    };
+
+    // Final validation before committing
+    if message.trim().is_empty() {
+        return Err("Aborting commit due to empty commit message.".to_string());
+    }
+
+    println!("Generated commit message: \"{}\"\n", message);
+    println!("Tokens: {}↑ {}↓", usage_info.input_tokens, usage_info.output_tokens);
+    println!("API Cost: ${:.4}", usage_info.total_cost);
+
+    // Display which model was used for Simple Free mode if applicable
+    if let Some(model) = &usage_info.model_used {
+        println!("Model used: {}", model);
+    }
+
+    for issue in lint_message(&message, &LintRules::from_settings(settings)) {
+        println!("Lint warning: {}", issue);
+    }
+
+    create_git_commit(&message)?;
+    println!("Commit successfully created.");
+    Ok(())
+}
+
+/// Commit each group of paths separately: save the staged changes to a patch under .git and
+/// unstage everything, then stage and commit one group at a time. On failure the changes not
+/// committed yet are staged again; the patch is kept until that or the last commit succeeds.
+async fn commit_per_scope(config: &Config, cli: &Cli, settings: &Settings, diff: &str, groups: &[(String, Vec<String>)]) -> Result<(), String> {
+    println!("The staged changes span {} scopes, committing them separately (scope.multiple = \"split\")", groups.len());
+    let patch = unstage_all()?;
+    println!("Staged changes saved to {} until all commits are made", patch.display());
+
+    for (index, (scope, paths)) in groups.iter().enumerate() {
+        println!("\n=== {} ({} file(s)) ===", if scope.is_empty() { "other changes" } else { scope }, paths.len());
+        let committed = match stage_paths(&patch, paths) {
+            Ok(()) => generate_and_commit(config, cli, settings, &diff_for_paths(diff, paths)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = committed {
+            let remaining: Vec<String> = groups[index..].iter().flat_map(|(_, paths)| paths.clone()).collect();
+            match restage_paths(&patch, &remaining) {
+                Ok(()) => discard_patch(&patch),
+                Err(restore) => eprintln!("Failed to stage the remaining changes again ({}); they are saved in {}", restore, patch.display()),
+            }
+            return Err(e);
+        }
+    }
+    discard_patch(&patch);
+    Ok(())
+}
+
+/// `--translate-to`: translate the --msg message and commit with it, or translate the last
+/// commit's message and amend it. --dry-run only prints the translation.
+pub async fn translate_commit(config: &Config, cli: &Cli, settings: &Settings) -> Result<(), String> {
+    let args = cli.commit_args();
+    let language = args.translate_to.as_deref().and_then(Language::parse)
+        .ok_or("--translate-to needs a language, e.g. --translate-to de")?;
+    let original = match &args.msg {
+        Some(msg) => msg.clone(),
+        None => last_commit_message()?,
+    };
+    if args.msg.is_none() && !args.dry_run {
+        if let Some(upstream) = pushed_to_upstream()? {
+            return Err(format!("The last commit is already on {}, amending it would rewrite published history. Translate a new message with --msg instead.", upstream));
+        }
+    }
 
+    let active_provider = config.active_provider_config(settings)
+        .ok_or("No active provider found")?;
+    let retry_policy = RetryPolicy::from_settings(settings).with_attempts(config.retry_attempts).for_provider(active_provider);
+    let (message, usage_info) = retry(&retry_policy, "translate commit message", || translate_with_provider(active_provider, &original, &language, cli, settings)).await?;
+    if message.trim().is_empty() {
+        return Err("Aborting due to an empty translation.".to_string());
+    }
+
+    println!("Translated commit message: \"{}\"\n", message);
+    println!("Tokens: {}↑ {}↓", usage_info.input_tokens, usage_info.output_tokens);
+    println!("API Cost: ${:.4}", usage_info.total_cost);
+
+    for issue in lint_message(&message, &translation_rules(&language, settings)) {
+        println!("Lint warning: {}", issue);
+    }
+
+    if args.dry_run {
+        return Ok(());
+    }
+    if args.msg.is_some() {
+        create_git_commit(&message)?;
+        println!("Commit successfully created.");
+    } else {
+        let output = Command::new("git")
+            .args(["commit", "--amend", "--only", "-m", &message])
+            .output()
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        println!("Last commit's message replaced.");
+    }
+    Ok(())
+}
+
+/// The upstream branch when HEAD is already on it
+fn pushed_to_upstream() -> Result<Option<String>, String> {
+    let upstream = Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"])
+        .output()
+        .map_err(|e| format!("Failed to run git rev-parse: {}", e))?;
+    if !upstream.status.success() {
+        return Ok(None);
+    }
+    let pushed = Command::new("git")
+        .args(["merge-base", "--is-ancestor", "HEAD", "@{u}"])
+        .status()
+        .map_err(|e| format!("Failed to run git merge-base: {}", e))?;
+    Ok(pushed.success().then(|| String::from_utf8_lossy(&upstream.stdout).trim().to_string()))
+}
+
+fn last_commit_message() -> Result<String, String> {
+    let output = Command::new("git")
+        .args(["log", "-1", "--format=%B"])
+        .output()
+        .map_err(|e| format!("Failed to run git log: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("No commit to translate: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Generate a commit message for `diff` with the given provider
+pub async fn generate_with_provider(provider: &ProviderConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
+    check_egress(provider, diff, settings).map_err(Failure::fatal)?;
+    let (message, usage) = match provider {
+        ProviderConfig::OpenRouter(c) => generate_openrouter_commit_message(c, diff, cli, settings).await,
+        ProviderConfig::Ollama(c) => generate_ollama_commit_message(c, diff, cli, settings).await,
+        ProviderConfig::OpenAICompatible(c) => generate_openai_compatible_commit_message(c, diff, cli, settings).await,
+        ProviderConfig::SimpleFreeOpenRouter(c) => {
+            // Model statistics are recorded in the state file; the clone just carries them for this run
+            let mut c_clone = c.clone();
+            generate_simple_free_commit_message(&mut c_clone, diff, cli, settings).await
+        },
+        ProviderConfig::ClaudeCode(c) => generate_claude_code_commit_message(c, diff, cli, settings).await,
+        ProviderConfig::OpenCode(c) => generate_opencode_commit_message(c, diff, cli, settings).await,
+    }?;
+    Ok((finish_message(&message, diff, settings), usage))
+}
+
+/// Translate an existing commit message with the given provider. Only the message is sent: the
+/// egress policy applies to it, the diff processing doesn't.
+pub async fn translate_with_provider(provider: &ProviderConfig, message: &str, language: &Language, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
+    check_egress(provider, message, settings).map_err(Failure::fatal)?;
+    let prompt = build_translation_prompt(message, language);
+    let (translation, usage) = match provider {
+        ProviderConfig::OpenRouter(c) => openrouter_completion(c, &prompt, cli).await,
+        ProviderConfig::Ollama(c) => ollama_completion(c, &prompt, cli).await,
+        ProviderConfig::OpenAICompatible(c) => openai_compatible_completion(c, &prompt, cli).await,
+        ProviderConfig::SimpleFreeOpenRouter(c) => {
+            let mut c_clone = c.clone();
+            simple_free_completion(&mut c_clone, &prompt, cli, settings, &translation_rules(language, settings)).await
+        },
+        ProviderConfig::ClaudeCode(_) => claude_code_completion(&prompt, cli),
+        ProviderConfig::OpenCode(_) => opencode_completion(&prompt, cli),
+    }?;
+    // A translation keeps the form of the message it was made from
+    Ok((english_type_keywords(&translation), usage))
+}
+
+/// Lint rules for a message translated into `language`
+fn translation_rules(language: &Language, settings: &Settings) -> LintRules {
+    LintRules { language: Some(language.clone()).filter(|language| !language.is_english()), ..LintRules::from_settings(settings) }
+}
+
+/// Rules applied to every generated message: the scope inferred from the changed paths, the
+/// form of the configured convention preset, English type keywords, then the ticket ID from
+/// the branch name
+pub fn finish_message(message: &str, diff: &str, settings: &Settings) -> String {
+    let message = enforce_scope(message, &scope_choice(diff, settings));
+    let message = match configured_preset(settings) {
+        Some(preset) => preset.post_process(&message),
+        None => message,
+    };
+    let message = english_type_keywords(&message);
+    apply_branch_ticket(&message, settings)
+}
+
+// From: 039_function_generate_openrouter_commit_message.rs
+pub async fn generate_openrouter_commit_message(config: &OpenRouterConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
     // Use the smart diff processing function instead of simple truncation
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the git commit message string based on the provided diff. Follow the Conventional Commits specification (type: description). Do NOT include any introductory phrases, explanations, or markdown formatting like ```.
-Examples:
-- feat: Add user authentication feature
-- fix: Correct calculation error in payment module
-- docs: Update README with installation instructions
-- style: Format code according to style guide
-- refactor: Simplify database query logic
-- test: Add unit tests for user service
-- chore: Update dependencies
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+
+    let processed_diff = guard_diff(&processed_diff, "https://openrouter.ai/api/v1/chat/completions", settings, cli.verbose).map_err(Failure::fatal)?;
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Detailed, settings);
+    openrouter_completion(config, &prompt, cli).await
+}
+
+/// Send a prompt to OpenRouter and return the cleaned up answer
+async fn openrouter_completion(config: &OpenRouterConfig, prompt: &str, cli: &Cli) -> Result<(String, UsageInfo), Failure> {
+    let client = http_client().map_err(Failure::fatal)?;
+    let api_key = resolve_secret(&config.api_key).map_err(Failure::fatal)?;
 
     // Show context in verbose mode
     if cli.verbose {
@@ -781,7 +926,7 @@ Commit Message ONLY:",
 
     let response = client
         .post("https://openrouter.ai/api/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", &config.api_key))
+        .header("Authorization", format!("Bearer {}", &api_key))
         .header("HTTP-Referer", "https://suenot.github.io/aicommit/")
         .header("X-Title", "aicommit")
         .header("X-Description", "A CLI tool that generates concise and descriptive git commit messages")
@@ -791,7 +936,10 @@ Commit Message ONLY:",
         .map_err(|e| format!("Failed to send request: {}", e))?;
 
     if !response.status().is_success() {
-        return Err(format!("API request failed: {}", response.status()));
+        let status = response.status();
+        let retry_after = response_retry_after(&response);
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(Failure::http(status, &error_text, retry_after, format!("API request failed: {}", status)));
     }
 
     let response_data: OpenRouterResponse = response
@@ -815,7 +963,7 @@ Commit Message ONLY:",
         .to_string();
 
     if message.is_empty() || message.len() < 3 {
-        return Err("Generated commit message is too short or empty".to_string());
+        return Err("Generated commit message is too short or empty".into());
     }
 
     // Используем информацию о токенах из ответа API
@@ -831,30 +979,18 @@ Commit Message ONLY:",
 }
 
 // From: 040_function_generate_ollama_commit_message.rs
-pub async fn generate_ollama_commit_message(config: &OllamaConfig, diff: &str, cli: &Cli) -> Result<(String, UsageInfo), String> {
-    let client = reqwest::Client::new();
-
+pub async fn generate_ollama_commit_message(config: &OllamaConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
     // Use the smart diff processing function instead of simple truncation
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the raw git commit message string (one line, max 72 chars) based on the diff. Follow Conventional Commits (type: description). Do NOT include any introductory text, explanations, or ```.
-Examples:
-- feat: add user login
-- fix: correct payment calculation
-- docs: update readme
-- style: format code
-- refactor: simplify query
-- test: add user tests
-- chore: update deps
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+
+    let processed_diff = guard_diff(&processed_diff, &config.url, settings, cli.verbose).map_err(Failure::fatal)?;
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Compact, settings);
+    ollama_completion(config, &prompt, cli).await
+}
+
+/// Send a prompt to Ollama and return the cleaned up answer
+async fn ollama_completion(config: &OllamaConfig, prompt: &str, cli: &Cli) -> Result<(String, UsageInfo), Failure> {
+    let client = http_client().map_err(Failure::fatal)?;
 
     // Show context in verbose mode
     if cli.verbose {
@@ -887,8 +1023,9 @@ Commit Message ONLY:",
 
     let status = response.status();
     if !status.is_success() {
+        let retry_after = response_retry_after(&response);
         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("API returned an error ({}): {}", status, error_text));
+        return Err(Failure::http(status, &error_text, retry_after, format!("API returned an error ({}): {}", status, error_text)));
     }
 
     let json: serde_json::Value = response
@@ -906,11 +1043,11 @@ Commit Message ONLY:",
         .ok_or_else(|| "No text found in API response".to_string())?;
 
     if commit_message.is_empty() || commit_message.len() < 3 {
-        return Err("Generated commit message is too short or empty".to_string());
+        return Err("Generated commit message is too short or empty".into());
     }
 
     // For Ollama, we estimate tokens based on characters (rough approximation)
-    let input_tokens = (diff.len() / 4) as i32;
+    let input_tokens = (prompt.len() / 4) as i32;
     let output_tokens = (commit_message.len() / 4) as i32;
     
     let input_cost = input_tokens as f32 * 0.0 / 1000.0;
@@ -928,30 +1065,19 @@ Commit Message ONLY:",
 }
 
 // From: 041_function_generate_openai_compatible_commit_message.rs
-pub async fn generate_openai_compatible_commit_message(config: &OpenAICompatibleConfig, diff: &str, cli: &Cli) -> Result<(String, UsageInfo), String> {
-    let client = reqwest::Client::new();
-
+pub async fn generate_openai_compatible_commit_message(config: &OpenAICompatibleConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
     // Use the smart diff processing function instead of simple truncation
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the git commit message string based on the provided diff. Follow the Conventional Commits specification (type: description). Do NOT include any introductory phrases, explanations, or markdown formatting like ```.
-Examples:
-- feat: Add user authentication feature
-- fix: Correct calculation error in payment module
-- docs: Update README with installation instructions
-- style: Format code according to style guide
-- refactor: Simplify database query logic
-- test: Add unit tests for user service
-- chore: Update dependencies
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+
+    let processed_diff = guard_diff(&processed_diff, &config.api_url, settings, cli.verbose).map_err(Failure::fatal)?;
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Detailed, settings);
+    openai_compatible_completion(config, &prompt, cli).await
+}
+
+/// Send a prompt to an OpenAI compatible API and return the cleaned up answer
+async fn openai_compatible_completion(config: &OpenAICompatibleConfig, prompt: &str, cli: &Cli) -> Result<(String, UsageInfo), Failure> {
+    let client = http_client().map_err(Failure::fatal)?;
+    let api_key = resolve_secret(&config.api_key).map_err(Failure::fatal)?;
 
     // Show context in verbose mode
     if cli.verbose {
@@ -979,7 +1105,7 @@ Commit Message ONLY:",
 
     let response = client
         .post(&config.api_url)
-        .header("Authorization", format!("Bearer {}", &config.api_key))
+        .header("Authorization", format!("Bearer {}", &api_key))
         .json(&request_body)
         .send()
         .await
@@ -987,8 +1113,9 @@ Commit Message ONLY:",
 
     if !response.status().is_success() {
         let status = response.status();
+        let retry_after = response_retry_after(&response);
         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("API request failed: {} - {}", status, error_text));
+        return Err(Failure::http(status, &error_text, retry_after, format!("API request failed: {} - {}", status, error_text)));
     }
 
     let response_data: OpenRouterResponse = response
@@ -1012,7 +1139,7 @@ Commit Message ONLY:",
         .to_string();
 
     if message.is_empty() || message.len() < 3 {
-        return Err("Generated commit message is too short or empty".to_string());
+        return Err("Generated commit message is too short or empty".into());
     }
 
     let usage = UsageInfo {
@@ -1029,30 +1156,46 @@ Commit Message ONLY:",
 pub async fn generate_simple_free_commit_message(
     config: &mut SimpleFreeOpenRouterConfig, 
     diff: &str, 
-    cli: &Cli
-) -> Result<(String, UsageInfo), String> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(15))
-        .build()
-        .unwrap_or_default();
+    cli: &Cli,
+    settings: &Settings
+) -> Result<(String, UsageInfo), Failure> {
+    // Use the smart diff processing function
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+    let processed_diff = guard_diff(&processed_diff, "https://openrouter.ai/api/v1/chat/completions", settings, cli.verbose).map_err(Failure::fatal)?;
+
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Detailed, settings);
+    simple_free_completion(config, &prompt, cli, settings, &LintRules::from_settings(settings)).await
+}
+
+/// Send a prompt to the best free models in turn, failing over and hedging as `failover.*`
+/// says. Answers are scored against `rules` in the model statistics.
+async fn simple_free_completion(
+    config: &mut SimpleFreeOpenRouterConfig,
+    prompt: &str,
+    cli: &Cli,
+    settings: &Settings,
+    rules: &LintRules,
+) -> Result<(String, UsageInfo), Failure> {
+    let client = http_client().map_err(Failure::fatal)?;
+    let api_key = resolve_secret(&config.api_key).map_err(Failure::fatal)?;
     
     // Get available free models
     if cli.verbose {
         println!("\n=== Getting available free models from OpenRouter ===");
-        println!("API Key: {}", config.api_key.chars().take(8).collect::<String>() + "..." + &config.api_key.chars().rev().take(4).collect::<String>());
+        println!("API Key: {}", redact_secret(&config.api_key));
     }
     
-    let available_models = match get_available_free_models(&config.api_key, cli.simulate_offline).await {
+    let available_models = match get_available_free_models(&api_key, cli.simulate_offline, cli.refresh_models, &RetryPolicy::from_settings(settings)).await {
         Ok(models) => models,
         Err(e) => {
-            println!("Error fetching models from OpenRouter: {}", e);
-            println!("This could be due to network connectivity issues or API changes.");
-            println!("Using fallback predefined free models list...");
+            eprintln!("Error fetching models from OpenRouter: {}", e);
+            eprintln!("This could be due to network connectivity issues or API changes.");
+            eprintln!("Using fallback predefined free models list...");
             
             // As a last resort, try to use our predefined list directly
             match fallback_to_preferred_models() {
                 Ok(models) => models,
-                Err(e) => return Err(format!("Failed to get models and fallback also failed: {}", e)),
+                Err(e) => return Err(format!("Failed to get models and fallback also failed: {}", e).into()),
             }
         }
     };
@@ -1068,58 +1211,131 @@ pub async fn generate_simple_free_commit_message(
     }
     
     if available_models.is_empty() {
-        return Err("No free models available on OpenRouter".to_string());
+        return Err("No free models available on OpenRouter".into());
     }
     
-    // Find the best available model using our advanced management system
-    let model = find_best_available_model(&available_models, config)
-        .ok_or_else(|| "Failed to find a suitable model, please try again later".to_string())?;
-    
-    // Use the smart diff processing function
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the git commit message string based on the provided diff. Follow the Conventional Commits specification (type: description). Do NOT include any introductory phrases, explanations, or markdown formatting like ```.
-Examples:
-- feat: Add user authentication feature
-- fix: Correct calculation error in payment module
-- docs: Update README with installation instructions
-- style: Format code according to style guide
-- refactor: Simplify database query logic
-- test: Add unit tests for user service
-- chore: Update dependencies
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let policy = JailPolicy::from_settings(settings);
 
     // Show context in verbose mode
     if cli.verbose {
         println!("\n=== Context for LLM ===");
         println!("Provider: Simple Free OpenRouter");
-        println!("Model: {}", model);
-        let model_status = if let Some(stats) = config.model_stats.get(&model) {
-            if stats.blacklisted {
-                "BLACKLISTED (being retried)"
-            } else if stats.jail_until.is_some() && stats.jail_until.unwrap() > chrono::Utc::now() {
-                "JAILED (being retried)"
-            } else {
-                "ACTIVE"
-            }
-        } else {
-            "NEW (no history)"
-        };
-        println!("Model status: {}", model_status);
         println!("Max tokens: {}", config.max_tokens);
         println!("Temperature: {}", config.temperature);
         println!("\n=== Prompt ===\n{}", prompt);
         println!("\n=== Sending request to API ===");
     }
 
+    // Try the best model; when it fails move on to the next best right away, and when it is slow
+    // race the next best against it. Stops at the first usable message or at the deadline.
+    let mut run = FailoverRun::new(FailoverPolicy::from_settings(settings), tokio::time::Instant::now());
+    let mut in_flight = tokio::task::JoinSet::new();
+
+    loop {
+        let now = tokio::time::Instant::now();
+        let until = match run.next_step(now, &available_models, config, &policy) {
+            FailoverStep::Stop => break,
+            FailoverStep::Wait(until) => until,
+            FailoverStep::Launch(model) => {
+                if cli.verbose {
+                    if let Some(slow) = run.running.first() {
+                        println!("Model {} is slow, racing the next best model against it", slow);
+                    }
+                    let model_status = config.model_stats.get(&model).map_or("NEW (no history)", model_status_label);
+                    println!("Trying model {} ({})", model, model_status);
+                }
+
+                let request = free_model_request(client.clone(), api_key.clone(), model.clone(), prompt.to_string(), config.max_tokens, config.temperature);
+                let task_model = model.clone();
+                in_flight.spawn(async move { (task_model, request.await) });
+                run.running.push(model);
+                run.last_launch = now;
+                continue;
+            }
+        };
+
+        tokio::select! {
+            joined = in_flight.join_next() => {
+                let Some(joined) = joined else { continue };
+                let (model, result): (String, Result<FreeModelReply, (FailureKind, String)>) =
+                    joined.map_err(|e| format!("Model request task failed: {}", e))?;
+                run.running.retain(|running_model| *running_model != model);
+
+                match result {
+                    Ok(reply) => {
+                        let lint_issues = lint_message(&reply.message, rules).len();
+                        update_provider_state(config, |state| {
+                            record_model_success(state.model_stats.entry(model.clone()).or_default(), reply.latency, lint_score(lint_issues));
+                            state.last_used_model = Some(model.clone());
+                        })?;
+
+                        if !run.tried.is_empty() {
+                            println!("Used {} after trying {}", model, format_tried_models(&run.tried));
+                        } else if cli.verbose {
+                            println!("Successfully generated commit message using model: {}", model);
+                        }
+
+                        let usage = UsageInfo {
+                            input_tokens: reply.usage.prompt_tokens,
+                            output_tokens: reply.usage.completion_tokens,
+                            total_cost: 0.0, // It's free!
+                            model_used: Some(model),
+                        };
+                        return Ok((reply.message, usage));
+                    }
+                    Err((kind, error)) => {
+                        update_model_stats(config, &model, |stats| record_model_failure(stats, kind, &policy))?;
+                        if cli.verbose {
+                            println!("Request failed for model {} ({}): {}", model, kind, error);
+                        } else {
+                            println!("Model {} failed ({})", model, kind);
+                        }
+                        // Retrying can't fix a rejected API key, and every other model would be rejected too
+                        if kind == FailureKind::Auth {
+                            return Err(Failure { kind, message: format!("API request failed with model {} ({}): {}", model, kind, error) });
+                        }
+                        run.tried.push((model, kind.to_string()));
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(until) => {}
+        }
+    }
+
+    for model in run.running.drain(..) {
+        run.tried.push((model, "still running at the deadline".to_string()));
+    }
+    if run.tried.is_empty() {
+        return Err("Failed to find a suitable model, please try again later".into());
+    }
+    Err(format!("No free model produced a commit message within {}s, tried {}",
+                run.policy.deadline.as_secs(), format_tried_models(&run.tried)).into())
+}
+
+/// A usable Simple Free response
+struct FreeModelReply {
+    message: String,
+    usage: OpenRouterUsage,
+    latency: std::time::Duration,
+}
+
+fn format_tried_models(tried: &[(String, String)]) -> String {
+    tried.iter()
+        .map(|(model, outcome)| format!("{} ({})", model, outcome))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// One chat completion request to a free model, classified on failure.
+/// Doesn't touch the model statistics, the caller records the outcome.
+async fn free_model_request(
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+    prompt: String,
+    max_tokens: i32,
+    temperature: f32,
+) -> Result<FreeModelReply, (FailureKind, String)> {
     let request_body = json!({
         "model": model,
         "messages": [
@@ -1128,122 +1344,64 @@ Commit Message ONLY:",
                 "content": prompt
             }
         ],
-        "max_tokens": config.max_tokens,
-        "temperature": config.temperature,
+        "max_tokens": max_tokens,
+        "temperature": temperature,
     });
 
     // Function to make an API request
-    let make_request = async {
-        client
-            .post("https://openrouter.ai/api/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", &config.api_key))
-            .header("HTTP-Referer", "https://suenot.github.io/aicommit/")
-            .header("X-Title", "aicommit")
-            .header("X-Description", "A CLI tool that generates concise and descriptive git commit messages")
-            .json(&request_body)
-            .send()
-            .await
-    };
-
-    // Make the request with a timeout
-    let response = match tokio::time::timeout(std::time::Duration::from_secs(30), make_request).await {
-        Ok(result) => match result {
-            Ok(response) => response,
-            Err(e) => {
-                // Record failure with the model
-                let stats = config.model_stats.entry(model.clone()).or_default();
-                record_model_failure(stats);
-                
-                // Save the updated config
-                save_simple_free_config(config)?;
-                
-                return Err(format!("Request error: {}", e));
-            },
-        },
-        Err(_) => {
-            // Record failure with the model - but be careful not to penalize for network timeouts
-            // Only record as a model failure if this is repeated
-            let stats = config.model_stats.entry(model.clone()).or_default();
-            
-            // Check if this is likely a network issue rather than model issue
-            let is_likely_network_issue = stats.failure_count == 0 || 
-                (stats.last_success.is_some() && 
-                 chrono::Utc::now() - stats.last_success.unwrap() < chrono::Duration::hours(1));
-            
-            if !is_likely_network_issue {
-                record_model_failure(stats);
-                save_simple_free_config(config)?;
-            }
-            
-            return Err("Request timed out after 30 seconds".to_string());
-        },
+    let make_request = client
+        .post("https://openrouter.ai/api/v1/chat/completions")
+        .header("Authorization", format!("Bearer {}", &api_key))
+        .header("HTTP-Referer", "https://suenot.github.io/aicommit/")
+        .header("X-Title", "aicommit")
+        .header("X-Description", "A CLI tool that generates concise and descriptive git commit messages")
+        .json(&request_body)
+        .send();
+
+    // The client's http.timeout_secs limits the request. Transport errors are not held against the model.
+    let started = std::time::Instant::now();
+    let response = match make_request.await {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() => return Err((FailureKind::Transport, format!("Request timed out: {}", e))),
+        Err(e) => return Err((FailureKind::Transport, format!("Request error: {}", e))),
     };
 
     if !response.status().is_success() {
-        // Get the status code before consuming the response
+        // Get the status code and rate limit headers before consuming the response
         let status_code = response.status();
-        
+        let retry_after = response_retry_after(&response);
+
         // Try to get the error message from the response
         let error_text = match response.text().await {
             Ok(text) => format!("API error response: {}", text),
             Err(_) => format!("API returned status code: {}", status_code),
         };
-        
-        if cli.verbose {
-            println!("Request failed for model {}: {}", model, error_text);
-        }
-        
-        // Record failure with the model
-        let stats = config.model_stats.entry(model.clone()).or_default();
-        record_model_failure(stats);
-        
-        // Save the updated config
-        save_simple_free_config(config)?;
-        
-        return Err(format!("API request failed with model {}: {}", model, error_text));
+        let kind = FailureKind::from_status(status_code.as_u16(), &error_text, retry_after);
+        return Err((kind, format!("{} {}", status_code, error_text)));
     }
 
     // Try to parse the response body
     let response_text = response.text().await
-        .map_err(|e| format!("Failed to read response body: {}", e))?;
-
-    let response_data: Result<OpenRouterResponse, _> = serde_json::from_str(&response_text);
+        .map_err(|e| (FailureKind::Transport, format!("Failed to read response body: {}", e)))?;
 
-    if let Err(e) = &response_data {
-        if cli.verbose {
-            println!("Failed to parse response: {}", e);
-            println!("Response body: {}", response_text);
+    let response_data: OpenRouterResponse = match serde_json::from_str(&response_text) {
+        Ok(response_data) => response_data,
+        Err(e) => {
+            // OpenRouter reports some upstream errors as {"error": {"code": 429, ...}} with status 200
+            let kind = serde_json::from_str::<serde_json::Value>(&response_text).ok()
+                .and_then(|body| body["error"]["code"].as_u64())
+                .map_or(FailureKind::BadOutput, |code| FailureKind::from_status(code as u16, &response_text, None));
+            return Err((kind, format!("Failed to parse response JSON: {} (Response: {})", e,
+                             if response_text.len() > 100 {
+                                 format!("{}...", &response_text[..get_safe_slice_length(&response_text, 100)])
+                             } else {
+                                 response_text.clone()
+                             })));
         }
-        
-        // Record failure with the model
-        let stats = config.model_stats.entry(model.clone()).or_default();
-        record_model_failure(stats);
-        
-        // Save the updated config
-        save_simple_free_config(config)?;
-        
-        return Err(format!("Failed to parse response JSON: {} (Response: {})", e, 
-                         if response_text.len() > 100 { 
-                             format!("{}...", &response_text[..100]) 
-                         } else { 
-                             response_text.clone() 
-                         }));
-    }
-    
-    let response_data = response_data.unwrap();
-    
-    let raw_message = response_data.choices
-        .get(0)
-        .ok_or_else(|| {
-            // Record failure with the model
-            let stats = config.model_stats.entry(model.clone()).or_default();
-            record_model_failure(stats);
-
-            // Save the updated config
-            let _ = save_simple_free_config(config);
+    };
 
-            "No choices in response"
-        })?
+    let raw_message = response_data.choices.first()
+        .ok_or((FailureKind::BadOutput, "No choices in response".to_string()))?
         .message
         .content
         .clone();
@@ -1257,65 +1415,24 @@ Commit Message ONLY:",
         .to_string();
 
     if message.is_empty() || message.len() < 3 {
-        // Record failure with the model
-        let stats = config.model_stats.entry(model.clone()).or_default();
-        record_model_failure(stats);
-
-        // Save the updated config
-        let _ = save_simple_free_config(config);
-
-        return Err("Generated commit message is too short or empty".to_string());
-    }
-
-    // Calculate usage info
-    let usage = UsageInfo {
-        input_tokens: response_data.usage.prompt_tokens,
-        output_tokens: response_data.usage.completion_tokens,
-        total_cost: 0.0, // It's free!
-        model_used: Some(model.clone()),
-    };
-
-    // Record success with the model
-    let stats = config.model_stats.entry(model.clone()).or_default();
-    record_model_success(stats);
-    
-    // Update last used model
-    config.last_used_model = Some(model.clone());
-    
-    // Save the updated config
-    save_simple_free_config(config)?;
-
-    if cli.verbose {
-        println!("Successfully generated commit message using model: {}", model);
+        return Err((FailureKind::BadOutput, "Generated commit message is too short or empty".to_string()));
     }
 
-    Ok((message, usage))
+    Ok(FreeModelReply { message, usage: response_data.usage, latency: started.elapsed() })
 }
 
 // From: 043_function_generate_claude_code_commit_message.rs
-pub async fn generate_claude_code_commit_message(_config: &ClaudeCodeConfig, diff: &str, cli: &Cli) -> Result<(String, UsageInfo), String> {
+pub async fn generate_claude_code_commit_message(_config: &ClaudeCodeConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
     // Use the smart diff processing function
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the raw git commit message string (one line, max 72 chars) based on the diff. Follow Conventional Commits (type: description). Do NOT include any introductory text, explanations, or ```.
-Examples:
-- feat: add user login
-- fix: correct payment calculation
-- docs: update readme
-- style: format code
-- refactor: simplify query
-- test: add user tests
-- chore: update deps
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+
+    let processed_diff = guard_diff(&processed_diff, "claude CLI", settings, cli.verbose).map_err(Failure::fatal)?;
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Compact, settings);
+    claude_code_completion(&prompt, cli)
+}
 
+/// Run a prompt through the claude CLI and return the cleaned up answer
+fn claude_code_completion(prompt: &str, cli: &Cli) -> Result<(String, UsageInfo), Failure> {
     // Show context in verbose mode
     if cli.verbose {
         println!("\n=== Context for LLM ===");
@@ -1327,7 +1444,7 @@ Commit Message ONLY:",
     // Execute claude CLI with the prompt
     let output = Command::new("claude")
         .arg("-p")
-        .arg(&prompt)
+        .arg(prompt)
         .output()
         .map_err(|e| {
             if e.kind() == std::io::ErrorKind::NotFound {
@@ -1339,7 +1456,7 @@ Commit Message ONLY:",
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Claude CLI failed with exit code {}: {}", output.status.code().unwrap_or(-1), stderr));
+        return Err(format!("Claude CLI failed with exit code {}: {}", output.status.code().unwrap_or(-1), stderr).into());
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -1354,11 +1471,11 @@ Commit Message ONLY:",
         .to_string();
 
     if commit_message.is_empty() || commit_message.len() < 3 {
-        return Err("Generated commit message is too short or empty".to_string());
+        return Err("Generated commit message is too short or empty".into());
     }
 
     // For Claude Code, we estimate tokens based on characters (rough approximation)
-    let input_tokens = (diff.len() / 4) as i32;
+    let input_tokens = (prompt.len() / 4) as i32;
     let output_tokens = (commit_message.len() / 4) as i32;
 
     let usage = UsageInfo {
@@ -1372,29 +1489,17 @@ Commit Message ONLY:",
 }
 
 // From: 044_function_generate_opencode_commit_message.rs
-pub async fn generate_opencode_commit_message(_config: &OpenCodeConfig, diff: &str, cli: &Cli) -> Result<(String, UsageInfo), String> {
+pub async fn generate_opencode_commit_message(_config: &OpenCodeConfig, diff: &str, cli: &Cli, settings: &Settings) -> Result<(String, UsageInfo), Failure> {
     // Use the smart diff processing function
-    let processed_diff = process_git_diff_output(diff, cli.no_aicommitignore);
-
-    let prompt = format!(
-        "Generate ONLY the raw git commit message string (one line, max 72 chars) based on the diff. Follow Conventional Commits (type: description). Do NOT include any introductory text, explanations, or ```.
-Examples:
-- feat: add user login
-- fix: correct payment calculation
-- docs: update readme
-- style: format code
-- refactor: simplify query
-- test: add user tests
-- chore: update deps
-
-Git Diff:
-```diff
-{}
-```
-Commit Message ONLY:",
-        processed_diff
-    );
+    let processed_diff = process_git_diff_output(diff, cli, settings);
+
+    let processed_diff = guard_diff(&processed_diff, "opencode CLI", settings, cli.verbose).map_err(Failure::fatal)?;
+    let prompt = build_commit_prompt(&processed_diff, PromptStyle::Compact, settings);
+    opencode_completion(&prompt, cli)
+}
 
+/// Run a prompt through the opencode CLI and return the cleaned up answer
+fn opencode_completion(prompt: &str, cli: &Cli) -> Result<(String, UsageInfo), Failure> {
     // Show context in verbose mode
     if cli.verbose {
         println!("\n=== Context for LLM ===");
@@ -1406,7 +1511,7 @@ Commit Message ONLY:",
     // Execute opencode CLI with the prompt
     let output = Command::new("opencode")
         .arg("run")
-        .arg(&prompt)
+        .arg(prompt)
         .output()
         .map_err(|e| {
             if e.kind() == std::io::ErrorKind::NotFound {
@@ -1418,7 +1523,7 @@ Commit Message ONLY:",
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("OpenCode CLI failed with exit code {}: {}", output.status.code().unwrap_or(-1), stderr));
+        return Err(format!("OpenCode CLI failed with exit code {}: {}", output.status.code().unwrap_or(-1), stderr).into());
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -1433,11 +1538,11 @@ Commit Message ONLY:",
         .to_string();
 
     if commit_message.is_empty() || commit_message.len() < 3 {
-        return Err("Generated commit message is too short or empty".to_string());
+        return Err("Generated commit message is too short or empty".into());
     }
 
     // For OpenCode, we estimate tokens based on characters (rough approximation)
-    let input_tokens = (diff.len() / 4) as i32;
+    let input_tokens = (prompt.len() / 4) as i32;
     let output_tokens = (commit_message.len() / 4) as i32;
 
     let usage = UsageInfo {
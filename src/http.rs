@@ -0,0 +1,151 @@
+// Shared HTTP client - built once at startup from the [http] settings (proxy, extra root certificates,
+// client certificate, timeouts, user agent) and reused by every provider and the catalogue fetch
+
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::secrets::expand_home;
+use crate::settings::Settings;
+use crate::{HTTP_CONNECT_TIMEOUT_SECS, HTTP_TIMEOUT_SECS};
+
+/// Connection settings from the [http] section of the layered settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpSettings {
+    pub proxy: Option<String>,
+    /// Hosts reached without the proxy (same syntax as NO_PROXY)
+    pub no_proxy: Vec<String>,
+    /// PEM files with extra root certificates, e.g. of a TLS-intercepting proxy
+    pub ca_certs: Vec<String>,
+    /// PEM client certificate and key for mutual TLS
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+    pub user_agent: String,
+}
+
+impl HttpSettings {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let seconds = |key: &str, default: u64| settings.get_int(key).map_or(Duration::from_secs(default), |n| Duration::from_secs(n.max(1) as u64));
+        Self {
+            proxy: settings.get_str("http.proxy").map(str::to_string),
+            no_proxy: settings.get_list("http.no_proxy"),
+            ca_certs: settings.get_list("http.ca_certs"),
+            client_cert: settings.get_str("http.client_cert").map(str::to_string),
+            client_key: settings.get_str("http.client_key").map(str::to_string),
+            connect_timeout: seconds("http.connect_timeout_secs", HTTP_CONNECT_TIMEOUT_SECS),
+            timeout: seconds("http.timeout_secs", HTTP_TIMEOUT_SECS),
+            user_agent: settings.get_str("http.user_agent")
+                .map_or_else(|| format!("aicommit/{}", env!("CARGO_PKG_VERSION")), str::to_string),
+        }
+    }
+
+    /// Build a client with these settings. Without `http.proxy` the usual HTTP(S)_PROXY and
+    /// NO_PROXY environment variables apply.
+    pub fn build_client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .user_agent(&self.user_agent);
+
+        #[cfg(feature = "rustls")]
+        {
+            builder = builder.use_rustls_tls();
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("Invalid http.proxy '{}': {}", proxy, e))?
+                .no_proxy(reqwest::NoProxy::from_string(&self.no_proxy.join(",")));
+            builder = builder.proxy(proxy);
+        }
+
+        for path in &self.ca_certs {
+            let pem = read_pem(path, "http.ca_certs")?;
+            for certificate in split_pem_certificates(&pem) {
+                let certificate = reqwest::Certificate::from_pem(certificate.as_bytes())
+                    .map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = read_pem(cert_path, "http.client_cert")?;
+                let key = read_pem(key_path, "http.client_key")?;
+                builder = builder.identity(client_identity(&cert, &key)
+                    .map_err(|e| format!("Invalid client certificate {} / key {}: {}", cert_path, key_path, e))?);
+            }
+            (None, None) => {}
+            _ => return Err("http.client_cert and http.client_key must be set together".to_string()),
+        }
+
+        builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+    }
+}
+
+static CLIENT: OnceLock<Result<reqwest::Client, String>> = OnceLock::new();
+
+/// Build the shared client from the resolved settings. Called once at startup; an invalid
+/// [http] section is reported by the first request instead.
+pub fn init_http_client(settings: &Settings) {
+    CLIENT.get_or_init(|| HttpSettings::from_settings(settings).build_client()
+        .map_err(|e| format!("Invalid [http] settings: {}", e)));
+}
+
+/// The shared client. Clones share one connection pool.
+pub fn http_client() -> Result<reqwest::Client, String> {
+    CLIENT.get().cloned().unwrap_or_else(|| Err("HTTP client used before init_http_client".to_string()))
+}
+
+fn read_pem(path: &str, setting: &str) -> Result<String, String> {
+    let path = expand_home(path);
+    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {} ({}): {}", path, setting, e))
+}
+
+/// A CA bundle holds many certificates, `Certificate::from_pem` takes one
+fn split_pem_certificates(pem: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.split_inclusive(END)
+        .filter_map(|block| block.find("-----BEGIN CERTIFICATE-----").map(|start| block[start..].to_string()))
+        .filter(|block| block.ends_with(END))
+        .collect()
+}
+
+#[cfg(feature = "rustls")]
+fn client_identity(cert: &str, key: &str) -> reqwest::Result<reqwest::Identity> {
+    reqwest::Identity::from_pem(format!("{}\n{}", key, cert).as_bytes())
+}
+
+#[cfg(not(feature = "rustls"))]
+fn client_identity(cert: &str, key: &str) -> reqwest::Result<reqwest::Identity> {
+    reqwest::Identity::from_pkcs8_pem(cert.as_bytes(), key.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pem_bundle() {
+        let bundle = "# Root 1\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+                      # Root 2\n-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\ntrailing";
+        assert_eq!(split_pem_certificates(bundle), vec![
+            "-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----".to_string(),
+            "-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_proxy_settings_build_a_client() {
+        let settings = HttpSettings {
+            proxy: Some("http://proxy.internal:3128".to_string()),
+            ..HttpSettings::from_settings(&Settings::defaults())
+        };
+        assert_eq!(settings.no_proxy, vec!["localhost", "127.0.0.1", "::1"]);
+        assert!(settings.user_agent.starts_with("aicommit/"));
+        assert!(settings.build_client().is_ok());
+
+        let half_identity = HttpSettings { client_cert: Some("cert.pem".to_string()), ..settings };
+        assert!(half_identity.build_client().unwrap_err().contains("must be set together"));
+    }
+}
@@ -1,10 +1,16 @@
 // .aicommitignore support module
 // Provides parsing and filtering for files that should be excluded from LLM diff analysis
 
-use std::fs;
-use std::path::Path;
-use glob::Pattern;
+use std::cell::RefCell;
+use std::fmt;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use ignore::Match;
 use tracing::debug;
+use crate::lockfile::{is_lockfile, version_changes};
+use crate::settings::{repository_root, user_config_dir, Settings};
+use crate::LOCKFILE_MAX_CHANGES;
 
 /// Default patterns for files that should always be ignored
 /// These include binary files, lock files, and other files that don't provide
@@ -120,84 +126,167 @@ pub const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
     "*.generated.cs",
 ];
 
-/// Structure representing the aicommitignore configuration
+/// Name of the per-directory ignore files
+pub const IGNORE_FILE_NAME: &str = ".aicommitignore";
+
+/// First line of the list of files summarised instead of shown in the filtered diff
+pub const STUB_HEADER: &str = "Changed files not shown in full:";
+
+/// Marks rules from the ignore.patterns setting (rules from files carry the file's path)
+const SETTING_SOURCE: &str = "<ignore.patterns>";
+
+/// Where an ignore rule was defined
+#[derive(Debug, Clone, PartialEq)]
+pub enum IgnoreSource {
+    BuiltIn,
+    Setting,
+    File { path: PathBuf, line: Option<usize> },
+}
+
+impl fmt::Display for IgnoreSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreSource::BuiltIn => write!(f, "built-in default"),
+            IgnoreSource::Setting => write!(f, "ignore.patterns setting"),
+            IgnoreSource::File { path, line: Some(line) } => write!(f, "{}:{}", path.display(), line),
+            IgnoreSource::File { path, line: None } => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The rule that decides whether a path is excluded
+#[derive(Debug, Clone, PartialEq)]
+pub struct IgnoreRule {
+    pub pattern: String,
+    pub source: IgnoreSource,
+    /// A `!` rule, which keeps the path in the diff
+    pub negated: bool,
+}
+
+/// Files excluded from the diff sent to the LLM, with gitignore semantics: negation (`!`),
+/// anchoring (`/build`), directory rules (`build/`) and `**`.
+///
+/// Rules are read from, lowest precedence first: the built-in defaults, the global
+/// `~/.config/aicommit/ignore`, the `ignore.patterns` setting, the `.aicommitignore` in the
+/// repository root, then `.aicommitignore` files in subdirectories (deeper ones win).
+/// Paths are relative to the repository root, wherever aicommit runs from.
 #[derive(Debug)]
 pub struct AiCommitIgnore {
-    patterns: Vec<Pattern>,
+    root: PathBuf,
+    /// Defaults, global file, setting and the root .aicommitignore
+    base: Gitignore,
+    /// .aicommitignore of subdirectories, loaded when a path below them is checked
+    nested: RefCell<HashMap<PathBuf, Option<Gitignore>>>,
 }
 
 impl AiCommitIgnore {
-    /// Create a new AiCommitIgnore instance
-    /// Loads patterns from .aicommitignore file and includes default patterns
-    pub fn new() -> Self {
-        let mut patterns = Vec::new();
-
-        // Load default patterns first
-        for pattern_str in DEFAULT_IGNORE_PATTERNS {
-            if let Ok(pattern) = Pattern::new(pattern_str) {
-                patterns.push(pattern);
-            }
+    /// Create a new AiCommitIgnore instance for the current repository
+    pub fn new(settings: &Settings) -> Self {
+        let root = repository_root()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("."));
+        let extra_patterns = settings.get_list("ignore.patterns");
+        let global_file = user_config_dir().map(|dir| dir.join("ignore"));
+        Self::with_root(&root, global_file.as_deref(), &extra_patterns)
+    }
+
+    pub fn with_root(root: &Path, global_file: Option<&Path>, extra_patterns: &[String]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in DEFAULT_IGNORE_PATTERNS {
+            add_line(&mut builder, None, pattern);
+        }
+        if let Some(global_file) = global_file.filter(|path| path.is_file()) {
+            add_file(&mut builder, global_file);
+        }
+        for pattern in extra_patterns {
+            add_line(&mut builder, Some(PathBuf::from(SETTING_SOURCE)), pattern);
+        }
+        let root_file = root.join(IGNORE_FILE_NAME);
+        if root_file.is_file() {
+            add_file(&mut builder, &root_file);
         }
 
-        // Load patterns from .aicommitignore file if it exists
-        if let Ok(content) = fs::read_to_string(".aicommitignore") {
-            for line in content.lines() {
-                let line = line.trim();
+        let base = builder.build().unwrap_or_else(|e| {
+            debug!("Failed to build ignore rules: {}", e);
+            Gitignore::empty()
+        });
+        Self { root: root.to_path_buf(), base, nested: RefCell::new(HashMap::new()) }
+    }
 
-                // Skip empty lines and comments
-                if line.is_empty() || line.starts_with('#') {
-                    continue;
-                }
+    /// The repository root the paths are relative to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
 
-                // Handle negation patterns (lines starting with !)
-                // For simplicity, we just skip negation patterns for now
-                // A full implementation would require more complex logic
-                if line.starts_with('!') {
-                    continue;
-                }
+    /// Check if a file path (relative to the repository root) should be ignored
+    pub fn is_ignored(&self, file_path: &str) -> bool {
+        self.matched(file_path).is_ignore()
+    }
+
+    /// The rule deciding about a path, `None` when no rule matches (the path is kept)
+    pub fn explain(&self, file_path: &str) -> Option<IgnoreRule> {
+        let glob = match self.matched(file_path) {
+            Match::None => return None,
+            Match::Ignore(glob) | Match::Whitelist(glob) => glob,
+        };
+        let source = match glob.from() {
+            None => IgnoreSource::BuiltIn,
+            Some(path) if path == Path::new(SETTING_SOURCE) => IgnoreSource::Setting,
+            Some(path) => {
+                // Later lines win, so the deciding rule is the last line with this pattern
+                let line = std::fs::read_to_string(path).ok().and_then(|content| {
+                    content.lines().enumerate().filter(|(_, line)| line.trim() == glob.original()).last().map(|(index, _)| index + 1)
+                });
+                let path = path.strip_prefix(&self.root).unwrap_or(path).to_path_buf();
+                IgnoreSource::File { path, line }
+            }
+        };
+        Some(IgnoreRule { pattern: glob.original().to_string(), source, negated: glob.is_whitelist() })
+    }
 
-                if let Ok(pattern) = Pattern::new(line) {
-                    patterns.push(pattern);
-                } else {
-                    debug!("Invalid pattern in .aicommitignore: {}", line);
+    /// The deciding rule for a path: the deepest .aicommitignore with a matching rule wins,
+    /// within one file the last matching line wins
+    fn matched(&self, file_path: &str) -> Match<Glob> {
+        let path = self.root.join(file_path);
+        let mut nested = self.nested.borrow_mut();
+
+        let mut dir = Path::new(file_path).parent();
+        while let Some(relative_dir) = dir.filter(|dir| !dir.as_os_str().is_empty()) {
+            let matcher = nested.entry(relative_dir.to_path_buf())
+                .or_insert_with(|| load_nested(&self.root.join(relative_dir)));
+            if let Some(matcher) = matcher {
+                match matcher.matched_path_or_any_parents(&path, false) {
+                    Match::None => {}
+                    decided => return decided.map(Glob::clone),
                 }
             }
+            dir = relative_dir.parent();
         }
 
-        Self { patterns }
+        self.base.matched_path_or_any_parents(&path, false).map(Glob::clone)
     }
+}
 
-    /// Check if a file path should be ignored based on the patterns
-    pub fn is_ignored(&self, file_path: &str) -> bool {
-        // Extract just the filename for pattern matching
-        let filename = Path::new(file_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or(file_path);
-
-        for pattern in &self.patterns {
-            // Try matching against the full path
-            if pattern.matches(file_path) {
-                return true;
-            }
-            // Also try matching against just the filename
-            if pattern.matches(filename) {
-                return true;
-            }
-            // Try matching with path components for patterns like "dir/*.ext"
-            if pattern.matches_path(Path::new(file_path)) {
-                return true;
-            }
-        }
+fn add_line(builder: &mut GitignoreBuilder, from: Option<PathBuf>, line: &str) {
+    if let Err(e) = builder.add_line(from, line) {
+        debug!("Invalid ignore pattern {}: {}", line, e);
+    }
+}
 
-        false
+fn add_file(builder: &mut GitignoreBuilder, path: &Path) {
+    if let Some(e) = builder.add(path) {
+        debug!("Invalid pattern in {}: {}", path.display(), e);
     }
 }
 
-impl Default for AiCommitIgnore {
-    fn default() -> Self {
-        Self::new()
+fn load_nested(dir: &Path) -> Option<Gitignore> {
+    let file = dir.join(IGNORE_FILE_NAME);
+    if !file.is_file() {
+        return None;
     }
+    let mut builder = GitignoreBuilder::new(dir);
+    add_file(&mut builder, &file);
+    builder.build().ok()
 }
 
 /// Extract file path from a git diff section header
@@ -228,23 +317,36 @@ pub fn extract_file_path_from_diff_header(header: &str) -> Option<String> {
     None
 }
 
-/// Filter a git diff output by removing sections for ignored files
+/// A diff without the sections of ignored files
+#[derive(Debug, Default)]
+pub struct FilteredDiff {
+    pub diff: String,
+    /// Excluded files with the size of their diff section in bytes
+    pub excluded: Vec<(String, usize)>,
+}
+
+/// Filter a git diff output by replacing sections for ignored files with one-line summaries
 /// If `skip_filter` is true, returns the original diff unchanged
-pub fn filter_diff_by_ignore_patterns(diff: &str, skip_filter: bool) -> String {
+pub fn filter_diff_by_ignore_patterns(diff: &str, skip_filter: bool, settings: &Settings) -> FilteredDiff {
     if skip_filter {
-        return diff.to_string();
+        return FilteredDiff { diff: diff.to_string(), excluded: Vec::new() };
     }
-    let ignore = AiCommitIgnore::new();
+    filter_diff(diff, &AiCommitIgnore::new(settings))
+}
 
+/// Replace the sections of files ignored by `ignore`, and of binary files, with stubs listed
+/// ahead of the diff (where truncation can't cut them off), remembering what was removed
+pub fn filter_diff(diff: &str, ignore: &AiCommitIgnore) -> FilteredDiff {
     // Split the diff into file sections
     let file_pattern = "diff --git ";
     let sections: Vec<&str> = diff.split(file_pattern).collect();
 
-    let mut filtered = String::new();
+    let mut filtered = FilteredDiff::default();
+    let mut stubs = Vec::new();
 
     // First section might be empty or contain only whitespace
     if !sections.is_empty() && !sections[0].trim().is_empty() {
-        filtered.push_str(sections[0]);
+        filtered.diff.push_str(sections[0]);
     }
 
     // Process each file section
@@ -260,18 +362,127 @@ pub fn filter_diff_by_ignore_patterns(diff: &str, skip_filter: bool) -> String {
         if let Some(file_path) = extract_file_path_from_diff_header(&format!("diff --git {}", first_line)) {
             if ignore.is_ignored(&file_path) {
                 debug!("Ignoring diff for file: {}", file_path);
+                stubs.push(summarise_section(&file_path, section));
+                filtered.excluded.push((file_path, file_pattern.len() + section.len()));
+                continue;
+            }
+            if is_binary_section(section) {
+                stubs.push(summarise_section(&file_path, section));
                 continue;
             }
         }
 
         // Include this section in the filtered output
-        filtered.push_str(file_pattern);
-        filtered.push_str(section);
+        filtered.diff.push_str(file_pattern);
+        filtered.diff.push_str(section);
     }
 
+    if !stubs.is_empty() {
+        filtered.diff = format!("{}\n{}\n\n{}", STUB_HEADER, stubs.join("\n"), filtered.diff);
+    }
     filtered
 }
 
+fn is_binary_section(section: &str) -> bool {
+    section.lines().any(|line| line.starts_with("Binary files ") || line == "GIT binary patch")
+}
+
+/// One line for a left-out file, e.g. "- `Cargo.lock`: 42 lines changed (lockfile)" followed
+/// by the dependency version changes, or "- `logo.png`: binary modified"
+fn summarise_section(path: &str, section: &str) -> String {
+    let change = if section.contains("\nnew file mode") {
+        "added"
+    } else if section.contains("\ndeleted file mode") {
+        "deleted"
+    } else if section.contains("\nrename from") {
+        "renamed"
+    } else {
+        "modified"
+    };
+    if is_binary_section(section) {
+        return format!("- `{}`: binary {}", path, change);
+    }
+
+    let lines = section.lines()
+        .skip_while(|line| !line.starts_with("@@"))
+        .filter(|line| line.starts_with('+') || line.starts_with('-'))
+        .count();
+    let size = match change {
+        "modified" => format!("{} lines changed", lines),
+        change => format!("{}, {} lines changed", change, lines),
+    };
+    if !is_lockfile(path) {
+        return format!("- `{}`: {} (excluded)", path, size);
+    }
+
+    let mut stub = format!("- `{}`: {} (lockfile)", path, size);
+    let changes = version_changes(path, section);
+    for change in changes.iter().take(LOCKFILE_MAX_CHANGES) {
+        stub.push_str(&format!("\n  - {}", change));
+    }
+    if changes.len() > LOCKFILE_MAX_CHANGES {
+        stub.push_str(&format!("\n  - and {} more", changes.len() - LOCKFILE_MAX_CHANGES));
+    }
+    stub
+}
+
+/// `--verbose`: which files were left out of the prompt and how much that saved
+pub fn print_excluded_summary(excluded: &[(String, usize)]) {
+    if excluded.is_empty() {
+        return;
+    }
+    println!("\n=== Excluded from the diff ===");
+    for (path, bytes) in excluded {
+        println!("  {} ({})", path, format_bytes(*bytes));
+    }
+    let total: usize = excluded.iter().map(|(_, bytes)| bytes).sum();
+    println!("{} file(s) excluded, {} saved (see 'aicommit ignore check <path>')", excluded.len(), format_bytes(total));
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+/// `aicommit ignore check <path>...`: whether each path is excluded from the diff, and by which rule
+pub fn print_ignore_check(paths: &[String], settings: &Settings) -> Result<(), String> {
+    let ignore = AiCommitIgnore::new(settings);
+    let cwd = std::env::current_dir().map_err(|e| format!("Failed to get current directory: {}", e))?;
+
+    for path in paths {
+        let Some(relative) = repository_path(ignore.root(), &cwd, path) else {
+            println!("{}: outside the repository", path);
+            continue;
+        };
+        let relative = relative.to_string_lossy();
+        match ignore.explain(&relative) {
+            Some(rule) if rule.negated => println!("{}: included, re-included by \"{}\" ({})", relative, rule.pattern, rule.source),
+            Some(rule) => println!("{}: excluded by \"{}\" ({})", relative, rule.pattern, rule.source),
+            None => println!("{}: included (no rule matches)", relative),
+        }
+    }
+    Ok(())
+}
+
+/// A path given on the command line (relative to `cwd`, or absolute) relative to the repository
+/// root, resolving `.` and `..` without touching the file system since the file may be deleted
+fn repository_path(root: &Path, cwd: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved.strip_prefix(root).ok().map(Path::to_path_buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,7 +501,12 @@ mod tests {
 
     #[test]
     fn test_default_patterns() {
-        let ignore = AiCommitIgnore::new();
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join(".aicommitignore"), "*.generated.ts\n").unwrap();
+        let ignore = AiCommitIgnore::with_root(repo.path(), None, &[]);
+
+        // Test the repository's own ignore file
+        assert!(ignore.is_ignored("src/api.generated.ts"));
 
         // Test binary files
         assert!(ignore.is_ignored("test.exe"));
@@ -341,21 +557,97 @@ index ccccccc..ddddddd 100644
 +New line
 "#;
 
-        let filtered = filter_diff_by_ignore_patterns(diff, false);
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join(".aicommitignore"), "docs/\n").unwrap();
+        let filtered = filter_diff(diff, &AiCommitIgnore::with_root(repo.path(), None, &[])).diff;
 
         // Should contain main.rs diff
         assert!(filtered.contains("src/main.rs"));
 
-        // Should NOT contain package-lock.json diff
-        assert!(!filtered.contains("package-lock.json"));
+        // Should NOT contain package-lock.json diff, only its stub
+        assert!(!filtered.contains("diff --git a/package-lock.json"));
+        assert!(filtered.starts_with("Changed files not shown in full:\n- `package-lock.json`: 0 lines changed (lockfile)\n\n"));
 
         // Should contain README.md diff
         assert!(filtered.contains("README.md"));
 
         // Test with skip_filter = true - should contain all files
-        let unfiltered = filter_diff_by_ignore_patterns(diff, true);
+        let unfiltered = filter_diff_by_ignore_patterns(diff, true, &Settings::defaults()).diff;
         assert!(unfiltered.contains("src/main.rs"));
         assert!(unfiltered.contains("package-lock.json"));
         assert!(unfiltered.contains("README.md"));
     }
+
+    #[test]
+    fn test_gitignore_semantics() {
+        let repo = tempfile::tempdir().unwrap();
+        let root = repo.path();
+        std::fs::write(root.join(".aicommitignore"), "/build\ndocs/**/*.html\ntmp/\n!Cargo.lock\n*.log\n").unwrap();
+        std::fs::create_dir_all(root.join("web/assets")).unwrap();
+        std::fs::write(root.join("web/.aicommitignore"), "!keep.log\nassets/*.css\n").unwrap();
+        let global = root.join("global-ignore");
+        std::fs::write(&global, "*.snap\n").unwrap();
+
+        let ignore = AiCommitIgnore::with_root(root, Some(&global), &["fixtures/**".to_string()]);
+
+        // Anchored: only build/ at the root
+        assert!(ignore.is_ignored("build/out.txt"));
+        assert!(!ignore.is_ignored("src/build/mod.rs"));
+        // ** and directory rules
+        assert!(ignore.is_ignored("docs/api/v1/index.html"));
+        assert!(ignore.is_ignored("src/tmp/scratch.rs"));
+        // Negation re-includes a default
+        assert!(!ignore.is_ignored("Cargo.lock"));
+        assert!(ignore.is_ignored("yarn.lock"));
+        // Nested file, relative to its directory, overriding the root file
+        assert!(ignore.is_ignored("web/assets/site.css"));
+        assert!(!ignore.is_ignored("assets/site.css"));
+        assert!(ignore.is_ignored("web/debug.log"));
+        assert!(!ignore.is_ignored("web/keep.log"));
+        // Global file and setting
+        assert!(ignore.is_ignored("tests/output.snap"));
+        assert!(ignore.is_ignored("fixtures/a/b.json"));
+        assert!(!ignore.is_ignored("src/main.rs"));
+    }
+
+    #[test]
+    fn test_explain_names_the_deciding_rule() {
+        let repo = tempfile::tempdir().unwrap();
+        let root = repo.path();
+        std::fs::write(root.join(".aicommitignore"), "# comment\n*.snap\n!Cargo.lock\n").unwrap();
+        let ignore = AiCommitIgnore::with_root(root, None, &["fixtures/".to_string()]);
+
+        let rule = ignore.explain("yarn.lock").unwrap();
+        assert_eq!((rule.pattern.as_str(), &rule.source, rule.negated), ("*.lock", &IgnoreSource::BuiltIn, false));
+
+        let rule = ignore.explain("Cargo.lock").unwrap();
+        assert!(rule.negated);
+        assert_eq!(rule.source, IgnoreSource::File { path: PathBuf::from(".aicommitignore"), line: Some(3) });
+
+        assert_eq!(ignore.explain("fixtures/a.json").unwrap().source, IgnoreSource::Setting);
+        assert_eq!(ignore.explain("src/main.rs"), None);
+
+        assert_eq!(repository_path(root, &root.join("src"), "../web/./app.js"), Some(PathBuf::from("web/app.js")));
+        assert_eq!(repository_path(root, root, "../elsewhere"), None);
+    }
+
+    #[test]
+    fn test_excluded_files_become_stubs() {
+        let diff = "diff --git a/Cargo.lock b/Cargo.lock\nindex 1..2 100644\n--- a/Cargo.lock\n+++ b/Cargo.lock\n\
+                    @@ -1,4 +1,4 @@\n [[package]]\n name = \"serde\"\n-version = \"1.0.199\"\n+version = \"1.0.200\"\n\
+                    diff --git a/docs/logo.png b/docs/logo.png\nnew file mode 100644\nindex 0..3\nBinary files /dev/null and b/docs/logo.png differ\n\
+                    diff --git a/data.bin.part b/data.bin.part\nindex 4..5 100644\nBinary files a/data.bin.part and b/data.bin.part differ\n\
+                    diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1 +1 @@\n-old\n+new\n";
+        let repo = tempfile::tempdir().unwrap();
+        let filtered = filter_diff(diff, &AiCommitIgnore::with_root(repo.path(), None, &[]));
+
+        assert_eq!(filtered.diff, "Changed files not shown in full:\n\
+                                   - `Cargo.lock`: 2 lines changed (lockfile)\n  - serde 1.0.199 -> 1.0.200\n\
+                                   - `docs/logo.png`: binary added\n\
+                                   - `data.bin.part`: binary modified\n\n\
+                                   diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1 +1 @@\n-old\n+new\n");
+        // Only files excluded by a rule count as excluded
+        let excluded: Vec<&str> = filtered.excluded.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(excluded, vec!["Cargo.lock", "docs/logo.png"]);
+    }
 }
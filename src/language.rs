@@ -0,0 +1,128 @@
+// Commit message language - the `language` setting and `--translate-to`, checked with the
+// same heuristics `style learn` uses
+
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::settings::Settings;
+use crate::style::{detect_language_strict, language_name, LANGUAGES};
+
+/// What stays in English whatever language the message is written in
+pub const KEEP_IN_ENGLISH: &str = "Keep the Conventional Commits type and scope (feat, fix, docs, ...), gitmoji codes, ticket IDs, file names and identifiers from the code in English";
+
+/// Conventional Commits types as models tend to translate them
+const TRANSLATED_TYPES: &[(&str, &str)] = &[
+    ("фича", "feat"),
+    ("функция", "feat"),
+    ("функциональность", "feat"),
+    ("исправление", "fix"),
+    ("исправить", "fix"),
+    ("фикс", "fix"),
+    ("документация", "docs"),
+    ("стиль", "style"),
+    ("рефакторинг", "refactor"),
+    ("производительность", "perf"),
+    ("тест", "test"),
+    ("тесты", "test"),
+    ("сборка", "build"),
+    ("обслуживание", "chore"),
+    ("funktion", "feat"),
+    ("fehlerbehebung", "fix"),
+    ("korrektur", "fix"),
+    ("dokumentation", "docs"),
+    ("stil", "style"),
+    ("refaktorierung", "refactor"),
+    ("leistung", "perf"),
+    ("wartung", "chore"),
+];
+
+/// A language for commit messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Language {
+    /// ISO 639-1 code, when the language is a known one
+    pub code: Option<&'static str>,
+    pub name: String,
+}
+
+impl Language {
+    /// From a code (`de`) or an English name (`German`); other values are used as given
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        let known = LANGUAGES.iter().find(|(code, name)| code.eq_ignore_ascii_case(value) || name.eq_ignore_ascii_case(value));
+        Some(match known {
+            Some((code, _)) => Self { code: Some(code), name: language_name(code).to_string() },
+            None => Self { code: None, name: value.to_string() },
+        })
+    }
+
+    pub fn is_english(&self) -> bool {
+        self.code == Some("en")
+    }
+
+    /// The prompt instruction
+    pub fn instruction(&self) -> String {
+        format!("Write the commit message in {}. {}.", self.name, KEEP_IN_ENGLISH)
+    }
+
+    /// A problem when the message looks like it's written in another language. Only Russian,
+    /// German and English are told apart, other languages are never flagged.
+    pub fn check(&self, message: &str) -> Option<String> {
+        let expected = self.code?;
+        // The type, scope and gitmoji are English anyway, code in backticks is whatever it is
+        static PREFIX: OnceLock<Regex> = OnceLock::new();
+        static CODE: OnceLock<Regex> = OnceLock::new();
+        let prefix = PREFIX.get_or_init(|| Regex::new(r"(?m)^(?:[\p{L}]+(?:\([^)]*\))?!?: |:[a-z0-9_+-]+: ?)").unwrap());
+        let code = CODE.get_or_init(|| Regex::new(r"`[^`]*`").unwrap());
+        let text = code.replace_all(&prefix.replace_all(message, ""), "").to_string();
+
+        match detect_language_strict(&text) {
+            Some(found) if found != expected => Some(format!("message seems to be written in {}, not {}", language_name(found), self.name)),
+            _ => None,
+        }
+    }
+}
+
+/// The language set with `language`, None for English, which the prompts use anyway
+pub fn configured_language(settings: &Settings) -> Option<Language> {
+    settings.get_str("language").and_then(Language::parse).filter(|language| !language.is_english())
+}
+
+/// Put a Conventional Commits type the model translated back into English
+/// (`исправление: ...` to `fix: ...`)
+pub fn english_type_keywords(message: &str) -> String {
+    static SUBJECT: OnceLock<Regex> = OnceLock::new();
+    let subject = SUBJECT.get_or_init(|| Regex::new(r"^(\p{L}+)((?:\([^)]*\))?!?: )").unwrap());
+    let Some(captures) = subject.captures(message) else {
+        return message.to_string();
+    };
+    let word = captures[1].to_lowercase();
+    match TRANSLATED_TYPES.iter().find(|(translated, _)| *translated == word) {
+        Some((_, kind)) => format!("{}{}{}", kind, &captures[2], &message[captures.get(0).unwrap().end()..]),
+        None => message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_check_and_keywords() {
+        let russian = Language::parse("Russian").unwrap();
+        assert_eq!(russian.code, Some("ru"));
+        assert_eq!(Language::parse("de").unwrap().name, "German");
+        assert_eq!(Language::parse("Esperanto").unwrap().code, None);
+        assert_eq!(Language::parse(" "), None);
+
+        assert_eq!(russian.check("feat(auth): добавить форму входа"), None);
+        assert_eq!(russian.check("feat(auth): add the login form").unwrap(), "message seems to be written in English, not Russian");
+        assert_eq!(Language::parse("de").unwrap().check("fix: Fehler beim Speichern behoben"), None);
+        assert_eq!(Language::parse("de").unwrap().check("fix: `parse_config`"), None);
+
+        assert_eq!(english_type_keywords("Исправление(api): убрать утечку"), "fix(api): убрать утечку");
+        assert_eq!(english_type_keywords("Dokumentation: Installation beschreiben"), "docs: Installation beschreiben");
+        assert_eq!(english_type_keywords("feat: добавить вход"), "feat: добавить вход");
+    }
+}
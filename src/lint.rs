@@ -0,0 +1,148 @@
+// Commit message lint rules
+
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::settings::{SettingOrigin, Settings};
+use crate::language::{configured_language, Language};
+use crate::preset::{configured_preset, Preset};
+use crate::style::{Convention, StyleProfile};
+
+/// Rules applied to generated commit messages
+#[derive(Debug, Clone)]
+pub struct LintRules {
+    pub max_subject_length: usize,
+    pub conventional: bool,
+    /// Convention learned by `aicommit style learn`, other than Conventional Commits
+    pub style: Option<Convention>,
+    /// Convention preset chosen with `convention.preset`
+    pub preset: Option<Preset>,
+    /// Language the message should be written in, other than English
+    pub language: Option<Language>,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        Self {
+            max_subject_length: 72,
+            conventional: true,
+            style: None,
+            preset: None,
+            language: None,
+        }
+    }
+}
+
+impl LintRules {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let configured = Self {
+            max_subject_length: settings.get_int("lint.max_subject_length")
+                .map(|n| n.max(1) as usize)
+                .unwrap_or(defaults.max_subject_length),
+            conventional: settings.get("lint.conventional")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(defaults.conventional),
+            style: None,
+            preset: None,
+            language: configured_language(settings),
+        };
+        let is_set = |key: &str| settings.origin(key).is_some_and(|origin| *origin != SettingOrigin::Default);
+        if let Some(preset) = configured_preset(settings) {
+            return configured.with_preset(preset, is_set);
+        }
+        match StyleProfile::load() {
+            Some(profile) => configured.with_profile(&profile, is_set),
+            None => configured,
+        }
+    }
+
+    /// Rules following a learned style profile, except for settings the user set explicitly
+    pub fn with_profile(self, profile: &StyleProfile, is_set: impl Fn(&str) -> bool) -> Self {
+        Self {
+            max_subject_length: if is_set("lint.max_subject_length") { self.max_subject_length } else { profile.max_length },
+            conventional: if is_set("lint.conventional") { self.conventional } else { profile.convention == Convention::Conventional },
+            style: Some(profile.convention).filter(|convention| !matches!(convention, Convention::Conventional | Convention::Freeform)),
+            preset: None,
+            language: self.language.or_else(|| Language::parse(&profile.language).filter(|language| !language.is_english())),
+        }
+    }
+
+    /// Rules of a convention preset, except for settings the user set explicitly
+    pub fn with_preset(self, preset: Preset, is_set: impl Fn(&str) -> bool) -> Self {
+        Self {
+            max_subject_length: if is_set("lint.max_subject_length") { self.max_subject_length } else { preset.max_subject_length() },
+            conventional: if is_set("lint.conventional") { self.conventional } else { preset.is_conventional() },
+            style: None,
+            preset: Some(preset),
+            ..self
+        }
+    }
+}
+
+/// Check a commit message against the rules and return a description of each problem found
+pub fn lint_message(message: &str, rules: &LintRules) -> Vec<String> {
+    let mut issues = Vec::new();
+    let subject = message.lines().next().unwrap_or("").trim();
+
+    if subject.is_empty() {
+        issues.push("subject line is empty".to_string());
+        return issues;
+    }
+
+    let length = subject.chars().count();
+    if length > rules.max_subject_length {
+        issues.push(format!("subject line is {} characters long (max {})", length, rules.max_subject_length));
+    }
+
+    if rules.conventional {
+        static CONVENTIONAL: OnceLock<Regex> = OnceLock::new();
+        let conventional = CONVENTIONAL.get_or_init(|| Regex::new(r"^[a-z]+(\([^)]+\))?!?: \S").unwrap());
+        if !conventional.is_match(subject) {
+            issues.push("subject does not follow Conventional Commits (type(scope): description)".to_string());
+        }
+    }
+
+    if let Some(pattern) = rules.style.and_then(Convention::pattern) {
+        if !pattern.is_match(subject) {
+            issues.push(format!("subject does not follow the repository's commit style ({})", rules.style.unwrap().describe()));
+        }
+    }
+
+    if let Some(preset) = rules.preset {
+        issues.extend(preset.lint(subject));
+    }
+
+    if let Some(issue) = rules.language.as_ref().and_then(|language| language.check(message)) {
+        issues.push(issue);
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_message() {
+        let rules = LintRules::default();
+        assert!(lint_message("feat(api): add login endpoint", &rules).is_empty());
+        assert_eq!(lint_message("Added login endpoint", &rules).len(), 1);
+        assert_eq!(lint_message(&format!("fix: {}", "x".repeat(80)), &rules).len(), 1);
+
+        let relaxed = LintRules { max_subject_length: 100, conventional: false, style: None, preset: None, language: None };
+        assert!(lint_message("Added login endpoint", &relaxed).is_empty());
+    }
+
+    #[test]
+    fn test_lint_follows_style_profile() {
+        let profile = StyleProfile::learn(&["[api] Add login endpoint".to_string(), "[ui] Fix button".to_string()]).unwrap();
+        let rules = LintRules::default().with_profile(&profile, |_| false);
+        assert!(!rules.conventional);
+        assert!(lint_message("[db] Add index", &rules).is_empty());
+        assert_eq!(lint_message("feat: add index", &rules).len(), 1);
+
+        let explicit = LintRules::default().with_profile(&profile, |key| key == "lint.conventional");
+        assert!(explicit.conventional);
+    }
+}
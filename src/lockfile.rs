@@ -0,0 +1,258 @@
+// Dependency version changes read from the diff of a lockfile, so a stub can say
+// "serde 1.0.199 -> 1.0.200" where the full lockfile diff is left out of the prompt
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::OnceLock;
+use regex::Regex;
+
+/// Lockfiles recognised by name; any other `*.lock` counts as a lockfile too
+const LOCKFILE_NAMES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "Cargo.lock",
+    "Gemfile.lock",
+    "poetry.lock",
+    "uv.lock",
+    "Pipfile.lock",
+    "composer.lock",
+    "pubspec.lock",
+    "go.sum",
+    "flake.lock",
+];
+
+/// Keys of package-lock.json objects that aren't packages
+const JSON_NON_PACKAGE_KEYS: &[&str] = &[
+    "", "packages", "dependencies", "devDependencies", "peerDependencies", "optionalDependencies",
+    "peerDependenciesMeta", "requires", "engines", "bin", "funding", "require", "require-dev",
+    "autoload", "autoload-dev", "dist", "source", "extra", "support",
+];
+
+/// One package whose version differs between the old and new lockfile
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionChange {
+    Bumped { name: String, from: String, to: String },
+    Added { name: String, version: String },
+    Removed { name: String, version: String },
+}
+
+impl fmt::Display for VersionChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionChange::Bumped { name, from, to } => write!(f, "{} {} -> {}", name, from, to),
+            VersionChange::Added { name, version } => write!(f, "{} {} (added)", name, version),
+            VersionChange::Removed { name, version } => write!(f, "{} {} (removed)", name, version),
+        }
+    }
+}
+
+pub fn is_lockfile(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    LOCKFILE_NAMES.contains(&name) || name.ends_with(".lock")
+}
+
+/// What a line of a lockfile says about packages
+enum Entry {
+    /// Starts the entry of a package, its version follows on a later line
+    Name(String),
+    Version(String),
+    Package(String, String),
+}
+
+/// How the entries of one lockfile format look
+enum Format {
+    /// `name = "serde"` then `version = "1.0.200"` (Cargo.lock, poetry.lock, uv.lock)
+    Toml { name: &'static Regex, version: &'static Regex },
+    /// `"node_modules/lodash": {` or `"name": "vendor/pkg"`, then `"version": "4.17.21"`
+    Json { key: &'static Regex, name: &'static Regex, version: &'static Regex },
+    /// `lodash@^4.17.0, lodash@^4.17.21:` then `  version "4.17.21"`
+    Yarn { version: &'static Regex },
+    /// `golang.org/x/net v0.25.0 h1:...` and `golang.org/x/net v0.25.0/go.mod h1:...`
+    GoSum { package: &'static Regex },
+    /// `    rails (7.1.3)` below `specs:`
+    Gemfile { package: &'static Regex },
+    /// `  /lodash@4.17.21:` or `  lodash@4.17.21:`
+    Pnpm { package: &'static Regex },
+}
+
+impl Format {
+    fn for_path(path: &str) -> Option<Self> {
+        static TOML_NAME: OnceLock<Regex> = OnceLock::new();
+        static TOML_VERSION: OnceLock<Regex> = OnceLock::new();
+        static JSON_KEY: OnceLock<Regex> = OnceLock::new();
+        static JSON_NAME: OnceLock<Regex> = OnceLock::new();
+        static JSON_VERSION: OnceLock<Regex> = OnceLock::new();
+        static YARN_VERSION: OnceLock<Regex> = OnceLock::new();
+        static GO_SUM_PACKAGE: OnceLock<Regex> = OnceLock::new();
+        static GEMFILE_PACKAGE: OnceLock<Regex> = OnceLock::new();
+        static PNPM_PACKAGE: OnceLock<Regex> = OnceLock::new();
+        let regex = |cell: &'static OnceLock<Regex>, pattern: &str| -> &'static Regex {
+            cell.get_or_init(|| Regex::new(pattern).unwrap())
+        };
+        let name = path.rsplit('/').next().unwrap_or(path);
+        Some(match name {
+            "Cargo.lock" | "poetry.lock" | "uv.lock" => Format::Toml {
+                name: regex(&TOML_NAME, r#"^name = "([^"]+)"$"#),
+                version: regex(&TOML_VERSION, r#"^version = "([^"]+)"$"#),
+            },
+            "package-lock.json" | "npm-shrinkwrap.json" | "composer.lock" => Format::Json {
+                key: regex(&JSON_KEY, r#"^\s*"([^"]*)": \{$"#),
+                name: regex(&JSON_NAME, r#"^\s*"name": "([^"]+)",?$"#),
+                version: regex(&JSON_VERSION, r#"^\s*"version": "([^"]+)",?$"#),
+            },
+            "yarn.lock" => Format::Yarn { version: regex(&YARN_VERSION, r#"^\s+version:? "?([^"\s]+)"?$"#) },
+            "go.sum" => Format::GoSum { package: regex(&GO_SUM_PACKAGE, r"^(\S+) (v[^/\s]+)(?:/go\.mod)? ") },
+            "Gemfile.lock" => Format::Gemfile { package: regex(&GEMFILE_PACKAGE, r"^    ([A-Za-z0-9_.-]+) \(([^)]+)\)$") },
+            "pnpm-lock.yaml" => Format::Pnpm { package: regex(&PNPM_PACKAGE, r"^  '?/?(@?[^@\s']+)@(\d[^:'(\s]*)") },
+            _ => return None,
+        })
+    }
+
+    fn parse(&self, line: &str) -> Option<Entry> {
+        let capture = |regex: &Regex, group: usize| regex.captures(line).map(|c| c[group].to_string());
+        match self {
+            Format::Toml { name, version } => capture(name, 1).map(Entry::Name)
+                .or_else(|| capture(version, 1).map(Entry::Version)),
+            Format::Json { key, name, version } => {
+                if let Some(key) = capture(key, 1) {
+                    if JSON_NON_PACKAGE_KEYS.contains(&key.as_str()) {
+                        return None;
+                    }
+                    let package = key.rsplit("node_modules/").next().unwrap_or(&key);
+                    return Some(Entry::Name(package.to_string()));
+                }
+                capture(name, 1).map(Entry::Name).or_else(|| capture(version, 1).map(Entry::Version))
+            }
+            Format::Yarn { version } => {
+                if !line.starts_with([' ', '#']) && line.ends_with(':') {
+                    let spec = line.split(',').next()?.trim_end_matches(':').trim_matches('"');
+                    let at = spec.rfind('@').filter(|&at| at > 0)?;
+                    return Some(Entry::Name(spec[..at].to_string()));
+                }
+                capture(version, 1).map(Entry::Version)
+            }
+            Format::GoSum { package } | Format::Gemfile { package } | Format::Pnpm { package } => {
+                package.captures(line).map(|c| Entry::Package(c[1].to_string(), c[2].to_string()))
+            }
+        }
+    }
+}
+
+/// Version changes in the diff section of a lockfile, empty for formats it can't read
+pub fn version_changes(path: &str, section: &str) -> Vec<VersionChange> {
+    let Some(format) = Format::for_path(path) else {
+        return Vec::new();
+    };
+
+    // Versions per package on each side; context lines name the package for both sides
+    let mut old: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut new: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let (mut old_name, mut new_name) = (None::<String>, None::<String>);
+
+    for line in section.lines().skip_while(|line| !line.starts_with("@@")) {
+        let (in_old, in_new, text) = match line.split_at(line.len().min(1)) {
+            ("-", text) => (true, false, text),
+            ("+", text) => (false, true, text),
+            (" ", text) => (true, true, text),
+            _ => continue,
+        };
+        match format.parse(text) {
+            Some(Entry::Name(name)) => {
+                if in_old {
+                    old_name = Some(name.clone());
+                }
+                if in_new {
+                    new_name = Some(name);
+                }
+            }
+            // A version on a context line didn't change
+            Some(Entry::Version(_)) | Some(Entry::Package(..)) if in_old && in_new => {}
+            Some(Entry::Version(version)) => {
+                let (versions, name) = if in_old { (&mut old, &old_name) } else { (&mut new, &new_name) };
+                if let Some(name) = name {
+                    versions.entry(name.clone()).or_default().insert(version);
+                }
+            }
+            Some(Entry::Package(name, version)) => {
+                let versions = if in_old { &mut old } else { &mut new };
+                versions.entry(name).or_default().insert(version);
+            }
+            None => {}
+        }
+    }
+
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let mut changes = Vec::new();
+    for name in names {
+        let empty = BTreeSet::new();
+        let before = old.get(name).unwrap_or(&empty);
+        let after = new.get(name).unwrap_or(&empty);
+        let removed: Vec<&String> = before.difference(after).collect();
+        let added: Vec<&String> = after.difference(before).collect();
+        let mut removed = removed.into_iter();
+        let mut added = added.into_iter();
+        loop {
+            match (removed.next(), added.next()) {
+                (Some(from), Some(to)) => changes.push(VersionChange::Bumped { name: name.clone(), from: from.clone(), to: to.clone() }),
+                (Some(version), None) => changes.push(VersionChange::Removed { name: name.clone(), version: version.clone() }),
+                (None, Some(version)) => changes.push(VersionChange::Added { name: name.clone(), version: version.clone() }),
+                (None, None) => break,
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cargo_lock_changes() {
+        let section = r#"a/Cargo.lock b/Cargo.lock
+index 1111111..2222222 100644
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -10,7 +10,7 @@
+ [[package]]
+ name = "serde"
+-version = "1.0.199"
++version = "1.0.200"
+ source = "registry+https://github.com/rust-lang/crates.io-index"
+@@ -40,6 +40,12 @@
+ ]
+
++[[package]]
++name = "tempfile"
++version = "3.10.1"
++
++[[package]]
+ name = "tokio"
+ version = "1.37.0"
+@@ -80,9 +86,4 @@
+-[[package]]
+-name = "glob"
+-version = "0.3.1"
+-
+"#;
+        let changes: Vec<String> = version_changes("Cargo.lock", section).iter().map(ToString::to_string).collect();
+        assert_eq!(changes, vec!["glob 0.3.1 (removed)", "serde 1.0.199 -> 1.0.200", "tempfile 3.10.1 (added)"]);
+    }
+
+    #[test]
+    fn test_other_lockfile_formats() {
+        let package_lock = "@@ -5,7 +5,7 @@\n     \"node_modules/@types/node\": {\n-      \"version\": \"20.1.0\",\n+      \"version\": \"20.2.0\",\n";
+        assert_eq!(version_changes("web/package-lock.json", package_lock)[0].to_string(), "@types/node 20.1.0 -> 20.2.0");
+
+        let yarn = "@@ -1,4 +1,4 @@\n \"@babel/core@^7.0.0\", \"@babel/core@^7.1.0\":\n-  version \"7.24.0\"\n+  version \"7.24.5\"\n";
+        assert_eq!(version_changes("yarn.lock", yarn)[0].to_string(), "@babel/core 7.24.0 -> 7.24.5");
+
+        let go_sum = "@@ -1,4 +1,4 @@\n-golang.org/x/net v0.24.0 h1:abc=\n-golang.org/x/net v0.24.0/go.mod h1:def=\n+golang.org/x/net v0.25.0 h1:ghi=\n+golang.org/x/net v0.25.0/go.mod h1:jkl=\n";
+        assert_eq!(version_changes("go.sum", go_sum)[0].to_string(), "golang.org/x/net v0.24.0 -> v0.25.0");
+
+        assert!(is_lockfile("deps/flake.lock") && is_lockfile("mix.lock") && !is_lockfile("Cargo.toml"));
+        assert!(version_changes("flake.lock", go_sum).is_empty());
+    }
+}
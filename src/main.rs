@@ -1,7 +1,7 @@
 // Main module - orchestrates all functionality
 
 use std::fs;
-use clap::Parser;
+use clap::CommandFactory;
 use tokio;
 use tracing::info;
 use logging::{LoggingConfig, init_logging};
@@ -16,119 +16,80 @@ mod models;
 mod utils;
 mod ignore;
 mod hooks;
+mod secrets;
+mod settings;
+mod prompt;
+mod lint;
+mod schema;
+mod state;
+mod completions;
+mod model_list;
+mod catalogue;
+mod retry;
+mod http;
+mod lockfile;
+mod scan;
+mod egress;
+mod context;
+mod style;
+mod scope;
+mod preset;
+mod language;
 
 // Use declarations from our modules
 use types::*;
 use version::*;
 use git::*;
 use models::*;
+use settings::Settings;
+use lint::{lint_message, LintRules};
+use retry::{retry, RetryPolicy};
 
 // Constants
 const MAX_DIFF_CHARS: usize = 15000; // Limit diff size to prevent excessive API usage
 const MAX_FILE_DIFF_CHARS: usize = 3000; // Maximum characters per file diff section
 
-// Define a list of preferred free models from best to worst
-const PREFERRED_FREE_MODELS: &[&str] = &[
-    // Meta models - Llama 4 series
-    "meta-llama/llama-4-maverick:free",
-    "meta-llama/llama-4-scout:free",
-    
-    // Ultra large models (200B+)
-    "nvidia/llama-3.1-nemotron-ultra-253b-v1:free",
-    "qwen/qwen3-235b-a22b:free",
-    
-    // Very large models (70B-200B)
-    "meta-llama/llama-3.1-405b:free",
-    "nvidia/llama-3.3-nemotron-super-49b-v1:free",
-    "meta-llama/llama-3.3-70b-instruct:free",
-    "deepseek/deepseek-r1-distill-llama-70b:free",
-    "shisa-ai/shisa-v2-llama3.3-70b:free",
-    
-    // Large models (32B-70B)
-    "qwen/qwen-2.5-72b-instruct:free",
-    "qwen/qwen2.5-vl-72b-instruct:free",
-    "bytedance-research/ui-tars-72b:free",
-    "featherless/qwerky-72b:free",
-    "thudm/glm-4-32b:free",
-    "thudm/glm-z1-32b:free",
-    "qwen/qwen3-32b:free",
-    "qwen/qwen3-30b-a3b:free",
-    "qwen/qwq-32b:free",
-    "qwen/qwq-32b-preview:free",
-    "deepseek/deepseek-r1-distill-qwen-32b:free",
-    "arliai/qwq-32b-arliai-rpr-v1:free",
-    "qwen/qwen2.5-vl-32b-instruct:free",
-    "open-r1/olympiccoder-32b:free",
-    "qwen/qwen-2.5-coder-32b-instruct:free",
-    
-    // Medium-large models (14B-30B)
-    "mistralai/mistral-small-3.1-24b-instruct:free",
-    "mistralai/mistral-small-24b-instruct-2501:free",
-    "cognitivecomputations/dolphin3.0-r1-mistral-24b:free",
-    "cognitivecomputations/dolphin3.0-mistral-24b:free",
-    "google/gemma-3-27b-it:free",
-    "google/gemini-2.0-flash-exp:free",
-    "rekaai/reka-flash-3:free",
-    
-    // Medium models (7B-14B)
-    "qwen/qwen3-14b:free",
-    "deepseek/deepseek-r1-distill-qwen-14b:free",
-    "agentica-org/deepcoder-14b-preview:free",
-    "moonshotai/moonlight-16b-a3b-instruct:free",
-    "opengvlab/internvl3-14b:free",
-    "google/gemma-3-12b-it:free",
-    "meta-llama/llama-3.2-11b-vision-instruct:free",
-    "thudm/glm-4-9b:free",
-    "thudm/glm-z1-9b:free",
-    "google/gemma-2-9b-it:free",
-    "qwen/qwen3-8b:free",
-    "meta-llama/llama-3.1-8b-instruct:free",
-    "nousresearch/deephermes-3-llama-3-8b-preview:free",
-    
-    // Specialized models (various sizes)
-    "deepseek/deepseek-r1:free",
-    "microsoft/phi-4-reasoning-plus:free",
-    "microsoft/phi-4-reasoning:free",
-    "deepseek/deepseek-v3-base:free",
-    "deepseek/deepseek-r1-zero:free",
-    "deepseek/deepseek-prover-v2:free",
-    "deepseek/deepseek-chat-v3-0324:free",
-    "deepseek/deepseek-chat:free",
-    "microsoft/mai-ds-r1:free",
-    "tngtech/deepseek-r1t-chimera:free",
-    "mistralai/mistral-nemo:free",
-    
-    // Small models (< 7B)
-    "qwen/qwen3-4b:free",
-    "google/gemma-3-4b-it:free",
-    "qwen/qwen-2.5-7b-instruct:free",
-    "mistralai/mistral-7b-instruct:free",
-    "qwen/qwen-2.5-vl-7b-instruct:free",
-    "opengvlab/internvl3-2b:free",
-    "google/gemma-3-1b-it:free",
-    "meta-llama/llama-3.2-3b-instruct:free",
-    "allenai/molmo-7b-d:free",
-    "qwen/qwen3-1.7b:free",
-    "qwen/qwen2.5-vl-3b-instruct:free",
-    "meta-llama/llama-3.2-1b-instruct:free",
-    "qwen/qwen3-0.6b-04-28:free",
-    
-    // Special cases and multimodal models
-    "google/learnlm-1.5-pro-experimental:free",
-    "moonshotai/kimi-vl-a3b-thinking:free"
-];
-
 const MAX_CONSECUTIVE_FAILURES: usize = 3;
 const INITIAL_JAIL_HOURS: i64 = 24;
 const JAIL_TIME_MULTIPLIER: i64 = 2;
 const MAX_JAIL_HOURS: i64 = 168; // 7 days
 const BLACKLIST_AFTER_JAIL_COUNT: usize = 3;
 const BLACKLIST_RETRY_DAYS: i64 = 7;
+const RATE_LIMIT_COOLDOWN_MINUTES: i64 = 5; // Cool-down after a 429 without Retry-After
+const OUTAGE_COOLDOWN_MINUTES: i64 = 15; // Cool-down after a 5xx from the model's provider
+const CONTENT_FILTER_COOLDOWN_MINUTES: i64 = 60; // Cool-down after the model's moderation refused a diff
+const FAILOVER_MAX_MODELS: usize = 4; // Free models tried within one Simple Free attempt
+const HEDGE_AFTER_SECS: i64 = 12; // A request this slow gets the next best model raced against it
+const FAILOVER_DEADLINE_SECS: i64 = 90; // Time limit of one Simple Free attempt
+const RETRY_BASE_DELAY_MS: u64 = 1000; // Delay before the first retry
+const RETRY_MULTIPLIER: u32 = 2; // Factor applied to the delay for every further retry
+const RETRY_MAX_DELAY_SECS: u64 = 30; // Longest delay between two tries
+const RETRY_JITTER_PERCENT: u64 = 30; // Randomized share of each delay
+const RETRY_DEADLINE_SECS: u64 = 180; // No retry starts later than this after the first try
+const HTTP_CONNECT_TIMEOUT_SECS: u64 = 10;
+const HTTP_TIMEOUT_SECS: u64 = 120; // Whole request, generous for slow local models
+const LOCKFILE_MAX_CHANGES: usize = 20; // Dependency version changes listed per lockfile stub
+const SECRET_ENTROPY_THRESHOLD: f64 = 4.5; // Bits per character above which a long token looks random
+const SECRET_MIN_ENTROPY_LENGTH: usize = 32; // Shorter tokens are never flagged for entropy alone
+const CONTEXT_MAX_CHARS: usize = 2000; // Budget for branch, file list, recent commits and README in the prompt
+const STYLE_MIN_SHARE: f64 = 0.5; // Share of recent subjects that must follow a convention for `style learn` to adopt it
+const STYLE_EXAMPLES: usize = 5; // Subjects from the history kept as prompt examples
+const RECENT_SAMPLES: usize = 20; // Requests per model kept for latency/success/lint statistics
+const SCORE_DECAY: f64 = 0.3; // Weight of the newest sample in a model's moving average score
+const EXPLORATION: f64 = 0.1; // How eagerly rarely used models are tried again
+const LATENCY_SCALE_SECS: f64 = 30.0; // A response this slow halves a model's score
+const CATALOGUE_TTL_HOURS: i64 = 6; // Age after which the cached OpenRouter catalogue is revalidated
 
 // From: 029_function_main.rs
 #[tokio::main]
 async fn main() -> Result<(), String> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_args()?;
+
+    // Runs on every TAB press: no logging, no .gitignore check, nothing but the values
+    if let Some(Commands::Complete { kind }) = cli.command {
+        completions::print_complete_values(kind);
+        return Ok(());
+    }
 
     // Initialize logging system
     let mut logging_config = LoggingConfig::new();
@@ -138,6 +99,11 @@ async fn main() -> Result<(), String> {
         logging_config.with_debug();
     }
 
+    // Commands whose stdout is meant for other programs must not be mixed with log lines
+    if matches!(cli.command, Some(Commands::Man | Commands::Completions { .. })) {
+        logging_config.file_only();
+    }
+
     let _logging_guard = match init_logging(&logging_config) {
         Ok(guard) => guard,
         Err(e) => {
@@ -154,302 +120,270 @@ async fn main() -> Result<(), String> {
         Config::check_gitignore()?;
     }
 
-    match () {
-        _ if cli.help => {
-            // Получаем версию для отображения в справке
-            let version = get_version();
-            
-            println!("aicommit v{} - A CLI tool that generates concise and descriptive git commit messages using LLMs", version);
-            println!("\nUsage:");
-            println!("  aicommit [OPTIONS]");
-            println!("\nOptions:");
-            println!("  --add-provider       Add a new provider (interactive mode)");
-            println!("  --add                Automatically stage all changes before commit");
-            println!("  --add-openrouter     Add OpenRouter provider non-interactively");
-            println!("  --add-simple-free    Add Simple Free OpenRouter provider (uses best available free models)");
-            println!("  --add-ollama         Add Ollama provider non-interactively");
-            println!("  --add-openai-compatible Add OpenAI compatible provider non-interactively");
-            println!("  --openrouter-api-key=<KEY> OpenRouter API key (required for --add-openrouter)");
-            println!("  --openrouter-model=<MODEL> OpenRouter model (default: mistralai/mistral-tiny)");
-            println!("  --ollama-url=<URL>    Ollama API URL (default: http://localhost:11434)");
-            println!("  --ollama-model=<MODEL> Ollama model (default: llama2)");
-            println!("  --openai-compatible-api-key=<KEY> OpenAI compatible API key");
-            println!("  --openai-compatible-api-url=<URL> OpenAI compatible API URL");
-            println!("  --openai-compatible-model=<MODEL> OpenAI compatible model (default: gpt-3.5-turbo)");
-            println!("  --max-tokens=<TOKENS> Max tokens for response (default: 200)");
-            println!("  --temperature=<TEMP>  Temperature for generation (default: 0.2)");
-            println!("  --list                List all providers");
-            println!("  --set=<ID>            Set active provider by ID");
-            println!("  --config              Edit configuration file");
-            println!("  --version-file=<FILE> Path to version file");
-            println!("  --version-iterate     Automatically increment version in version file");
-            println!("  --version-cargo       Synchronize version with Cargo.toml");
-            println!("  --version-npm         Synchronize version with package.json");
-            println!("  --version-github      Update version on GitHub");
-            println!("  --dry-run             Interactive commit message generation");
-            println!("  --pull                Pull changes before commit");
-            println!("  --push                Automatically push changes after commit");
-            println!("  --push-all            Push changes to all configured remotes");
-            println!("  --help                Display this help message");
-            println!("  --version             Display version information");
-            println!("  --verbose             Display verbose information");
-            println!("  --no-gitignore-check  Skip .gitignore check and creation");
-            println!("  --no-aicommitignore   Skip .aicommitignore filtering (include all files in diff)");
-            println!("  --watch               Watch for changes and auto-commit");
-            println!("  --wait-for-edit=<DURATION> Wait for edit delay before committing (e.g. \"30s\")");
-            println!("  --jail-status         Show status of all model jails and blacklists");
-            println!("  --unjail=<MODEL>      Release specific model from jail/blacklist (model ID as parameter)");
-            println!("  --unjail-all          Release all models from jail/blacklist");
-            println!("\nGitHub Action Options:");
-            println!("  --github-action       Run in GitHub Action mode (non-interactive)");
-            println!("  --input-diff=<FILE>   Input diff from file (for --github-action mode)");
-            println!("  --stdin               Read diff from stdin (for --github-action mode)");
-            println!("  --output-format=<FMT> Output format: text, json, github (default: text)");
-            println!("  --api-key=<KEY>       API key for GitHub Action mode");
-            println!("  --provider=<TYPE>     Provider: openrouter, simple-free, ollama, openai-compatible");
-            println!("  --model=<MODEL>       Model name for GitHub Action mode");
-            println!("  --analyze-commits     Analyze commits from GitHub event context");
-            println!("\nGit Hook Options:");
-            println!("  --hook=install        Install prepare-commit-msg hook for automatic AI messages");
-            println!("  --hook=uninstall      Remove the installed hook");
-            println!("  --hook=status         Check if hook is installed");
-            println!("\nExamples:");
-            println!("  aicommit --add-provider");
-            println!("  aicommit --add");
-            println!("  aicommit --add-openrouter --openrouter-api-key=<KEY>");
-            println!("  aicommit --add-simple-free --openrouter-api-key=<KEY>");
-            println!("  aicommit --list");
-            println!("  aicommit --set=<ID>");
-            println!("  aicommit --version-file=version.txt --version-iterate");
-            println!("  aicommit --watch");
-            println!("  aicommit --github-action --stdin --api-key=$OPENROUTER_API_KEY");
-            println!("  aicommit --github-action --analyze-commits --provider=simple-free");
-            println!("  aicommit --hook=install");
-            println!("  aicommit --hook=uninstall");
-            println!("  aicommit");
-            Ok(())
-        }
-        _ if cli.version => {
-            println!("aicommit v{}", get_version());
-            Ok(())
+    // Load layered settings early so configuration errors are reported up front
+    let settings = Settings::load(&cli)?;
+    http::init_http_client(&settings);
+
+    match cli.command.as_ref() {
+        None | Some(Commands::Commit(_)) if cli.commit_args().translate_to.is_some() => {
+            let config = load_config_with_active_provider(&settings);
+            translate_commit(&config, &cli, &settings).await
         }
-        _ if cli.jail_status => {
-            // Display jail status for all models
-            let config = Config::load()?;
-            
-            // Find a Simple Free provider
-            let mut found = false;
-            
-            for provider in config.providers {
-                if let ProviderConfig::SimpleFreeOpenRouter(c) = provider {
-                    display_model_jail_status(&c)?;
-                    found = true;
-                    break;
+        None | Some(Commands::Commit(_)) if cli.commit_args().dry_run => {
+            // Special handling for --dry-run to provide better error messages
+            match dry_run(&cli, &settings).await {
+                Ok(message) => {
+                    println!("{}", message);
+                    Ok(())
+                }
+                Err(e) => {
+                    // Provide more detailed error message
+                    eprintln!("Error in dry-run mode: {}", e);
+                    Err(e)
                 }
             }
-            
-            if !found {
-                println!("No Simple Free OpenRouter configuration found. You can add one with 'aicommit --add-simple-free'");
-            }
-            
-            Ok(())
         }
-        _ if cli.unjail.is_some() => {
-            // Unjail a specific model
-            let model_id = cli.unjail.unwrap();
-            let mut config = Config::load()?;
-            let mut found = false;
-            
-            for provider in &mut config.providers {
-                if let ProviderConfig::SimpleFreeOpenRouter(c) = provider {
-                    unjail_model(c, &model_id)?;
-                    println!("Model '{}' successfully released from jail", model_id);
-                    found = true;
-                    break;
+        None | Some(Commands::Commit(_)) => {
+            // --msg commits without a provider, so it also works before any setup
+            let config = if cli.commit_args().msg.is_some() {
+                Config::load().unwrap_or_else(|_| Config::new())
+            } else {
+                load_config_with_active_provider(&settings)
+            };
+            run_commit(&config, &cli, &settings).await
+        }
+        Some(Commands::Watch(args)) => {
+            let config = load_config_with_active_provider(&settings);
+            watch_and_commit(&config, &cli, &settings, args).await
+        }
+        Some(Commands::Provider { action }) => match action {
+            ProviderCommand::Add(args) => {
+                match args.kind {
+                    Some(kind) => Config::setup_non_interactive(args, kind).await?,
+                    None => Config::setup_interactive().await?,
+                };
+                println!("Provider added successfully!");
+                Ok(())
+            }
+            ProviderCommand::List => {
+                let config = Config::load()?;
+                let active_id = config.active_provider_config(&settings).map(|p| p.id().to_string());
+                for provider in &config.providers {
+                    let marker = if active_id.as_deref() == Some(provider.id()) { "*" } else { " " };
+                    let mut details = format!("model: {}, endpoint: {}", provider.model().unwrap_or("auto"), provider.endpoint());
+                    if let Some(key) = provider.api_key() {
+                        details.push_str(&format!(", api_key: {}", secrets::redact_secret(key)));
+                    }
+                    println!("{} {}: {} ({})", marker, provider.kind_name(), provider.id(), details);
                 }
+                warn_plaintext_api_keys(&config);
+                Ok(())
             }
-            
-            if !found {
-                println!("No Simple Free OpenRouter configuration found. You can add one with 'aicommit --add-simple-free'");
+            ProviderCommand::Use { id } => {
+                Config::update(|config| {
+                    if !config.providers.iter().any(|p| p.id() == id) {
+                        return Err(format!("Provider '{}' not found", id));
+                    }
+                    config.active_provider = id.clone();
+                    Ok(())
+                })?;
+
+                println!("Active provider set to {}", id);
+                Ok(())
             }
-            
+            ProviderCommand::Remove { id } => providers::remove_provider(id),
+            ProviderCommand::Rename { id, new_id } => providers::rename_provider(id, new_id),
+            ProviderCommand::Edit { id } => providers::edit_provider(id),
+            ProviderCommand::Show { id } => {
+                let config = Config::load()?;
+                let provider = find_provider(&config, id.as_deref(), &settings)?;
+                providers::show_provider(&config, provider, &settings)
+            }
+            ProviderCommand::Test { id } => {
+                let config = Config::load()?;
+                let provider = find_provider(&config, id.as_deref(), &settings)?;
+                providers::test_provider(provider, &cli, &settings).await
+            }
+        },
+        Some(Commands::Models { provider, action: ModelsCommand::List { free } }) => {
+            // The catalogue is public, the Simple Free key is only sent when there is one
+            let api_key = match Config::load() {
+                Ok(mut config) => find_simple_free(&mut config, provider.as_deref(), &settings)?
+                    .map(|simple_free| secrets::resolve_secret(&simple_free.api_key))
+                    .transpose()?,
+                Err(_) => None,
+            };
+            let models = catalogue::load_catalogue(api_key.as_deref(), cli.refresh_models, &RetryPolicy::from_settings(&settings)).await?;
+            let free_models = free.then(|| free_models_from_catalogue(&models));
+            catalogue::print_catalogue(&models, free_models.as_deref());
             Ok(())
         }
-        _ if cli.unjail_all => {
-            // Unjail all models
+        Some(Commands::Models { provider, action }) => {
             let mut config = Config::load()?;
-            let mut found = false;
-            
-            for provider in &mut config.providers {
-                if let ProviderConfig::SimpleFreeOpenRouter(c) = provider {
-                    unjail_all_models(c)?;
+            let Some(simple_free) = find_simple_free(&mut config, provider.as_deref(), &settings)? else {
+                println!("No Simple Free OpenRouter configuration found. You can add one with 'aicommit provider add simple-free'");
+                return Ok(());
+            };
+            let policy = JailPolicy::from_settings(&settings);
+
+            match action {
+                ModelsCommand::Status { json: false } => display_model_jail_status(simple_free),
+                ModelsCommand::Status { json: true } => {
+                    println!("{}", serde_json::to_string_pretty(&model_status_json(simple_free))
+                        .map_err(|e| format!("Failed to serialize model status: {}", e))?);
+                    Ok(())
+                }
+                ModelsCommand::Jail { model, duration } => {
+                    let duration = duration.as_deref().map(utils::parse_duration).transpose()?;
+                    let until = jail_model(simple_free, model, duration, &policy)?;
+                    println!("Model '{}' jailed until {}", model, until.format("%Y-%m-%d %H:%M UTC"));
+                    Ok(())
+                }
+                ModelsCommand::Unjail { all: true, .. } => {
+                    unjail_all_models(simple_free)?;
                     println!("All models successfully released from jail");
-                    found = true;
-                    break;
+                    Ok(())
                 }
+                ModelsCommand::Unjail { model, .. } => {
+                    let model_id = model.as_deref().unwrap_or_default();
+                    unjail_model(simple_free, model_id)?;
+                    println!("Model '{}' successfully released from jail", model_id);
+                    Ok(())
+                }
+                ModelsCommand::Blacklist { model, remove } => {
+                    ban_model(simple_free, model, !remove)?;
+                    if *remove {
+                        println!("Model '{}' is no longer banned", model);
+                    } else {
+                        println!("Model '{}' banned. Lift the ban with 'aicommit models blacklist --remove {}'", model, model);
+                    }
+                    Ok(())
+                }
+                ModelsCommand::Pin { model, .. } => {
+                    pin_model(simple_free, model.as_deref())?;
+                    match model {
+                        Some(model) => println!("Model '{}' pinned; it is used whenever it is available", model),
+                        None => println!("Pin cleared"),
+                    }
+                    Ok(())
+                }
+                ModelsCommand::History { model, json } => display_model_history(simple_free, model.as_deref(), *json),
+                ModelsCommand::Update => update_model_list(simple_free, cli.refresh_models, &RetryPolicy::from_settings(&settings)).await,
+                ModelsCommand::List { .. } => unreachable!("handled above"),
             }
-            
-            if !found {
-                println!("No Simple Free OpenRouter configuration found. You can add one with 'aicommit --add-simple-free'");
-            }
-            
-            Ok(())
         }
-        _ if cli.github_action => {
+        Some(Commands::Hook { action }) => match action {
+            HookCommand::Install => hooks::install_hook(),
+            HookCommand::Uninstall => hooks::uninstall_hook(),
+            HookCommand::Status => hooks::hook_status(),
+        },
+        Some(Commands::Action(args)) => {
             // GitHub Action mode - non-interactive
-            run_github_action_mode(&cli).await
+            run_github_action_mode(&cli, &settings, args).await
         }
-        _ if cli.hook.is_some() => {
-            // Hook management
-            let hook_cmd = cli.hook.as_ref().unwrap();
-            match hook_cmd.as_str() {
-                "install" => hooks::install_hook(),
-                "uninstall" => hooks::uninstall_hook(),
-                "status" => hooks::hook_status(),
-                _ => Err(format!(
-                    "Unknown hook command: '{}'. Valid commands are: install, uninstall, status",
-                    hook_cmd
-                )),
+        Some(Commands::Ignore { action: IgnoreCommand::Check { paths } }) => ignore::print_ignore_check(paths, &settings),
+        Some(Commands::Style { action }) => match action {
+            StyleCommand::Learn { commits } => style::learn_style(*commits),
+            StyleCommand::Show => style::show_style(),
+        },
+        Some(Commands::Config { action }) => match action {
+            ConfigCommand::Show { origin } => {
+                settings.print(*origin);
+                Ok(())
             }
-        }
-        _ if cli.add_provider => {
-            Config::setup_interactive().await?;
-            println!("Provider added successfully!");
-            Ok(())
-        }
-        _ if cli.add_openrouter || cli.add_ollama || cli.add_openai_compatible || cli.add_simple_free => {
-            Config::setup_non_interactive(&cli).await?;
-            println!("Provider added successfully!");
-            Ok(())
-        }
-        _ if cli.list => {
-            // Список всех провайдеров
-            let config = Config::load()?;
-            for provider in config.providers {
-                match provider {
-                    ProviderConfig::OpenRouter(c) => println!("OpenRouter: {}", c.id),
-                    ProviderConfig::Ollama(c) => println!("Ollama: {}", c.id),
-                    ProviderConfig::OpenAICompatible(c) => println!("OpenAI Compatible: {}", c.id),
-                    ProviderConfig::SimpleFreeOpenRouter(c) => println!("Simple Free OpenRouter: {}", c.id),
-                    ProviderConfig::ClaudeCode(c) => println!("Claude Code: {}", c.id),
-                    ProviderConfig::OpenCode(c) => println!("OpenCode: {}", c.id),
+            ConfigCommand::Validate => Config::validate(),
+            ConfigCommand::Edit => {
+                Config::edit()?;
+                println!("Configuration updated.");
+                if let Err(e) = Config::validate() {
+                    eprintln!("Warning: {}", e);
                 }
-            }
-            Ok(())
-        }
-        _ if cli.set.is_some() => {
-            // Установка активного провайдера
-            let mut config = Config::load()?;
-            let new_active_provider = cli.set.unwrap();
-            let mut found = false;
-
-            for provider in &config.providers {
-                match provider {
-                    ProviderConfig::OpenRouter(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    ProviderConfig::Ollama(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    ProviderConfig::OpenAICompatible(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    ProviderConfig::SimpleFreeOpenRouter(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    ProviderConfig::ClaudeCode(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    ProviderConfig::OpenCode(c) => {
-                        if c.id == new_active_provider {
-                            config.active_provider = c.id.clone();
-                            found = true;
-                            break;
-                        }
-                    }
+                if let Ok(config) = Config::load() {
+                    warn_plaintext_api_keys(&config);
                 }
+                Ok(())
             }
+        },
+        Some(Commands::Man) => {
+            clap_mangen::Man::new(Cli::command())
+                .render(&mut std::io::stdout())
+                .map_err(|e| format!("Failed to render man page: {}", e))
+        }
+        Some(Commands::Completions { shell }) => completions::print_completions(*shell),
+        Some(Commands::Complete { .. }) => unreachable!("handled before logging is set up"),
+    }
+}
 
-            if !found {
-                return Err(format!("Provider '{}' not found", new_active_provider));
-            }
-
-            let config_path = dirs::home_dir()
-                .ok_or_else(|| "Could not find home directory".to_string())?
-                .join(".aicommit.json");
+/// Load ~/.aicommit.json for commit/watch, exiting with a hint when no provider is configured
+fn load_config_with_active_provider(settings: &Settings) -> Config {
+    let config = Config::load().unwrap_or_else(|_| {
+        println!("No configuration found. Run 'aicommit provider add' to set up a provider.");
+        std::process::exit(1);
+    });
 
-            let content = serde_json::to_string_pretty(&config)
-                .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    if config.active_provider_config(settings).is_none() {
+        println!("No active provider found. Please run 'aicommit provider add' to configure a provider.");
+        std::process::exit(1);
+    }
 
-            fs::write(&config_path, content)
-                .map_err(|e| format!("Failed to write config file: {}", e))?;
+    config
+}
 
-            println!("Active provider set to {}", new_active_provider);
-            Ok(())
-        }
-        _ if cli.config => {
-            Config::edit()?;
-            println!("Configuration updated.");
-            Ok(())
-        }
-        _ if cli.dry_run => {
-            // Special handling for --dry-run to provide better error messages
-            match dry_run(&cli).await {
-                Ok(message) => {
-                    println!("{}", message);
-                    Ok(())
-                }
-                Err(e) => {
-                    // Provide more detailed error message
-                    eprintln!("Error in dry-run mode: {}", e);
-                    Err(e)
-                }
+/// The Simple Free provider `models` commands work on: the given one, else the active provider
+/// if it is a Simple Free one, else the first Simple Free provider
+fn find_simple_free<'a>(config: &'a mut Config, id: Option<&str>, settings: &Settings) -> Result<Option<&'a mut SimpleFreeOpenRouterConfig>, String> {
+    let is_simple_free = |p: &ProviderConfig| matches!(p, ProviderConfig::SimpleFreeOpenRouter(_));
+    let index = match id {
+        Some(id) => {
+            let index = config.providers.iter().position(|p| p.id() == id)
+                .ok_or_else(|| format!("Provider '{}' not found", id))?;
+            if !is_simple_free(&config.providers[index]) {
+                return Err(format!("Provider '{}' is not a Simple Free OpenRouter provider", id));
             }
+            Some(index)
         }
-        _ => {
-            let config = Config::load().unwrap_or_else(|_| {
-                println!("No configuration found. Run 'aicommit --add-provider' to set up a provider.");
-                std::process::exit(1);
-            });
-
-            if config.active_provider.is_empty() || config.providers.is_empty() {
-                println!("No active provider found. Please run 'aicommit --add-provider' to configure a provider.");
-                std::process::exit(1);
+        None => {
+            let active_id = config.active_provider_config(settings)
+                .filter(|p| is_simple_free(p))
+                .map(|p| p.id().to_string());
+            match active_id {
+                Some(active_id) => config.providers.iter().position(|p| p.id() == active_id),
+                None => config.providers.iter().position(is_simple_free),
             }
+        }
+    };
 
-            // Check if we're in watch mode
-            if cli.watch {
-                watch_and_commit(&config, &cli).await?
-            } else {
-                run_commit(&config, &cli).await?;
+    Ok(index.and_then(|index| match &mut config.providers[index] {
+        ProviderConfig::SimpleFreeOpenRouter(c) => Some(c),
+        _ => None,
+    }))
+}
+
+/// Look up a provider by ID, or the active provider when no ID is given
+fn find_provider<'a>(config: &'a Config, id: Option<&str>, settings: &Settings) -> Result<&'a ProviderConfig, String> {
+    match id {
+        Some(id) => config.providers.iter().find(|p| p.id() == id)
+            .ok_or_else(|| format!("Provider '{}' not found", id)),
+        None => config.active_provider_config(settings)
+            .ok_or_else(|| "No active provider found. Pass a provider ID or set one with 'aicommit provider use <name>'".to_string()),
+    }
+}
+
+/// Point out providers whose API key is stored in plain text in ~/.aicommit.json
+fn warn_plaintext_api_keys(config: &Config) {
+    for provider in &config.providers {
+        if let Some(key) = provider.api_key() {
+            if !key.is_empty() && !secrets::is_secret_reference(key) {
+                eprintln!("Warning: provider {} stores its API key in plain text ({}). \
+                           Consider an env:, file:, cmd: or keyring: reference instead.",
+                          provider.id(), secrets::redact_secret(key));
             }
-            Ok(())
         }
     }
 }
 
 // From: 033_function_dry_run.rs
-async fn dry_run(cli: &Cli) -> Result<String, String> {
+async fn dry_run(cli: &Cli, settings: &Settings) -> Result<String, String> {
     // Check .gitignore at startup if not skipping
     if !cli.no_gitignore_check {
         Config::check_gitignore()?;
@@ -471,58 +405,34 @@ async fn dry_run(cli: &Cli) -> Result<String, String> {
         }
     };
     
-    // Generate commit message
-    let (message, _) = {
-        let active_provider = config.providers.iter().find(|p| match p {
-            ProviderConfig::OpenRouter(c) => c.id == config.active_provider,
-            ProviderConfig::Ollama(c) => c.id == config.active_provider,
-            ProviderConfig::OpenAICompatible(c) => c.id == config.active_provider,
-            ProviderConfig::SimpleFreeOpenRouter(c) => c.id == config.active_provider,
-            ProviderConfig::ClaudeCode(c) => c.id == config.active_provider,
-            ProviderConfig::OpenCode(c) => c.id == config.active_provider,
-        }).ok_or_else(|| "No active provider found".to_string())?;
-
-        let mut attempt_count = 0;
-        loop {
-            if attempt_count > 0 {
-                eprintln!("Retry attempt {} of {}", attempt_count + 1, config.retry_attempts);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            }
+    // With scope.multiple = "split", one message per scope as the commit would make
+    let diffs = match scope::split_groups(&diff, settings) {
+        Some(groups) => groups.iter().map(|(_, paths)| scope::diff_for_paths(&diff, paths)).collect(),
+        None => vec![diff],
+    };
 
-            let result = match active_provider {
-                ProviderConfig::OpenRouter(c) => generate_openrouter_commit_message(c, &diff, cli).await,
-                ProviderConfig::Ollama(c) => generate_ollama_commit_message(c, &diff, cli).await,
-                ProviderConfig::OpenAICompatible(c) => generate_openai_compatible_commit_message(c, &diff, cli).await,
-                ProviderConfig::SimpleFreeOpenRouter(c) => {
-                    let mut c_clone = c.clone();
-                    let result = generate_simple_free_commit_message(&mut c_clone, &diff, cli).await;
-                    // We don't need to save failed models in dry-run mode
-                    result
-                },
-                ProviderConfig::ClaudeCode(c) => generate_claude_code_commit_message(c, &diff, cli).await,
-                ProviderConfig::OpenCode(c) => generate_opencode_commit_message(c, &diff, cli).await,
-            };
+    let active_provider = config.active_provider_config(settings)
+        .ok_or_else(|| "No active provider found".to_string())?;
+    let retry_policy = RetryPolicy::from_settings(settings).with_attempts(config.retry_attempts).for_provider(active_provider);
 
-            match result {
-                Ok(result) => break result,
-                Err(e) => {
-                    eprintln!("Attempt {} failed: {}", attempt_count + 1, e);
-                    attempt_count += 1;
-                    if attempt_count >= config.retry_attempts {
-                        return Err(format!("Failed to generate commit message after {} attempts. Last error: {}", config.retry_attempts, e));
-                    }
-                }
-            }
+    let mut messages = Vec::new();
+    for diff in &diffs {
+        // Generate commit message
+        let (message, _) = retry(&retry_policy, "generate commit message", || generate_with_provider(active_provider, diff, cli, settings)).await?;
+
+        // Final validation before returning in dry-run mode
+        if message.trim().is_empty() {
+            return Err("Aborting commit due to empty commit message.".to_string());
         }
-    };
 
-    // Final validation before returning in dry-run mode
-    if message.trim().is_empty() {
-        return Err("Aborting commit due to empty commit message.".to_string());
+        for issue in lint_message(&message, &LintRules::from_settings(settings)) {
+            eprintln!("Lint warning: {}", issue);
+        }
+        messages.push(message);
     }
 
-    // In dry-run mode, only return the generated message
-    Ok(message)
+    // In dry-run mode, only return the generated message(s)
+    Ok(messages.join("\n\n---\n\n"))
 }
 
 // GitHub Action output structures
@@ -538,52 +448,58 @@ struct GitHubActionOutput {
 }
 
 // From: github_action.rs
-async fn run_github_action_mode(cli: &Cli) -> Result<(), String> {
+async fn run_github_action_mode(cli: &Cli, settings: &Settings, args: &ActionArgs) -> Result<(), String> {
     // Get the diff input
-    let diff = get_github_action_diff(cli)?;
+    let diff = get_github_action_diff(cli, args)?;
 
     if diff.trim().is_empty() {
-        return output_github_action_error(cli, "No diff provided. Use --stdin, --input-diff, or --analyze-commits");
+        return output_github_action_error(args, "No diff provided. Use --stdin, --input-diff, or --analyze-commits");
     }
 
     // Get or create provider configuration
-    let (provider_config, mut simple_free_config) = create_github_action_provider(cli)?;
+    let (provider_config, mut simple_free_config) = create_github_action_provider(args)?;
+
+    // The egress policy applies to CI runs too
+    if let Err(e) = egress::check_egress(&provider_config, &diff, settings) {
+        return output_github_action_error(args, &e);
+    }
 
     // Generate the commit message
     let result = match &provider_config {
-        ProviderConfig::OpenRouter(c) => generate_openrouter_commit_message(c, &diff, cli).await,
-        ProviderConfig::Ollama(c) => generate_ollama_commit_message(c, &diff, cli).await,
-        ProviderConfig::OpenAICompatible(c) => generate_openai_compatible_commit_message(c, &diff, cli).await,
+        ProviderConfig::OpenRouter(c) => generate_openrouter_commit_message(c, &diff, cli, settings).await,
+        ProviderConfig::Ollama(c) => generate_ollama_commit_message(c, &diff, cli, settings).await,
+        ProviderConfig::OpenAICompatible(c) => generate_openai_compatible_commit_message(c, &diff, cli, settings).await,
         ProviderConfig::SimpleFreeOpenRouter(_) => {
             if let Some(ref mut c) = simple_free_config {
-                generate_simple_free_commit_message(c, &diff, cli).await
+                generate_simple_free_commit_message(c, &diff, cli, settings).await
             } else {
-                Err("Simple free config not available".to_string())
+                Err("Simple free config not available".into())
             }
         },
-        ProviderConfig::ClaudeCode(c) => generate_claude_code_commit_message(c, &diff, cli).await,
-        ProviderConfig::OpenCode(c) => generate_opencode_commit_message(c, &diff, cli).await,
+        ProviderConfig::ClaudeCode(c) => generate_claude_code_commit_message(c, &diff, cli, settings).await,
+        ProviderConfig::OpenCode(c) => generate_opencode_commit_message(c, &diff, cli, settings).await,
     };
 
     match result {
         Ok((message, usage_info)) => {
-            output_github_action_result(cli, &message, Some(usage_info))
+            let message = finish_message(&message, &diff, settings);
+            output_github_action_result(args, &message, Some(usage_info))
         }
         Err(e) => {
-            output_github_action_error(cli, &e)
+            output_github_action_error(args, &e.message)
         }
     }
 }
 
-fn get_github_action_diff(cli: &Cli) -> Result<String, String> {
+fn get_github_action_diff(cli: &Cli, args: &ActionArgs) -> Result<String, String> {
     // Priority: --input-diff > --stdin > --analyze-commits > env var > git diff
 
-    if let Some(ref file_path) = cli.input_diff {
+    if let Some(ref file_path) = args.input_diff {
         return fs::read_to_string(file_path)
             .map_err(|e| format!("Failed to read diff file '{}': {}", file_path, e));
     }
 
-    if cli.stdin {
+    if args.stdin {
         use std::io::{self, Read};
         let mut buffer = String::new();
         io::stdin().read_to_string(&mut buffer)
@@ -591,7 +507,7 @@ fn get_github_action_diff(cli: &Cli) -> Result<String, String> {
         return Ok(buffer);
     }
 
-    if cli.analyze_commits {
+    if args.analyze_commits {
         return get_commits_from_github_context();
     }
 
@@ -668,19 +584,19 @@ fn get_commits_from_github_context() -> Result<String, String> {
     Ok(context)
 }
 
-fn create_github_action_provider(cli: &Cli) -> Result<(ProviderConfig, Option<SimpleFreeOpenRouterConfig>), String> {
+fn create_github_action_provider(args: &ActionArgs) -> Result<(ProviderConfig, Option<SimpleFreeOpenRouterConfig>), String> {
     // Try to get API key from CLI or environment
-    let api_key = cli.api_key.clone()
+    let api_key = args.api_key.clone()
         .or_else(|| std::env::var("OPENROUTER_API_KEY").ok())
         .or_else(|| std::env::var("AICOMMIT_API_KEY").ok());
 
     // Determine provider type
-    let provider_type = cli.provider.clone()
+    let provider_type = args.provider.clone()
         .or_else(|| std::env::var("AICOMMIT_PROVIDER").ok())
         .unwrap_or_else(|| "simple-free".to_string());
 
     // Get model from CLI or environment
-    let model = cli.model.clone()
+    let model = args.model.clone()
         .or_else(|| std::env::var("AICOMMIT_MODEL").ok());
 
     match provider_type.as_str() {
@@ -693,8 +609,8 @@ fn create_github_action_provider(cli: &Cli) -> Result<(ProviderConfig, Option<Si
                 provider: "openrouter".to_string(),
                 api_key,
                 model: model.unwrap_or_else(|| "mistralai/mistral-tiny".to_string()),
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
+                max_tokens: args.max_tokens,
+                temperature: args.temperature,
             }), None))
         }
         "simple-free" => {
@@ -705,9 +621,8 @@ fn create_github_action_provider(cli: &Cli) -> Result<(ProviderConfig, Option<Si
                 id: "github-action".to_string(),
                 provider: "simple_free_openrouter".to_string(),
                 api_key,
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
-                failed_models: Vec::new(),
+                max_tokens: args.max_tokens,
+                temperature: args.temperature,
                 model_stats: std::collections::HashMap::new(),
                 last_used_model: model,
                 last_config_update: chrono::Utc::now(),
@@ -716,40 +631,43 @@ fn create_github_action_provider(cli: &Cli) -> Result<(ProviderConfig, Option<Si
         }
         "ollama" => {
             let url = std::env::var("OLLAMA_URL")
-                .unwrap_or_else(|_| cli.ollama_url.clone());
+                .ok()
+                .or_else(|| args.url.clone())
+                .unwrap_or_else(|| "http://localhost:11434".to_string());
 
             Ok((ProviderConfig::Ollama(OllamaConfig {
                 id: "github-action".to_string(),
                 provider: "ollama".to_string(),
-                model: model.unwrap_or_else(|| cli.ollama_model.clone()),
+                model: model.unwrap_or_else(|| "llama2".to_string()),
                 url,
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
+                max_tokens: args.max_tokens,
+                temperature: args.temperature,
             }), None))
         }
         "openai-compatible" => {
             let api_key = api_key.ok_or_else(||
                 "API key required for OpenAI Compatible. Set --api-key environment variable.".to_string())?;
             let api_url = std::env::var("OPENAI_COMPATIBLE_URL")
-                .or_else(|_| cli.openai_compatible_api_url.clone().ok_or(()))
-                .map_err(|_| "API URL required for OpenAI Compatible. Set OPENAI_COMPATIBLE_URL environment variable.".to_string())?;
+                .ok()
+                .or_else(|| args.url.clone())
+                .ok_or_else(|| "API URL required for OpenAI Compatible. Set --url or OPENAI_COMPATIBLE_URL environment variable.".to_string())?;
 
             Ok((ProviderConfig::OpenAICompatible(OpenAICompatibleConfig {
                 id: "github-action".to_string(),
                 provider: "openai_compatible".to_string(),
                 api_key,
                 api_url,
-                model: model.unwrap_or_else(|| cli.openai_compatible_model.clone()),
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
+                model: model.unwrap_or_else(|| "gpt-3.5-turbo".to_string()),
+                max_tokens: args.max_tokens,
+                temperature: args.temperature,
             }), None))
         }
         _ => Err(format!("Unknown provider type: {}. Valid options: openrouter, simple-free, ollama, openai-compatible", provider_type))
     }
 }
 
-fn output_github_action_result(cli: &Cli, message: &str, usage_info: Option<UsageInfo>) -> Result<(), String> {
-    match cli.output_format.as_str() {
+fn output_github_action_result(args: &ActionArgs, message: &str, usage_info: Option<UsageInfo>) -> Result<(), String> {
+    match args.output_format.as_str() {
         "json" => {
             let output = GitHubActionOutput {
                 commit_message: message.to_string(),
@@ -799,8 +717,8 @@ fn output_github_action_result(cli: &Cli, message: &str, usage_info: Option<Usag
     Ok(())
 }
 
-fn output_github_action_error(cli: &Cli, error: &str) -> Result<(), String> {
-    match cli.output_format.as_str() {
+fn output_github_action_error(args: &ActionArgs, error: &str) -> Result<(), String> {
+    match args.output_format.as_str() {
         "json" => {
             let output = GitHubActionOutput {
                 commit_message: String::new(),
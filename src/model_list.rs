@@ -0,0 +1,181 @@
+// Free model preference list - bundled with the binary, overridable by
+// ~/.config/aicommit/free_models.json, which `aicommit models update` rebuilds from OpenRouter's catalogue
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use crate::settings::user_config_dir;
+use crate::utils::write_private_file;
+
+const BUNDLED_MODEL_LIST: &str = include_str!("free_models.json");
+
+/// Preferred free models (best first) and the name patterns used to guess a model's size
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelList {
+    /// When `models update` built this list (absent for the bundled list)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+    pub preferred: Vec<String>,
+    #[serde(default)]
+    pub size_hints: Vec<SizeHint>,
+}
+
+/// A model whose name contains `pattern` is assumed to have `size` billion parameters.
+/// Hints are checked in order, the first match wins.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SizeHint {
+    pub pattern: String,
+    pub size: u32,
+}
+
+/// ~/.config/aicommit/free_models.json
+pub fn user_model_list_path() -> Result<PathBuf, String> {
+    user_config_dir()
+        .map(|dir| dir.join("free_models.json"))
+        .ok_or_else(|| "Could not find home directory".to_string())
+}
+
+impl ModelList {
+    /// The list in use: the user's copy when there is a valid one, the bundled list otherwise
+    pub fn get() -> &'static ModelList {
+        static LIST: OnceLock<ModelList> = OnceLock::new();
+        LIST.get_or_init(|| match Self::load_user() {
+            Ok(Some(list)) => list,
+            Ok(None) => Self::bundled(),
+            Err(e) => {
+                eprintln!("Warning: {}. Using the bundled free model list.", e);
+                Self::bundled()
+            }
+        })
+    }
+
+    pub fn bundled() -> ModelList {
+        serde_json::from_str(BUNDLED_MODEL_LIST).expect("bundled free_models.json is valid")
+    }
+
+    fn load_user() -> Result<Option<ModelList>, String> {
+        let path = user_model_list_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save_user(&self) -> Result<PathBuf, String> {
+        let path = user_model_list_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize model list: {}", e))?;
+        write_private_file(&path, &content)?;
+        Ok(path)
+    }
+
+    /// Guess a model's size in billions of parameters from its name (0 when nothing matches).
+    /// A numeric pattern only matches on its own, so "7b" does not match "17b".
+    pub fn model_size(&self, model_name: &str) -> u32 {
+        let lower_name = model_name.to_lowercase();
+        for hint in &self.size_hints {
+            let matched = lower_name.match_indices(&hint.pattern).any(|(index, _)| {
+                let numeric = hint.pattern.starts_with(|c: char| c.is_ascii_digit());
+                !numeric || !lower_name[..index].ends_with(|c: char| c.is_ascii_digit() || c == '.')
+            });
+            if matched {
+                return hint.size;
+            }
+        }
+        0
+    }
+
+    /// Rebuild the list from OpenRouter's `/models` catalogue and the IDs of its free models.
+    /// Models of the current list that are still free keep their order at the top, new free
+    /// models follow by guessed size and context length. Sizes that appear in the new model
+    /// names ("-27b-") are added to the size hints.
+    pub fn rebuild(&self, catalogue: &[serde_json::Value], free_models: &[String]) -> ModelList {
+        let free: HashSet<&str> = free_models.iter().map(String::as_str).collect();
+
+        let mut size_hints = self.size_hints.clone();
+        static SIZE_PATTERN: OnceLock<Regex> = OnceLock::new();
+        let size_pattern = SIZE_PATTERN.get_or_init(|| Regex::new(r"(?:^|[^0-9.])(\d{1,4})b(?:[^a-z]|$)").unwrap());
+        for model in free_models {
+            for captures in size_pattern.captures_iter(&model.to_lowercase()) {
+                let pattern = format!("{}b", &captures[1]);
+                if let Ok(size) = captures[1].parse::<u32>() {
+                    if size > 0 && !size_hints.iter().any(|hint| hint.pattern == pattern) {
+                        size_hints.push(SizeHint { pattern, size });
+                    }
+                }
+            }
+        }
+        // Numeric hints largest first, keyword hints ("large", "tiny") after them
+        size_hints.sort_by_key(|hint| {
+            let numeric = hint.pattern.starts_with(|c: char| c.is_ascii_digit());
+            (!numeric, std::cmp::Reverse(if numeric { hint.size } else { 0 }))
+        });
+
+        let mut list = ModelList {
+            updated: Some(chrono::Utc::now().format("%Y-%m-%d").to_string()),
+            preferred: self.preferred.iter().filter(|model| free.contains(model.as_str())).cloned().collect(),
+            size_hints,
+        };
+
+        let context_length = |id: &str| catalogue.iter()
+            .find(|model| model["id"].as_str() == Some(id))
+            .and_then(|model| model["context_length"].as_u64())
+            .unwrap_or(0);
+        let mut seen: HashSet<&String> = list.preferred.iter().collect();
+        let mut new_models: Vec<&String> = free_models.iter()
+            .filter(|model| seen.insert(*model))
+            .collect();
+        new_models.sort_by_key(|model| (std::cmp::Reverse(list.model_size(model)), std::cmp::Reverse(context_length(model))));
+        list.preferred.extend(new_models.into_iter().cloned());
+
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bundled_list_sizes() {
+        let list = ModelList::bundled();
+        assert!(!list.preferred.is_empty());
+        assert_eq!(list.model_size("qwen/qwen3-235b-a22b:free"), 235);
+        assert_eq!(list.model_size("meta-llama/llama-3.3-70b-instruct:free"), 70);
+        assert_eq!(list.model_size("meta-llama/llama-4-maverick-17b:free"), 0);
+        assert_eq!(list.model_size("mistralai/mistral-small:free"), 5);
+    }
+
+    #[test]
+    fn test_rebuild_keeps_known_order_and_adds_new_models() {
+        let list = ModelList {
+            updated: None,
+            preferred: vec!["a/known-1:free".to_string(), "a/gone:free".to_string(), "a/known-2:free".to_string()],
+            size_hints: vec![SizeHint { pattern: "8b".to_string(), size: 8 }],
+        };
+        let catalogue = vec![
+            json!({"id": "b/new-8b:free", "context_length": 8192}),
+            json!({"id": "b/new-27b:free", "context_length": 4096}),
+            json!({"id": "b/other-8b:free", "context_length": 131072}),
+        ];
+        let free: Vec<String> = ["a/known-2:free", "b/new-8b:free", "a/known-1:free", "b/new-27b:free", "b/other-8b:free"]
+            .iter().map(|s| s.to_string()).collect();
+
+        let rebuilt = list.rebuild(&catalogue, &free);
+        assert_eq!(rebuilt.preferred, vec!["a/known-1:free", "a/known-2:free", "b/new-27b:free", "b/other-8b:free", "b/new-8b:free"]);
+        assert_eq!(rebuilt.size_hints[0], SizeHint { pattern: "27b".to_string(), size: 27 });
+        assert_eq!(rebuilt.model_size("x/y-27b"), 27);
+    }
+}
@@ -1,150 +1,442 @@
 // Model management functions
 
+use std::sync::OnceLock;
+use regex::Regex;
 use crate::types::*;
-use crate::{PREFERRED_FREE_MODELS, MAX_CONSECUTIVE_FAILURES, INITIAL_JAIL_HOURS, JAIL_TIME_MULTIPLIER, MAX_JAIL_HOURS, BLACKLIST_AFTER_JAIL_COUNT, BLACKLIST_RETRY_DAYS};
-use std::fs;
+use crate::state::update_provider_state;
+use crate::model_list::ModelList;
+use crate::secrets::resolve_secret;
+use crate::catalogue::{load_catalogue, model_price};
+use crate::settings::Settings;
+use crate::retry::RetryPolicy;
+use crate::{MAX_CONSECUTIVE_FAILURES, INITIAL_JAIL_HOURS, JAIL_TIME_MULTIPLIER, MAX_JAIL_HOURS, BLACKLIST_AFTER_JAIL_COUNT, BLACKLIST_RETRY_DAYS};
+use crate::{RATE_LIMIT_COOLDOWN_MINUTES, OUTAGE_COOLDOWN_MINUTES, CONTENT_FILTER_COOLDOWN_MINUTES};
+use crate::{FAILOVER_MAX_MODELS, HEDGE_AFTER_SECS, FAILOVER_DEADLINE_SECS};
+use crate::{RECENT_SAMPLES, SCORE_DECAY, EXPLORATION, LATENCY_SCALE_SECS};
 use chrono;
 
+/// Thresholds of the jail system, configurable in the [jail] section of the layered settings
+#[derive(Debug, Clone)]
+pub struct JailPolicy {
+    pub max_consecutive_failures: usize,
+    pub initial_hours: i64,
+    pub multiplier: i64,
+    pub max_hours: i64,
+    pub blacklist_after: usize,
+    pub blacklist_retry_days: i64,
+    pub rate_limit_minutes: i64,
+    pub outage_minutes: i64,
+    pub content_filter_minutes: i64,
+}
+
+impl Default for JailPolicy {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: MAX_CONSECUTIVE_FAILURES,
+            initial_hours: INITIAL_JAIL_HOURS,
+            multiplier: JAIL_TIME_MULTIPLIER,
+            max_hours: MAX_JAIL_HOURS,
+            blacklist_after: BLACKLIST_AFTER_JAIL_COUNT,
+            blacklist_retry_days: BLACKLIST_RETRY_DAYS,
+            rate_limit_minutes: RATE_LIMIT_COOLDOWN_MINUTES,
+            outage_minutes: OUTAGE_COOLDOWN_MINUTES,
+            content_filter_minutes: CONTENT_FILTER_COOLDOWN_MINUTES,
+        }
+    }
+}
+
+impl JailPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let positive = |key: &str| settings.get_int(key).map(|n| n.max(1));
+        Self {
+            max_consecutive_failures: positive("jail.max_consecutive_failures").map_or(defaults.max_consecutive_failures, |n| n as usize),
+            initial_hours: positive("jail.initial_hours").unwrap_or(defaults.initial_hours),
+            multiplier: positive("jail.multiplier").unwrap_or(defaults.multiplier),
+            max_hours: positive("jail.max_hours").unwrap_or(defaults.max_hours),
+            blacklist_after: positive("jail.blacklist_after").map_or(defaults.blacklist_after, |n| n as usize),
+            blacklist_retry_days: positive("jail.blacklist_retry_days").unwrap_or(defaults.blacklist_retry_days),
+            rate_limit_minutes: positive("jail.rate_limit_minutes").unwrap_or(defaults.rate_limit_minutes),
+            outage_minutes: positive("jail.outage_minutes").unwrap_or(defaults.outage_minutes),
+            content_filter_minutes: positive("jail.content_filter_minutes").unwrap_or(defaults.content_filter_minutes),
+        }
+    }
+}
+
+/// How one Simple Free attempt moves between models, configurable in the [failover] section
+#[derive(Debug, Clone)]
+pub struct FailoverPolicy {
+    pub max_models: usize,
+    /// `None` when hedging is disabled
+    pub hedge_after: Option<std::time::Duration>,
+    pub deadline: std::time::Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            max_models: FAILOVER_MAX_MODELS,
+            hedge_after: Some(std::time::Duration::from_secs(HEDGE_AFTER_SECS as u64)),
+            deadline: std::time::Duration::from_secs(FAILOVER_DEADLINE_SECS as u64),
+        }
+    }
+}
+
+impl FailoverPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let seconds = |n: i64| std::time::Duration::from_secs(n.max(0) as u64);
+        Self {
+            max_models: settings.get_int("failover.max_models").map_or(defaults.max_models, |n| n.max(1) as usize),
+            hedge_after: match settings.get_int("failover.hedge_after_secs") {
+                Some(n) if n <= 0 => None,
+                Some(n) => Some(seconds(n)),
+                None => defaults.hedge_after,
+            },
+            deadline: settings.get_int("failover.deadline_secs").map_or(defaults.deadline, |n| seconds(n.max(1))),
+        }
+    }
+}
+
+/// What the Simple Free failover loop does next
+#[derive(Debug, PartialEq)]
+pub enum FailoverStep {
+    /// Send the prompt to this model
+    Launch(String),
+    /// Wait for the running requests until this instant at the latest
+    Wait(tokio::time::Instant),
+    /// The deadline has passed, or nothing runs and no model is left to try
+    Stop,
+}
+
+/// The state of one Simple Free generation: the models running and the ones that failed
+pub struct FailoverRun {
+    pub policy: FailoverPolicy,
+    pub deadline: tokio::time::Instant,
+    pub running: Vec<String>,
+    /// Model and what went wrong
+    pub tried: Vec<(String, String)>,
+    pub last_launch: tokio::time::Instant,
+}
+
+impl FailoverRun {
+    pub fn new(policy: FailoverPolicy, started: tokio::time::Instant) -> Self {
+        Self { deadline: started + policy.deadline, policy, running: Vec::new(), tried: Vec::new(), last_launch: started }
+    }
+
+    /// The step at `now`: launch the best model not tried yet when nothing runs, or when the
+    /// only running one has been slow for `hedge_after`, otherwise wait. Cooled down and
+    /// jailed models are skipped as `find_best_available_model` does.
+    pub fn next_step(&self, now: tokio::time::Instant, models: &[String], config: &SimpleFreeOpenRouterConfig, jail: &JailPolicy) -> FailoverStep {
+        if now >= self.deadline {
+            return FailoverStep::Stop;
+        }
+        let next_model = || {
+            if self.tried.len() + self.running.len() >= self.policy.max_models {
+                return None;
+            }
+            let candidates: Vec<String> = models.iter()
+                .filter(|model| !self.running.contains(model) && !self.tried.iter().any(|(tried, _)| tried == *model))
+                .cloned()
+                .collect();
+            find_best_available_model(&candidates, config, jail)
+        };
+
+        if self.running.is_empty() {
+            return next_model().map_or(FailoverStep::Stop, FailoverStep::Launch);
+        }
+        match self.policy.hedge_after {
+            Some(hedge_after) if self.running.len() == 1 => {
+                let hedge_at = self.last_launch + hedge_after;
+                match next_model() {
+                    Some(model) if now >= hedge_at => FailoverStep::Launch(model),
+                    Some(_) => FailoverStep::Wait(hedge_at.min(self.deadline)),
+                    None => FailoverStep::Wait(self.deadline),
+                }
+            }
+            _ => FailoverStep::Wait(self.deadline),
+        }
+    }
+}
+
+/// Why a request to a model failed. Only bad output counts towards jail terms, rate limits,
+/// outages and content filters cool the model down for a while, auth and transport errors
+/// are not the model's fault and leave its statistics alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    /// A local error no retry can fix: invalid settings, a blocked secret, the egress policy
+    Fatal,
+    /// 401/402/403: the API key or the account
+    Auth,
+    /// 429, cooled down for the advertised Retry-After
+    RateLimit { retry_after: Option<std::time::Duration> },
+    /// 5xx or no endpoint serving the model
+    Outage,
+    /// The model's moderation refused the diff
+    ContentFilter,
+    /// Unparseable, empty or too short response, or a request the model rejects
+    BadOutput,
+    /// Connection error or timeout
+    Transport,
+}
+
+impl FailureKind {
+    /// Classify an HTTP error response (OpenRouter also reports errors as `{"error": {"code": ...}}`
+    /// inside a 200 response, pass that code here too)
+    pub fn from_status(status: u16, body: &str, retry_after: Option<std::time::Duration>) -> Self {
+        let body = body.to_lowercase();
+        if ["moderation", "flagged", "content filter", "content_filter"].iter().any(|needle| body.contains(needle)) {
+            return FailureKind::ContentFilter;
+        }
+        match status {
+            401..=403 => FailureKind::Auth,
+            429 => FailureKind::RateLimit { retry_after },
+            408 => FailureKind::Transport,
+            404 | 500..=599 => FailureKind::Outage,
+            _ => FailureKind::BadOutput,
+        }
+    }
+
+    /// Classify the error message of a failed generation, for providers that only report strings
+    pub fn from_error_message(error: &str) -> Self {
+        let lower = error.to_lowercase();
+        // "429 Too Many Requests" from reqwest, "code": 429 from an error body
+        static STATUS: OnceLock<Regex> = OnceLock::new();
+        static RETRY_AFTER: OnceLock<Regex> = OnceLock::new();
+        let status = STATUS.get_or_init(|| Regex::new(r#""code"\s*:\s*([45]\d\d)|\b([45]\d\d) [A-Z][a-z]"#).unwrap())
+            .captures(error)
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .and_then(|code| code.as_str().parse::<u16>().ok());
+        let retry_after = RETRY_AFTER.get_or_init(|| Regex::new(r"retry after (\d+)s").unwrap())
+            .captures(&lower)
+            .and_then(|captures| captures[1].parse().ok())
+            .map(std::time::Duration::from_secs);
+
+        if lower.contains("rate limit") || lower.contains("too many requests") {
+            return FailureKind::RateLimit { retry_after };
+        }
+        if lower.contains("timed out") || lower.contains("request error") || lower.contains("failed to connect") {
+            return FailureKind::Transport;
+        }
+        if ["unauthorized", "forbidden", "invalid api key", "no auth credentials"].iter().any(|needle| lower.contains(needle)) {
+            return FailureKind::Auth;
+        }
+        Self::from_status(status.unwrap_or(200), &lower, retry_after)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FailureKind::Fatal => "fatal",
+            FailureKind::Auth => "auth",
+            FailureKind::RateLimit { .. } => "rate limit",
+            FailureKind::Outage => "outage",
+            FailureKind::ContentFilter => "content filter",
+            FailureKind::BadOutput => "bad output",
+            FailureKind::Transport => "transport",
+        }
+    }
+
+    /// Name of the kind in the `retry.retryable` setting
+    pub fn key(&self) -> &'static str {
+        match self {
+            FailureKind::Fatal => "fatal",
+            FailureKind::Auth => "auth",
+            FailureKind::RateLimit { .. } => "rate_limit",
+            FailureKind::Outage => "outage",
+            FailureKind::ContentFilter => "content_filter",
+            FailureKind::BadOutput => "bad_output",
+            FailureKind::Transport => "transport",
+        }
+    }
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureKind::RateLimit { retry_after: Some(delay) } => write!(f, "rate limit, retry after {}s", delay.as_secs()),
+            kind => f.write_str(kind.label()),
+        }
+    }
+}
+
+/// A failed request with its kind. Errors that arrive as plain strings are classified with
+/// `FailureKind::from_error_message`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn fatal(message: String) -> Self {
+        Self { kind: FailureKind::Fatal, message }
+    }
+
+    /// A non-2xx response, classified by its status and body
+    pub fn http(status: reqwest::StatusCode, body: &str, retry_after: Option<std::time::Duration>, message: String) -> Self {
+        Self { kind: FailureKind::from_status(status.as_u16(), body, retry_after), message }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self { kind: FailureKind::from_error_message(&message), message }
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<Failure> for String {
+    fn from(failure: Failure) -> Self {
+        failure.message
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Delay advertised by a 429 response: `Retry-After` in seconds or as an HTTP date, or OpenRouter's
+/// `X-RateLimit-Reset` (milliseconds since the epoch)
+pub fn parse_retry_after(retry_after: Option<&str>, rate_limit_reset: Option<&str>, now: chrono::DateTime<chrono::Utc>) -> Option<std::time::Duration> {
+    let until = |at: chrono::DateTime<chrono::Utc>| (at - now).to_std().ok();
+    if let Some(value) = retry_after.map(str::trim) {
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(std::time::Duration::from_secs(seconds));
+        }
+        if let Ok(at) = chrono::DateTime::parse_from_rfc2822(value) {
+            return until(at.with_timezone(&chrono::Utc));
+        }
+    }
+    rate_limit_reset
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .and_then(until)
+}
+
+/// `parse_retry_after` for the headers of an error response
+pub fn response_retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok());
+    parse_retry_after(header("retry-after"), header("x-ratelimit-reset"), chrono::Utc::now())
+}
+
 // From: 035_function_get_available_free_models.rs
-pub async fn get_available_free_models(api_key: &str, simulate_offline: bool) -> Result<Vec<String>, String> {
+pub async fn get_available_free_models(api_key: &str, simulate_offline: bool, refresh: bool, retry_policy: &RetryPolicy) -> Result<Vec<String>, String> {
     // If simulate_offline is true, immediately return the fallback list
     if simulate_offline {
-        println!("Debug: Simulating offline mode, using fallback model list");
+        eprintln!("Debug: Simulating offline mode, using fallback model list");
         return fallback_to_preferred_models();
     }
     
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(10)) // Add a reasonable timeout
-        .build()
-        .unwrap_or_default();
-    
-    // Try to fetch models from OpenRouter API
-    let response = match tokio::time::timeout(
-        std::time::Duration::from_secs(15),
-        client.get("https://openrouter.ai/api/v1/models")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("HTTP-Referer", "https://suenot.github.io/aicommit/")
-            .header("X-Title", "aicommit")
-            .send()
-    ).await {
-        Ok(result) => match result {
-            Ok(response) => {
-                if !response.status().is_success() {
-                    println!("Warning: OpenRouter API returned status code: {}", response.status());
-                    return fallback_to_preferred_models();
-                }
-                response
-            },
-            Err(e) => {
-                println!("Warning: Failed to connect to OpenRouter API: {}", e);
-                println!("Using predefined free models as fallback...");
-                return fallback_to_preferred_models();
-            }
-        },
-        Err(_) => {
-            println!("Warning: Request to OpenRouter API timed out after 15 seconds");
-            println!("Using predefined free models as fallback...");
+    let catalogue = match load_catalogue(Some(api_key), refresh, retry_policy).await {
+        Ok(catalogue) => catalogue,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            eprintln!("Using predefined free models as fallback...");
             return fallback_to_preferred_models();
         }
     };
-    
-    // Try to parse the response
-    let models_response: Result<serde_json::Value, _> = response.json().await;
-    if let Err(e) = &models_response {
-        println!("Warning: Failed to parse OpenRouter API response: {}", e);
-        println!("Using predefined free models as fallback...");
+    let free_models = free_models_from_catalogue(&catalogue);
+
+    // If we still found no free models, fall back to predefined list
+    if free_models.is_empty() {
+        eprintln!("Warning: No free models found from OpenRouter API");
+        eprintln!("Using predefined free models as fallback...");
         return fallback_to_preferred_models();
     }
     
-    let models_response = models_response.unwrap();
+    Ok(free_models)
+}
+
+/// IDs of the free models in the catalogue
+pub fn free_models_from_catalogue(catalogue: &[serde_json::Value]) -> Vec<String> {
     let mut free_models = Vec::new();
-    
-    if let Some(data) = models_response["data"].as_array() {
-        // First pass: check all models that are explicitly marked as free
-        for model in data {
-            if let Some(id) = model["id"].as_str() {
-                // Multiple ways to detect if a model is free:
-                
-                // 1. Check if the model ID contains ":free"
-                if id.contains(":free") {
-                    free_models.push(id.to_string());
-                    continue;
-                }
-                
-                // 2. Check if "free" field is true
-                if let Some(true) = model["free"].as_bool() {
+
+    // First pass: check all models that are explicitly marked as free
+    for model in catalogue {
+        if let Some(id) = model["id"].as_str() {
+            // Multiple ways to detect if a model is free:
+            
+            // 1. Check if the model ID contains ":free"
+            if id.contains(":free") {
+                free_models.push(id.to_string());
+                continue;
+            }
+            
+            // 2. Check if "free" field is true
+            if let Some(true) = model["free"].as_bool() {
+                free_models.push(id.to_string());
+                continue;
+            }
+            
+            // 3. Check if "free_tokens" is greater than 0
+            if let Some(tokens) = model["free_tokens"].as_u64() {
+                if tokens > 0 {
                     free_models.push(id.to_string());
                     continue;
                 }
-                
-                // 3. Check if "free_tokens" is greater than 0
-                if let Some(tokens) = model["free_tokens"].as_u64() {
-                    if tokens > 0 {
-                        free_models.push(id.to_string());
-                        continue;
-                    }
-                }
-                
-                // 4. Check if pricing is 0 for both prompt and completion
-                if let Some(pricing) = model["pricing"].as_object() {
-                    let prompt_price = pricing.get("prompt")
-                        .and_then(|v| v.as_f64())
-                        .unwrap_or(1.0);
-                    
-                    let completion_price = pricing.get("completion")
-                        .and_then(|v| v.as_f64())
-                        .unwrap_or(1.0);
-                    
-                    if prompt_price == 0.0 && completion_price == 0.0 {
-                        free_models.push(id.to_string());
-                        continue;
-                    }
-                }
+            }
+            
+            // 4. Check if pricing is 0 for both prompt and completion
+            let prompt_price = model_price(model, "prompt").unwrap_or(1.0);
+            let completion_price = model_price(model, "completion").unwrap_or(1.0);
+            if prompt_price == 0.0 && completion_price == 0.0 {
+                free_models.push(id.to_string());
+                continue;
             }
         }
-        
-        // If no free models found, try a second pass with more relaxed criteria
-        if free_models.is_empty() {
-            // Look for models with very low pricing (<= 0.0001)
-            for model in data {
-                if let Some(id) = model["id"].as_str() {
-                    if let Some(pricing) = model["pricing"].as_object() {
-                        let prompt_price = pricing.get("prompt")
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(1.0);
-                        
-                        let completion_price = pricing.get("completion")
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(1.0);
-                        
-                        // Consider very low-priced models as "effectively free"
-                        if prompt_price <= 0.0001 && completion_price <= 0.0001 {
-                            free_models.push(id.to_string());
-                        }
-                    }
+    }
+    
+    // If no free models found, try a second pass with more relaxed criteria
+    if free_models.is_empty() {
+        // Look for models with very low pricing (<= 0.0001)
+        for model in catalogue {
+            if let Some(id) = model["id"].as_str() {
+                let prompt_price = model_price(model, "prompt").unwrap_or(1.0);
+                let completion_price = model_price(model, "completion").unwrap_or(1.0);
+
+                // Consider very low-priced models as "effectively free"
+                if prompt_price <= 0.0001 && completion_price <= 0.0001 {
+                    free_models.push(id.to_string());
                 }
             }
         }
     }
-    
-    // If we still found no free models, fall back to predefined list
+
+    free_models
+}
+
+/// `aicommit models update`: rebuild the preference list from the current catalogue and save it
+/// as the user's copy
+pub async fn update_model_list(config: &SimpleFreeOpenRouterConfig, refresh: bool, retry_policy: &RetryPolicy) -> Result<(), String> {
+    let api_key = resolve_secret(&config.api_key)?;
+    let catalogue = load_catalogue(Some(&api_key), refresh, retry_policy).await?;
+    let free_models = free_models_from_catalogue(&catalogue);
     if free_models.is_empty() {
-        println!("Warning: No free models found from OpenRouter API");
-        println!("Using predefined free models as fallback...");
-        return fallback_to_preferred_models();
+        return Err("OpenRouter returned no free models, keeping the current list".to_string());
     }
-    
-    Ok(free_models)
+
+    let current = ModelList::get();
+    let updated = current.rebuild(&catalogue, &free_models);
+    let path = updated.save_user()?;
+
+    let added = updated.preferred.iter().filter(|model| !current.preferred.contains(model)).count();
+    let removed = current.preferred.iter().filter(|model| !updated.preferred.contains(model)).count();
+    println!("Free model list updated: {} models ({} new, {} no longer free)", updated.preferred.len(), added, removed);
+    println!("Saved to {}", path.display());
+    Ok(())
 }
 
 // From: 036_function_fallback_to_preferred_models.rs
 pub fn fallback_to_preferred_models() -> Result<Vec<String>, String> {
-    let mut models = Vec::new();
-    
-    // Add all predefined free models
-    for model in PREFERRED_FREE_MODELS {
-        models.push(model.to_string());
-    }
+    // All predefined free models
+    let models = ModelList::get().preferred.clone();
     
     if models.is_empty() {
         return Err("No free models available, and fallback model list is empty".to_string());
@@ -154,47 +446,32 @@ pub fn fallback_to_preferred_models() -> Result<Vec<String>, String> {
 }
 
 // From: 037_function_find_best_available_model.rs
-pub fn find_best_available_model(available_models: &[String], config: &SimpleFreeOpenRouterConfig) -> Option<String> {
-    // Start by using the previously successful model if it's still available
-    if let Some(last_model) = &config.last_used_model {
-        if available_models.contains(last_model) {
-            let stats = config.model_stats.get(last_model);
-            if is_model_available(&stats) {
-                return Some(last_model.clone());
-            }
-        }
-    }
-
+pub fn find_best_available_model(available_models: &[String], config: &SimpleFreeOpenRouterConfig, policy: &JailPolicy) -> Option<String> {
     // Filter models that are not in jail or blacklisted
     let available_candidates: Vec<&String> = available_models
         .iter()
         .filter(|model| {
             let stats = config.model_stats.get(*model);
-            is_model_available(&stats)
+            is_model_available(&stats, policy)
         })
         .collect();
-    
-    // First try our curated list of preferred models in order
-    for preferred in PREFERRED_FREE_MODELS {
-        let preferred_str = preferred.to_string();
-        if available_candidates.contains(&&preferred_str) {
-            return Some(preferred_str);
-        }
+
+    // A pinned model wins whenever it can be used
+    if let Some(pinned) = available_candidates.iter().find(|model| config.model_stats.get(**model).is_some_and(|stats| stats.pinned)) {
+        return Some((*pinned).clone());
     }
-    
-    // If none of our preferred models are available, use an intelligent fallback approach
-    // by analyzing model names for parameter sizes (like 70b, 32b, etc.)
+
+    // Pick the highest score; ties go to the model listed first
     if !available_candidates.is_empty() {
-        // Sort by estimated parameter count (highest first)
-        let mut sorted_candidates = available_candidates.clone();
-        sorted_candidates.sort_by(|a, b| {
-            let a_size = extract_model_size(a);
-            let b_size = extract_model_size(b);
-            b_size.cmp(&a_size) // Reverse order (largest first)
-        });
-        
-        // Return the largest available model
-        return Some(sorted_candidates[0].clone());
+        let total_samples: usize = available_candidates.iter()
+            .map(|model| config.model_stats.get(*model).map_or(0, |stats| stats.recent.len()))
+            .sum();
+        let score = |model: &String| model_score(model, config.model_stats.get(model), total_samples);
+
+        return available_candidates.into_iter()
+            .rev()
+            .max_by(|a, b| score(a).total_cmp(&score(b)))
+            .cloned();
     }
     
     // If all models are jailed or blacklisted, try the least recently jailed one
@@ -204,8 +481,9 @@ pub fn find_best_available_model(available_models: &[String], config: &SimpleFre
         
         for model in available_models {
             if let Some(stats) = config.model_stats.get(model) {
-                if !stats.blacklisted && stats.jail_until.is_some() {
-                    jailed_models.push((model.clone(), stats.jail_until.unwrap()));
+                let until = stats.jail_until.max(stats.cooldown_until);
+                if !stats.blacklisted && until.is_some() {
+                    jailed_models.push((model.clone(), until.unwrap()));
                 }
             }
         }
@@ -216,8 +494,10 @@ pub fn find_best_available_model(available_models: &[String], config: &SimpleFre
             return Some(jailed_models[0].0.clone());
         }
         
-        // Last resort: just use any model, even blacklisted ones
-        return Some(available_models[0].clone());
+        // Last resort: just use any model, even blacklisted ones, but never one banned by hand
+        return available_models.iter()
+            .find(|model| !config.model_stats.get(*model).is_some_and(|stats| stats.banned))
+            .cloned();
     }
     
     None
@@ -225,49 +505,96 @@ pub fn find_best_available_model(available_models: &[String], config: &SimpleFre
 
 // From: 038_function_extract_model_size.rs
 pub fn extract_model_size(model_name: &str) -> u32 {
-    let lower_name = model_name.to_lowercase();
-    
-    // Look for patterns like "70b", "32b", "7b", etc.
-    let patterns = [
-        "253b", "235b", "200b", "124b",
-        "70b", "80b", "90b", "72b", "65b", 
-        "40b", "32b", "30b", "24b", "20b",
-        "16b", "14b", "13b", "12b", "11b", "10b",
-        "9b", "8b", "7b", "6b", "5b", "4b", "3b", "2b", "1b"
-    ];
-    
-    for pattern in patterns {
-        if lower_name.contains(pattern) {
-            // Extract the number from the pattern (e.g., "70b" -> 70)
-            if let Ok(size) = pattern.trim_end_matches(|c| c == 'b' || c == 'B').parse::<u32>() {
-                return size;
-            }
-        }
+    // Look for patterns like "70b", "32b", "7b" and keywords like "large" from the model list
+    ModelList::get().model_size(model_name)
+}
+
+/// What we expect from a model before measuring it: the preferred list order first, then the
+/// parameter count guessed from the name. Stays below what a fast, working model measures.
+pub fn prior_score(model: &str) -> f64 {
+    let preferred = &ModelList::get().preferred;
+    if let Some(position) = preferred.iter().position(|m| m == model) {
+        return 0.8 - 0.4 * position as f64 / preferred.len() as f64;
     }
-    
-    // Default size if no pattern matches
-    // Check for specific keywords that might indicate a more powerful model
-    if lower_name.contains("large") || lower_name.contains("ultra") {
-        return 15; // Assume it's a medium-large model
-    } else if lower_name.contains("medium") {
-        return 10;
-    } else if lower_name.contains("small") || lower_name.contains("tiny") {
-        return 5;
+    0.1 + 0.3 * extract_model_size(model).min(253) as f64 / 253.0
+}
+
+/// Selection score of a model: an exponentially weighted moving average of its recent requests
+/// (starting from the prior), plus a UCB exploration bonus for models with few samples.
+/// A failed request counts 0, a successful one its lint score scaled down by latency.
+pub fn model_score(model: &str, stats: Option<&ModelStats>, total_samples: usize) -> f64 {
+    let recent = stats.map_or(&[][..], |stats| &stats.recent[..]);
+
+    let mut score = prior_score(model);
+    for sample in recent {
+        let value = if sample.ok {
+            let latency_secs = sample.latency_ms.unwrap_or(0) as f64 / 1000.0;
+            sample.lint_score.unwrap_or(1.0) / (1.0 + latency_secs / LATENCY_SCALE_SECS)
+        } else {
+            0.0
+        };
+        score = (1.0 - SCORE_DECAY) * score + SCORE_DECAY * value;
+    }
+
+    let exploration = EXPLORATION * ((total_samples as f64 + 1.0).ln() / (recent.len() as f64 + 1.0)).sqrt();
+    score + exploration
+}
+
+/// Score of a generated message for model statistics: 1.0 without lint warnings, less for each one
+pub fn lint_score(issue_count: usize) -> f64 {
+    1.0 / (1.0 + issue_count as f64)
+}
+
+/// Latency percentile (0-100) of the recent successful requests, in milliseconds
+pub fn latency_percentile(stats: &ModelStats, percentile: f64) -> Option<u64> {
+    let mut latencies: Vec<u64> = stats.recent.iter().filter_map(|sample| sample.latency_ms).collect();
+    if latencies.is_empty() {
+        return None;
+    }
+    latencies.sort_unstable();
+    let rank = ((percentile / 100.0) * latencies.len() as f64).ceil() as usize;
+    Some(latencies[rank.clamp(1, latencies.len()) - 1])
+}
+
+/// Share of the recent requests that succeeded
+pub fn recent_success_rate(stats: &ModelStats) -> Option<f64> {
+    if stats.recent.is_empty() {
+        return None;
+    }
+    Some(stats.recent.iter().filter(|sample| sample.ok).count() as f64 / stats.recent.len() as f64)
+}
+
+/// Average lint score of the recent successful requests
+pub fn average_lint_score(stats: &ModelStats) -> Option<f64> {
+    let scores: Vec<f64> = stats.recent.iter().filter_map(|sample| sample.lint_score).collect();
+    if scores.is_empty() {
+        return None;
+    }
+    Some(scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+fn push_sample(model_stats: &mut ModelStats, sample: ModelSample) {
+    model_stats.recent.push(sample);
+    if model_stats.recent.len() > RECENT_SAMPLES {
+        let excess = model_stats.recent.len() - RECENT_SAMPLES;
+        model_stats.recent.drain(..excess);
     }
-    
-    // Default fallback
-    0
 }
 
 // From: 046_function_is_model_available.rs
-pub fn is_model_available(model_stats: &Option<&ModelStats>) -> bool {
+pub fn is_model_available(model_stats: &Option<&ModelStats>, policy: &JailPolicy) -> bool {
     match model_stats {
         None => true, // No stats yet, model is available
         Some(stats) => {
+            // Banned by hand: only `models unjail` or `models blacklist --remove` bring it back
+            if stats.banned {
+                return false;
+            }
+
             // Check if blacklisted but should be retried
             if stats.blacklisted {
                 if let Some(blacklisted_since) = stats.blacklisted_since {
-                    let retry_duration = chrono::Duration::days(BLACKLIST_RETRY_DAYS);
+                    let retry_duration = chrono::Duration::days(policy.blacklist_retry_days);
                     let now = chrono::Utc::now();
                     
                     // If blacklisted for more than retry period, give it another chance
@@ -279,6 +606,10 @@ pub fn is_model_available(model_stats: &Option<&ModelStats>) -> bool {
                 return false;
             }
             
+            if stats.cooldown_until.is_some_and(|until| chrono::Utc::now() < until) {
+                return false;
+            }
+
             // Check if currently in jail
             if let Some(jail_until) = stats.jail_until {
                 if chrono::Utc::now() < jail_until {
@@ -292,81 +623,99 @@ pub fn is_model_available(model_stats: &Option<&ModelStats>) -> bool {
 }
 
 // From: 047_function_record_model_success.rs
-pub fn record_model_success(model_stats: &mut ModelStats) {
+pub fn record_model_success(model_stats: &mut ModelStats, latency: std::time::Duration, lint_score: f64) {
     model_stats.success_count += 1;
     model_stats.last_success = Some(chrono::Utc::now());
+    push_sample(model_stats, ModelSample {
+        at: model_stats.last_success,
+        ok: true,
+        latency_ms: Some(latency.as_millis() as u64),
+        lint_score: Some(lint_score),
+    });
     
     // Reset consecutive failures if successful
     if model_stats.last_failure.is_none() || 
        model_stats.last_success.unwrap() > model_stats.last_failure.unwrap() {
         // The model is working now, remove any jail time
         model_stats.jail_until = None;
+        model_stats.cooldown_until = None;
     }
 }
 
 // From: 048_function_record_model_failure.rs
-pub fn record_model_failure(model_stats: &mut ModelStats) {
+pub fn record_model_failure(model_stats: &mut ModelStats, kind: FailureKind, policy: &JailPolicy) {
     let now = chrono::Utc::now();
+    let cooldown = match kind {
+        FailureKind::Fatal | FailureKind::Auth | FailureKind::Transport => return,
+        FailureKind::RateLimit { retry_after } => Some(retry_after
+            .and_then(|delay| chrono::Duration::from_std(delay).ok())
+            .unwrap_or_else(|| chrono::Duration::minutes(policy.rate_limit_minutes))
+            .min(chrono::Duration::hours(policy.max_hours))),
+        FailureKind::Outage => Some(chrono::Duration::minutes(policy.outage_minutes)),
+        FailureKind::ContentFilter => Some(chrono::Duration::minutes(policy.content_filter_minutes)),
+        FailureKind::BadOutput => None,
+    };
     model_stats.failure_count += 1;
     model_stats.last_failure = Some(now);
-    
-    // Check if we have consecutive failures
-    let has_consecutive_failures = match model_stats.last_success {
-        None => true, // Never had a success
-        Some(last_success) => {
-            // If last success is older than last failure, we have consecutive failures
-            model_stats.last_failure.unwrap() > last_success
-        }
-    };
-    
-    if has_consecutive_failures {
-        // Count consecutive failures by comparing timestamps
-        let consecutive_failures = if let Some(last_success) = model_stats.last_success {
-            let hours_since_success = (now - last_success).num_hours();
-            // If it's been more than a day since last success, count as consecutive failures
-            if hours_since_success > 24 {
-                model_stats.failure_count.min(MAX_CONSECUTIVE_FAILURES)
-            } else {
-                // Count failures since last success
-                1 // This is at least 1 consecutive failure
-            }
-        } else {
-            // No success ever, count all failures
-            model_stats.failure_count.min(MAX_CONSECUTIVE_FAILURES)
-        };
-        
-        // Jail if we hit the threshold
-        if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
-            // Calculate jail duration based on recidivism
-            let jail_hours = INITIAL_JAIL_HOURS * JAIL_TIME_MULTIPLIER.pow(model_stats.jail_count as u32);
-            let jail_hours = jail_hours.min(MAX_JAIL_HOURS); // Cap at maximum
-            
-            // Set jail expiration time
-            model_stats.jail_until = Some(now + chrono::Duration::hours(jail_hours));
-            model_stats.jail_count += 1;
-            
-            // Blacklist if consistently problematic
-            if model_stats.jail_count >= BLACKLIST_AFTER_JAIL_COUNT {
-                model_stats.blacklisted = true;
-                model_stats.blacklisted_since = Some(now);
-            }
+    model_stats.last_failure_kind = Some(kind.label().to_string());
+
+    if let Some(cooldown) = cooldown {
+        model_stats.cooldown_until = Some(now + cooldown);
+        return;
+    }
+
+    push_sample(model_stats, ModelSample { at: Some(now), ok: false, latency_ms: None, lint_score: None });
+
+    // Failures since the last success among the recent requests
+    let consecutive_failures = model_stats.recent.iter().rev().take_while(|sample| !sample.ok).count();
+
+    // Jail if we hit the threshold
+    if consecutive_failures >= policy.max_consecutive_failures {
+        // Calculate jail duration based on recidivism
+        let jail_hours = policy.initial_hours.saturating_mul(policy.multiplier.saturating_pow(model_stats.jail_count as u32));
+        let jail_hours = jail_hours.min(policy.max_hours); // Cap at maximum
+
+        // Set jail expiration time
+        model_stats.jail_until = Some(now + chrono::Duration::hours(jail_hours));
+        model_stats.jail_count += 1;
+
+        // Blacklist if consistently problematic
+        if model_stats.jail_count >= policy.blacklist_after {
+            model_stats.blacklisted = true;
+            model_stats.blacklisted_since = Some(now);
         }
     }
 }
 
+/// Status label of a model: BANNED, BLACKLISTED, JAILED, COOLDOWN, PINNED or ACTIVE
+pub fn model_status_label(stats: &ModelStats) -> &'static str {
+    if stats.banned {
+        "BANNED"
+    } else if stats.blacklisted {
+        "BLACKLISTED"
+    } else if stats.jail_until.is_some_and(|until| chrono::Utc::now() < until) {
+        "JAILED"
+    } else if stats.cooldown_until.is_some_and(|until| chrono::Utc::now() < until) {
+        "COOLDOWN"
+    } else if stats.pinned {
+        "PINNED"
+    } else {
+        "ACTIVE"
+    }
+}
+
 // From: 049_function_format_model_status.rs
 pub fn format_model_status(model: &str, stats: &ModelStats) -> String {
-    let status = if stats.blacklisted {
-        "BLACKLISTED".to_string()
-    } else if let Some(jail_until) = stats.jail_until {
-        if chrono::Utc::now() < jail_until {
+    let status = match (model_status_label(stats), stats.jail_until) {
+        ("JAILED", Some(jail_until)) => {
             let remaining = jail_until - chrono::Utc::now();
             format!("JAILED ({}h remaining)", remaining.num_hours())
-        } else {
-            "ACTIVE".to_string()
         }
-    } else {
-        "ACTIVE".to_string()
+        ("COOLDOWN", _) => {
+            let remaining = stats.cooldown_until.map_or(0, |until| (until - chrono::Utc::now()).num_minutes());
+            format!("COOLDOWN ({}m remaining)", remaining)
+        }
+        (label, _) => label.to_string(),
     };
     
     let last_success = stats.last_success.map_or("Never".to_string(), |ts| {
@@ -380,7 +729,7 @@ pub fn format_model_status(model: &str, stats: &ModelStats) -> String {
         }
     });
     
-    let last_failure = stats.last_failure.map_or("Never".to_string(), |ts| {
+    let mut last_failure = stats.last_failure.map_or("Never".to_string(), |ts| {
         let ago = chrono::Utc::now() - ts;
         if ago.num_days() > 0 {
             format!("{} days ago", ago.num_days())
@@ -390,9 +739,24 @@ pub fn format_model_status(model: &str, stats: &ModelStats) -> String {
             format!("{} minutes ago", ago.num_minutes())
         }
     });
+    if let (Some(_), Some(kind)) = (stats.last_failure, &stats.last_failure_kind) {
+        last_failure = format!("{} ({})", last_failure, kind);
+    }
     
-    format!("{}: {} (Success: {}, Failure: {}, Last success: {}, Last failure: {})",
-            model, status, stats.success_count, stats.failure_count, last_success, last_failure)
+    let mut measured = Vec::new();
+    if let (Some(p50), Some(p90)) = (latency_percentile(stats, 50.0), latency_percentile(stats, 90.0)) {
+        measured.push(format!("Latency p50/p90: {:.1}s/{:.1}s", p50 as f64 / 1000.0, p90 as f64 / 1000.0));
+    }
+    if let Some(rate) = recent_success_rate(stats) {
+        measured.push(format!("Recent success: {:.0}% of {}", rate * 100.0, stats.recent.len()));
+    }
+    if let Some(lint) = average_lint_score(stats) {
+        measured.push(format!("Lint score: {:.2}", lint));
+    }
+    measured.push(format!("Score: {:.2}", model_score(model, Some(stats), stats.recent.len())));
+
+    format!("{}: {} (Success: {}, Failure: {}, Last success: {}, Last failure: {}, {})",
+            model, status, stats.success_count, stats.failure_count, last_success, last_failure, measured.join(", "))
 }
 
 // From: 050_function_display_model_jail_status.rs
@@ -406,101 +770,155 @@ pub fn display_model_jail_status(config: &SimpleFreeOpenRouterConfig) -> Result<
     println!("===================");
     
     // Group models by status
-    let mut active_models = Vec::new();
-    let mut jailed_models = Vec::new();
-    let mut blacklisted_models = Vec::new();
-    
-    for (model, stats) in &config.model_stats {
-        if stats.blacklisted {
-            blacklisted_models.push(format_model_status(model, stats));
-        } else if let Some(jail_until) = stats.jail_until {
-            if chrono::Utc::now() < jail_until {
-                jailed_models.push(format_model_status(model, stats));
-            } else {
-                active_models.push(format_model_status(model, stats));
+    for (label, heading) in [("PINNED", "PINNED MODELS"), ("ACTIVE", "ACTIVE MODELS"), ("COOLDOWN", "COOLING DOWN"), ("JAILED", "JAILED MODELS"),
+                             ("BLACKLISTED", "BLACKLISTED MODELS"), ("BANNED", "BANNED MODELS")] {
+        let mut models: Vec<String> = config.model_stats.iter()
+            .filter(|(_, stats)| model_status_label(stats) == label)
+            .map(|(model, stats)| format_model_status(model, stats))
+            .collect();
+        if !models.is_empty() {
+            println!("\n{}:", heading);
+            models.sort();
+            for model in models {
+                println!("  {}", model);
             }
-        } else {
-            active_models.push(format_model_status(model, stats));
         }
     }
     
-    // Sort and display each group
-    if !active_models.is_empty() {
-        println!("\nACTIVE MODELS:");
-        active_models.sort();
-        for model in active_models {
-            println!("  {}", model);
-        }
+    Ok(())
+}
+
+/// `models status --json`: one object per model with its jail state and measured statistics
+pub fn model_status_json(config: &SimpleFreeOpenRouterConfig) -> serde_json::Value {
+    let total_samples: usize = config.model_stats.values().map(|stats| stats.recent.len()).sum();
+    let mut models: Vec<(&String, &ModelStats)> = config.model_stats.iter().collect();
+    models.sort_by_key(|(model, _)| *model);
+
+    let models: Vec<serde_json::Value> = models.into_iter().map(|(model, stats)| serde_json::json!({
+        "model": model,
+        "status": model_status_label(stats),
+        "success_count": stats.success_count,
+        "failure_count": stats.failure_count,
+        "last_success": stats.last_success.map(|ts| ts.to_rfc3339()),
+        "last_failure": stats.last_failure.map(|ts| ts.to_rfc3339()),
+        "jail_until": stats.jail_until.map(|ts| ts.to_rfc3339()),
+        "jail_count": stats.jail_count,
+        "cooldown_until": stats.cooldown_until.map(|ts| ts.to_rfc3339()),
+        "last_failure_kind": stats.last_failure_kind,
+        "latency_p50_ms": latency_percentile(stats, 50.0),
+        "latency_p90_ms": latency_percentile(stats, 90.0),
+        "recent_success_rate": recent_success_rate(stats),
+        "lint_score": average_lint_score(stats),
+        "score": model_score(model, Some(stats), total_samples),
+    })).collect();
+
+    serde_json::json!({ "provider": config.id, "models": models })
+}
+
+/// `models history`: the recent requests of one model (or all), oldest first
+pub fn display_model_history(config: &SimpleFreeOpenRouterConfig, model: Option<&str>, json: bool) -> Result<(), String> {
+    let mut models: Vec<(&String, &ModelStats)> = config.model_stats.iter()
+        .filter(|(id, _)| model.is_none_or(|model| *id == model))
+        .collect();
+    models.sort_by_key(|(id, _)| *id);
+    if let (Some(model), true) = (model, models.is_empty()) {
+        return Err(format!("Model '{}' not found in statistics", model));
     }
-    
-    if !jailed_models.is_empty() {
-        println!("\nJAILED MODELS:");
-        jailed_models.sort();
-        for model in jailed_models {
-            println!("  {}", model);
-        }
+
+    if json {
+        let history: serde_json::Map<String, serde_json::Value> = models.iter()
+            .map(|(id, stats)| (id.to_string(), serde_json::to_value(&stats.recent).unwrap_or_default()))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&history).map_err(|e| format!("Failed to serialize history: {}", e))?);
+        return Ok(());
     }
-    
-    if !blacklisted_models.is_empty() {
-        println!("\nBLACKLISTED MODELS:");
-        blacklisted_models.sort();
-        for model in blacklisted_models {
-            println!("  {}", model);
+
+    for (id, stats) in models {
+        println!("{} ({}):", id, model_status_label(stats));
+        if stats.recent.is_empty() {
+            println!("  no recorded requests");
+        }
+        for sample in &stats.recent {
+            let at = sample.at.map_or("unknown time    ".to_string(), |at| at.format("%Y-%m-%d %H:%M").to_string());
+            if sample.ok {
+                let latency = sample.latency_ms.map_or("?".to_string(), |ms| format!("{:.1}s", ms as f64 / 1000.0));
+                let lint = sample.lint_score.map_or("?".to_string(), |score| format!("{:.2}", score));
+                println!("  {}  ok      latency {:>6}  lint {}", at, latency, lint);
+            } else {
+                println!("  {}  failed", at);
+            }
         }
     }
-    
     Ok(())
 }
 
+/// `models jail`: keep a model out of rotation for `duration` (the policy's first jail term by default)
+pub fn jail_model(config: &mut SimpleFreeOpenRouterConfig, model_id: &str, duration: Option<std::time::Duration>, policy: &JailPolicy) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let duration = match duration {
+        Some(duration) => chrono::Duration::from_std(duration).map_err(|e| format!("Invalid jail duration: {}", e))?,
+        None => chrono::Duration::hours(policy.initial_hours),
+    };
+    let until = chrono::Utc::now() + duration;
+    update_provider_state(config, |state| {
+        state.model_stats.entry(model_id.to_string()).or_default().jail_until = Some(until);
+    })?;
+    Ok(until)
+}
+
+/// `models blacklist`: ban a model by hand, or lift the ban
+pub fn ban_model(config: &mut SimpleFreeOpenRouterConfig, model_id: &str, banned: bool) -> Result<(), String> {
+    update_provider_state(config, |state| {
+        let stats = state.model_stats.entry(model_id.to_string()).or_default();
+        stats.banned = banned;
+        if banned {
+            stats.pinned = false;
+        }
+    })
+}
+
+/// `models pin`: pin one model (unpinning any other), or clear the pin with `None`
+pub fn pin_model(config: &mut SimpleFreeOpenRouterConfig, model_id: Option<&str>) -> Result<(), String> {
+    update_provider_state(config, |state| {
+        for stats in state.model_stats.values_mut() {
+            stats.pinned = false;
+        }
+        if let Some(model_id) = model_id {
+            state.model_stats.entry(model_id.to_string()).or_default().pinned = true;
+        }
+    })
+}
+
 // From: 051_function_unjail_model.rs
 pub fn unjail_model(config: &mut SimpleFreeOpenRouterConfig, model_id: &str) -> Result<(), String> {
-    let model_found = if model_id == "*" {
-        // Reset all models
-        for (_, stats) in config.model_stats.iter_mut() {
-            stats.jail_until = None;
-            stats.blacklisted = false;
-            stats.jail_count = 0;
-        }
-        true
-    } else {
-        // Reset specific model
-        if let Some(stats) = config.model_stats.get_mut(model_id) {
+    // Reset against the latest state on disk so statistics recorded by other processes are kept
+    let model_found = update_provider_state(config, |state| {
+        if model_id == "*" {
+            // Reset all models
+            for (_, stats) in state.model_stats.iter_mut() {
+                stats.jail_until = None;
+                stats.cooldown_until = None;
+                stats.blacklisted = false;
+                stats.banned = false;
+                stats.jail_count = 0;
+            }
+            true
+        } else if let Some(stats) = state.model_stats.get_mut(model_id) {
+            // Reset specific model
             stats.jail_until = None;
+            stats.cooldown_until = None;
             stats.blacklisted = false;
+            stats.banned = false;
             stats.jail_count = 0;
             true
         } else {
             false
         }
-    };
+    })?;
     
     if !model_found {
         return Err(format!("Model '{}' not found in statistics", model_id));
     }
     
-    // Save updated config
-    let config_path = dirs::home_dir()
-        .ok_or_else(|| "Could not find home directory".to_string())?
-        .join(".aicommit.json");
-        
-    let mut full_config = Config::load()?;
-    
-    // Update the provider in the full config
-    for provider in &mut full_config.providers {
-        if let ProviderConfig::SimpleFreeOpenRouter(simple_config) = provider {
-            if simple_config.id == config.id {
-                *simple_config = config.clone();
-                break;
-            }
-        }
-    }
-    
-    let content = serde_json::to_string_pretty(&full_config)
-        .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        
-    fs::write(&config_path, content)
-        .map_err(|e| format!("Failed to write config file: {}", e))?;
-    
     Ok(())
 }
 
@@ -509,3 +927,163 @@ pub fn unjail_all_models(config: &mut SimpleFreeOpenRouterConfig) -> Result<(),
     unjail_model(config, "*")
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config_with_stats(stats: Vec<(&str, ModelStats)>) -> SimpleFreeOpenRouterConfig {
+        SimpleFreeOpenRouterConfig {
+            id: "free".to_string(),
+            provider: "simple_free_openrouter".to_string(),
+            api_key: "key".to_string(),
+            max_tokens: 200,
+            temperature: 0.2,
+            model_stats: stats.into_iter().map(|(model, stats)| (model.to_string(), stats)).collect(),
+            last_used_model: None,
+            last_config_update: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_measured_model_beats_failing_preferred_model() {
+        let preferred = ModelList::get().preferred[0].as_str();
+        let other = "someone/small-7b:free";
+        let available = vec![preferred.to_string(), other.to_string()];
+
+        // Without measurements the preferred list decides
+        let config = config_with_stats(vec![]);
+        assert_eq!(find_best_available_model(&available, &config, &JailPolicy::default()).as_deref(), Some(preferred));
+
+        // Recent failures (not yet jailed) let a model that actually works take over
+        let mut failing = ModelStats::default();
+        let mut working = ModelStats::default();
+        for _ in 0..2 {
+            failing.recent.push(ModelSample { at: None, ok: false, latency_ms: None, lint_score: None });
+        }
+        for _ in 0..5 {
+            record_model_success(&mut working, Duration::from_millis(1500), lint_score(0));
+        }
+        let config = config_with_stats(vec![(preferred, failing), (other, working)]);
+        assert_eq!(find_best_available_model(&available, &config, &JailPolicy::default()).as_deref(), Some(other));
+    }
+
+    #[test]
+    fn test_sample_statistics() {
+        let mut stats = ModelStats::default();
+        for latency in 1..=RECENT_SAMPLES as u64 + 5 {
+            record_model_success(&mut stats, Duration::from_millis(latency * 100), lint_score(1));
+        }
+        record_model_failure(&mut stats, FailureKind::BadOutput, &JailPolicy::default());
+
+        assert_eq!(stats.recent.len(), RECENT_SAMPLES);
+        assert_eq!(latency_percentile(&stats, 50.0), Some(1600));
+        assert_eq!(latency_percentile(&stats, 100.0), Some(2500));
+        assert_eq!(recent_success_rate(&stats), Some(19.0 / 20.0));
+        assert_eq!(average_lint_score(&stats), Some(0.5));
+    }
+
+    #[test]
+    fn test_jail_policy_counts_consecutive_failures() {
+        let policy = JailPolicy { max_consecutive_failures: 2, initial_hours: 1, ..JailPolicy::default() };
+        let mut stats = ModelStats::default();
+
+        record_model_failure(&mut stats, FailureKind::BadOutput, &policy);
+        record_model_success(&mut stats, Duration::from_secs(1), 1.0);
+        record_model_failure(&mut stats, FailureKind::BadOutput, &policy);
+        assert!(stats.jail_until.is_none());
+
+        record_model_failure(&mut stats, FailureKind::BadOutput, &policy);
+        let remaining = stats.jail_until.unwrap() - chrono::Utc::now();
+        assert!(remaining <= chrono::Duration::hours(1) && remaining > chrono::Duration::minutes(59));
+    }
+
+    #[test]
+    fn test_pinned_and_banned_models() {
+        let available = vec!["a/first:free".to_string(), "b/second:free".to_string()];
+        let pinned = ModelStats { pinned: true, ..ModelStats::default() };
+        let banned = ModelStats { banned: true, ..ModelStats::default() };
+        let policy = JailPolicy::default();
+
+        let config = config_with_stats(vec![("b/second:free", pinned)]);
+        assert_eq!(find_best_available_model(&available, &config, &policy).as_deref(), Some("b/second:free"));
+
+        let config = config_with_stats(vec![("a/first:free", banned.clone()), ("b/second:free", banned)]);
+        assert_eq!(find_best_available_model(&available, &config, &policy), None);
+    }
+
+    #[test]
+    fn test_failure_kinds_have_their_own_policy() {
+        let policy = JailPolicy { max_consecutive_failures: 1, ..JailPolicy::default() };
+        let mut stats = ModelStats::default();
+
+        let rate_limit = FailureKind::from_status(429, "", Some(Duration::from_secs(90)));
+        assert_eq!(rate_limit, FailureKind::RateLimit { retry_after: Some(Duration::from_secs(90)) });
+        record_model_failure(&mut stats, rate_limit, &policy);
+        let cooldown = stats.cooldown_until.unwrap() - chrono::Utc::now();
+        assert!(cooldown <= chrono::Duration::seconds(90) && cooldown > chrono::Duration::seconds(80));
+        assert!(stats.jail_until.is_none() && stats.recent.is_empty());
+        assert_eq!(model_status_label(&stats), "COOLDOWN");
+        assert!(!is_model_available(&Some(&stats), &policy));
+
+        record_model_failure(&mut stats, FailureKind::Transport, &policy);
+        record_model_failure(&mut stats, FailureKind::Auth, &policy);
+        assert_eq!(stats.failure_count, 1);
+
+        record_model_failure(&mut stats, FailureKind::BadOutput, &policy);
+        assert!(stats.jail_until.is_some());
+    }
+
+    #[test]
+    fn test_failover_steps() {
+        let models = vec!["a/cooling:free".to_string(), "b/jailed:free".to_string(), "c/good:free".to_string(), "d/other:free".to_string()];
+        let later = chrono::Utc::now() + chrono::Duration::hours(1);
+        let config = config_with_stats(vec![
+            ("a/cooling:free", ModelStats { cooldown_until: Some(later), ..ModelStats::default() }),
+            ("b/jailed:free", ModelStats { jail_until: Some(later), ..ModelStats::default() }),
+        ]);
+        let jail = JailPolicy::default();
+        let policy = FailoverPolicy { max_models: 3, hedge_after: Some(Duration::from_secs(10)), deadline: Duration::from_secs(60) };
+        let started = tokio::time::Instant::now();
+        let mut run = FailoverRun::new(policy, started);
+
+        // Cooled down and jailed models are skipped
+        assert_eq!(run.next_step(started, &models, &config, &jail), FailoverStep::Launch("c/good:free".to_string()));
+        run.running.push("c/good:free".to_string());
+
+        // A slow model gets the next best one raced against it once hedge_after has passed
+        let hedge_at = started + Duration::from_secs(10);
+        assert_eq!(run.next_step(started + Duration::from_secs(1), &models, &config, &jail), FailoverStep::Wait(hedge_at));
+        assert_eq!(run.next_step(hedge_at, &models, &config, &jail), FailoverStep::Launch("d/other:free".to_string()));
+        run.running.push("d/other:free".to_string());
+        run.last_launch = hedge_at;
+        assert_eq!(run.next_step(hedge_at, &models, &config, &jail), FailoverStep::Wait(run.deadline));
+
+        // Nothing is launched past the deadline or past max_models
+        assert_eq!(run.next_step(run.deadline, &models, &config, &jail), FailoverStep::Stop);
+        run.running.clear();
+        run.tried = vec![("c/good:free".to_string(), "bad output".to_string()), ("d/other:free".to_string(), "outage".to_string())];
+        assert_eq!(run.next_step(hedge_at, &models, &config, &jail), FailoverStep::Launch("a/cooling:free".to_string()));
+        run.tried.push(("a/cooling:free".to_string(), "outage".to_string()));
+        assert_eq!(run.next_step(hedge_at, &models, &config, &jail), FailoverStep::Stop);
+    }
+
+    #[test]
+    fn test_classify_failures() {
+        assert_eq!(FailureKind::from_status(503, "", None), FailureKind::Outage);
+        assert_eq!(FailureKind::from_status(403, r#"{"error":{"message":"Input was flagged by moderation"}}"#, None), FailureKind::ContentFilter);
+        assert_eq!(FailureKind::from_error_message("API request failed: 401 Unauthorized"), FailureKind::Auth);
+        assert_eq!(FailureKind::from_error_message(r#"API error response: {"error":{"code": 502}}"#), FailureKind::Outage);
+        assert_eq!(FailureKind::from_error_message("Request timed out after 30 seconds"), FailureKind::Transport);
+        assert_eq!(FailureKind::from_error_message("Model x is rate limited, retry after 12s"),
+                   FailureKind::RateLimit { retry_after: Some(Duration::from_secs(12)) });
+        assert_eq!(FailureKind::from_error_message("Generated commit message is too short or empty"), FailureKind::BadOutput);
+
+        let now = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(parse_retry_after(Some("30"), None, now), Some(Duration::from_secs(30)));
+        let reset = (now + chrono::Duration::seconds(45)).timestamp_millis().to_string();
+        assert_eq!(parse_retry_after(None, Some(&reset), now), Some(Duration::from_secs(45)));
+        assert_eq!(parse_retry_after(Some("soon"), None, now), None);
+    }
+}
@@ -0,0 +1,344 @@
+// Commit convention presets - the prompt rules and examples, lint checks and post-processing
+// of each supported convention, selected per repository with `convention.preset`
+
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::prompt::PromptStyle;
+use crate::settings::{SettingOrigin, Settings};
+use crate::style::Convention;
+
+/// Conventions with built-in support
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    /// `feat(api): add login`, any lowercase type
+    Conventional,
+    /// `feat(router): add lazy routes`, Angular's types, lowercase, no period
+    Angular,
+    /// `feat(config): allow a custom port`, Karma's types, lowercase, no period
+    Karma,
+    /// `:sparkles: Add login`
+    Gitmoji,
+    /// `net: fix use-after-free in tcp_close()`
+    Kernel,
+    /// `Add login`
+    Freeform,
+}
+
+const ANGULAR_TYPES: &[&str] = &["build", "ci", "docs", "feat", "fix", "perf", "refactor", "test"];
+const KARMA_TYPES: &[&str] = &["feat", "fix", "docs", "style", "refactor", "perf", "test", "chore"];
+
+/// Conventional Commits type to gitmoji
+const GITMOJI: &[(&str, &str)] = &[
+    ("feat", ":sparkles:"),
+    ("fix", ":bug:"),
+    ("docs", ":memo:"),
+    ("style", ":art:"),
+    ("refactor", ":recycle:"),
+    ("perf", ":zap:"),
+    ("test", ":white_check_mark:"),
+    ("build", ":package:"),
+    ("ci", ":construction_worker:"),
+    ("chore", ":wrench:"),
+    ("revert", ":rewind:"),
+];
+
+impl Preset {
+    pub const ALL: [Preset; 6] = [Preset::Conventional, Preset::Angular, Preset::Karma, Preset::Gitmoji, Preset::Kernel, Preset::Freeform];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Conventional => "conventional",
+            Preset::Angular => "angular",
+            Preset::Karma => "karma",
+            Preset::Gitmoji => "gitmoji",
+            Preset::Kernel => "kernel",
+            Preset::Freeform => "freeform",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "linux" => Some(Preset::Kernel),
+            name => Self::ALL.into_iter().find(|preset| preset.name() == name),
+        }
+    }
+
+    /// Subjects follow `type(scope): description`
+    pub fn is_conventional(self) -> bool {
+        matches!(self, Preset::Conventional | Preset::Angular | Preset::Karma)
+    }
+
+    /// Types the convention allows; None allows any
+    pub fn types(self) -> Option<&'static [&'static str]> {
+        match self {
+            Preset::Angular => Some(ANGULAR_TYPES),
+            Preset::Karma => Some(KARMA_TYPES),
+            _ => None,
+        }
+    }
+
+    pub fn max_subject_length(self) -> usize {
+        match self {
+            Preset::Angular => 100,
+            Preset::Karma => 70,
+            Preset::Kernel => 75,
+            _ => 72,
+        }
+    }
+
+    fn rule(self, style: PromptStyle) -> &'static str {
+        match (self, style) {
+            (Preset::Conventional, PromptStyle::Detailed) => "the Conventional Commits specification (type: description)",
+            (Preset::Conventional, PromptStyle::Compact) => "Conventional Commits (type: description)",
+            (Preset::Angular, _) => "the Angular commit convention (type(scope): subject, type one of build, ci, docs, feat, fix, perf, refactor, test; the subject lowercase, imperative, without a period)",
+            (Preset::Karma, _) => "the Karma commit convention (type(scope): subject, type one of feat, fix, docs, style, refactor, perf, test, chore; the subject lowercase, imperative, without a period)",
+            (Preset::Gitmoji, _) => "the gitmoji convention (a gitmoji code such as :sparkles:, :bug: or :memo:, then a capitalized description)",
+            (Preset::Kernel, _) => "the Linux kernel style (subsystem: summary, the subsystem or path prefix of the change, then a lowercase imperative summary without a period)",
+            (Preset::Freeform, _) => "a plain imperative subject starting with a capital letter, without a type prefix or a period at the end",
+        }
+    }
+
+    fn examples(self, style: PromptStyle) -> &'static [&'static str] {
+        match (self, style) {
+            (Preset::Conventional, PromptStyle::Detailed) => &[
+                "feat: Add user authentication feature",
+                "fix: Correct calculation error in payment module",
+                "docs: Update README with installation instructions",
+                "style: Format code according to style guide",
+                "refactor: Simplify database query logic",
+                "test: Add unit tests for user service",
+                "chore: Update dependencies",
+            ],
+            (Preset::Conventional, PromptStyle::Compact) => &[
+                "feat: add user login",
+                "fix: correct payment calculation",
+                "docs: update readme",
+                "style: format code",
+                "refactor: simplify query",
+                "test: add user tests",
+                "chore: update deps",
+            ],
+            (Preset::Angular, _) => &[
+                "feat(router): add support for lazy-loaded routes",
+                "fix(forms): reset validity when a control is disabled",
+                "docs: update the installation guide",
+                "perf(core): skip rendering unchanged lists",
+                "refactor(http): simplify the interceptor chain",
+                "test(auth): cover token refresh",
+                "build: update dependencies",
+            ],
+            (Preset::Karma, _) => &[
+                "feat(config): allow a custom port",
+                "fix(watcher): ignore editor swap files",
+                "docs: describe the reporters option",
+                "style(launcher): format the browser launcher",
+                "refactor(server): extract the file list",
+                "test: add runner tests",
+                "chore: update dependencies",
+            ],
+            (Preset::Gitmoji, _) => &[
+                ":sparkles: Add user authentication",
+                ":bug: Fix calculation error in payment module",
+                ":memo: Update README with installation instructions",
+                ":art: Format code according to style guide",
+                ":recycle: Simplify database query logic",
+                ":white_check_mark: Add unit tests for user service",
+                ":arrow_up: Update dependencies",
+            ],
+            (Preset::Kernel, _) => &[
+                "net: fix use-after-free in tcp_close()",
+                "mm/slab: remove unused cache flags",
+                "Documentation: update the build instructions",
+                "drm/i915: add support for the new display engine",
+                "ext4: simplify extent lookup",
+                "selftests/bpf: add a map iteration test",
+            ],
+            (Preset::Freeform, _) => &[
+                "Add user authentication",
+                "Fix calculation error in payment module",
+                "Update README with installation instructions",
+                "Simplify database query logic",
+                "Add unit tests for user service",
+                "Update dependencies",
+            ],
+        }
+    }
+
+    /// The convention part of the prompt
+    pub fn instructions(self, style: PromptStyle) -> String {
+        let mut instructions = match style {
+            PromptStyle::Detailed => format!("Generate ONLY the git commit message string based on the provided diff. Follow {}. Do NOT include any introductory phrases, explanations, or markdown formatting like ```.", self.rule(style)),
+            PromptStyle::Compact => format!("Generate ONLY the raw git commit message string (one line, max {} chars) based on the diff. Follow {}. Do NOT include any introductory text, explanations, or ```.", self.max_subject_length(), self.rule(style)),
+        };
+        instructions.push_str("\nExamples:");
+        for example in self.examples(style) {
+            instructions.push_str(&format!("\n- {}", example));
+        }
+        instructions
+    }
+
+    /// Problems with a subject beyond the Conventional Commits syntax, which `lint.conventional` checks
+    pub fn lint(self, subject: &str) -> Vec<String> {
+        let mut issues = Vec::new();
+        match (self, conventional_subject(subject)) {
+            (Preset::Angular | Preset::Karma, Some(parts)) => {
+                let types = self.types().unwrap();
+                if !types.contains(&parts.kind) {
+                    issues.push(format!("type '{}' is not a {} type ({})", parts.kind, self.name(), types.join(", ")));
+                }
+                if parts.description.starts_with(char::is_uppercase) || parts.description.ends_with('.') {
+                    issues.push(format!("{} subjects start with a lowercase letter and don't end with a period", self.name()));
+                }
+            }
+            (Preset::Gitmoji, _) if !Convention::Gitmoji.pattern().unwrap().is_match(subject) => {
+                issues.push("subject does not start with a gitmoji (:sparkles: Description)".to_string());
+            }
+            (Preset::Kernel, _) => {
+                static KERNEL: OnceLock<Regex> = OnceLock::new();
+                if !KERNEL.get_or_init(|| Regex::new(r"^[\w./-]+(?:: [\w./-]+)*: \S").unwrap()).is_match(subject) {
+                    issues.push("subject does not follow the Linux kernel style (subsystem: summary)".to_string());
+                }
+                if subject.ends_with('.') {
+                    issues.push("subject ends with a period".to_string());
+                }
+            }
+            (Preset::Freeform, parts) => {
+                if parts.is_some() {
+                    issues.push("freeform subjects don't start with a type prefix".to_string());
+                }
+                if !subject.starts_with(char::is_uppercase) {
+                    issues.push("subject does not start with a capital letter".to_string());
+                }
+            }
+            _ => {}
+        }
+        issues
+    }
+
+    /// Bring a Conventional Commits subject the model produced anyway into the preset's form,
+    /// e.g. `feat: add login` to `:sparkles: Add login` for gitmoji. The body is kept as it is.
+    pub fn post_process(self, message: &str) -> String {
+        let (subject, body) = message.split_once('\n').map_or((message, None), |(subject, body)| (subject, Some(body)));
+        let Some(parts) = conventional_subject(subject.trim()) else {
+            return message.to_string();
+        };
+        let scope = parts.scope.map(|scope| format!("({})", scope)).unwrap_or_default();
+        let description = parts.description.trim_end_matches('.');
+
+        let subject = match self {
+            Preset::Conventional => return message.to_string(),
+            Preset::Angular | Preset::Karma => {
+                let renamed = [("chore", "build"), ("style", "refactor"), ("build", "chore"), ("ci", "chore")];
+                let kind = if self.types().unwrap().contains(&parts.kind) {
+                    parts.kind
+                } else {
+                    renamed.iter().find(|(from, to)| *from == parts.kind && self.types().unwrap().contains(to)).map_or(parts.kind, |(_, to)| to)
+                };
+                format!("{}{}{}: {}", kind, scope, parts.breaking, lowercase_first(description))
+            }
+            Preset::Gitmoji => {
+                let emoji = if parts.breaking.is_empty() {
+                    GITMOJI.iter().find(|(kind, _)| *kind == parts.kind).map_or(":sparkles:", |(_, emoji)| emoji)
+                } else {
+                    ":boom:"
+                };
+                match parts.scope {
+                    Some(_) => format!("{} {}: {}", emoji, scope, uppercase_first(description)),
+                    None => format!("{} {}", emoji, uppercase_first(description)),
+                }
+            }
+            // The scope is the subsystem; without one there's nothing to put in its place
+            Preset::Kernel => match parts.scope {
+                Some(subsystem) => format!("{}: {}", subsystem, lowercase_first(description)),
+                None => return message.to_string(),
+            },
+            Preset::Freeform => uppercase_first(description),
+        };
+        match body {
+            Some(body) => format!("{}\n{}", subject, body),
+            None => subject,
+        }
+    }
+}
+
+struct ConventionalSubject<'a> {
+    kind: &'a str,
+    scope: Option<&'a str>,
+    breaking: &'a str,
+    description: &'a str,
+}
+
+fn conventional_subject(subject: &str) -> Option<ConventionalSubject<'_>> {
+    static SUBJECT: OnceLock<Regex> = OnceLock::new();
+    let captures = SUBJECT.get_or_init(|| Regex::new(r"^([a-z]+)(?:\(([^)]*)\))?(!?): (\S.*)$").unwrap()).captures(subject)?;
+    Some(ConventionalSubject {
+        kind: captures.get(1).unwrap().as_str(),
+        scope: captures.get(2).map(|scope| scope.as_str()).filter(|scope| !scope.is_empty()),
+        breaking: captures.get(3).unwrap().as_str(),
+        description: captures.get(4).unwrap().as_str(),
+    })
+}
+
+/// Lowercase the first letter, unless the word is an acronym like `API`
+fn lowercase_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(first), second) if !second.is_some_and(char::is_uppercase) => first.to_lowercase().chain(text[first.len_utf8()..].chars()).collect(),
+        _ => text.to_string(),
+    }
+}
+
+fn uppercase_first(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The preset set with `convention.preset`, None when it's left at its default (so a learned
+/// style profile applies) or invalid
+pub fn configured_preset(settings: &Settings) -> Option<Preset> {
+    if settings.origin("convention.preset").is_none_or(|origin| *origin == SettingOrigin::Default) {
+        return None;
+    }
+    let name = settings.get_str("convention.preset")?;
+    Preset::parse(name).or_else(|| {
+        let names: Vec<&str> = Preset::ALL.iter().map(|preset| preset.name()).collect();
+        eprintln!("Warning: ignoring unknown convention.preset '{}' (expected one of {})", name, names.join(", "));
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_post_process() {
+        assert_eq!(Preset::Gitmoji.post_process("feat: add login"), ":sparkles: Add login");
+        assert_eq!(Preset::Gitmoji.post_process("fix(api)!: drop v1 tokens\n\nBody."), ":boom: (api): Drop v1 tokens\n\nBody.");
+        assert_eq!(Preset::Angular.post_process("chore(deps): Update serde."), "build(deps): update serde");
+        assert_eq!(Preset::Karma.post_process("ci: cache cargo"), "chore: cache cargo");
+        assert_eq!(Preset::Kernel.post_process("fix(net): Fix leak in tcp_close()"), "net: fix leak in tcp_close()");
+        assert_eq!(Preset::Kernel.post_process("fix: API leak"), "fix: API leak");
+        assert_eq!(Preset::Freeform.post_process("docs: update readme"), "Update readme");
+        assert_eq!(Preset::Conventional.post_process("feat: Add X."), "feat: Add X.");
+        assert_eq!(Preset::Gitmoji.post_process(":bug: Fix crash"), ":bug: Fix crash");
+    }
+
+    #[test]
+    fn test_preset_lint() {
+        assert!(Preset::Angular.lint("feat(router): add lazy routes").is_empty());
+        assert_eq!(Preset::Angular.lint("chore: Update deps.").len(), 2);
+        assert!(Preset::Karma.lint("chore: update deps").is_empty());
+        assert!(Preset::Gitmoji.lint(":sparkles: Add login").is_empty());
+        assert_eq!(Preset::Gitmoji.lint("feat: add login").len(), 1);
+        assert!(Preset::Kernel.lint("mm/slab: remove unused flags").is_empty());
+        assert_eq!(Preset::Kernel.lint("Remove unused flags.").len(), 2);
+        assert!(Preset::Freeform.lint("Add login").is_empty());
+        assert_eq!(Preset::Freeform.lint("feat: add login").len(), 2);
+        assert_eq!(Preset::parse("Linux"), Some(Preset::Kernel));
+        assert!(Preset::Kernel.instructions(PromptStyle::Compact).contains("max 75 chars"));
+    }
+}
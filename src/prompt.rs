@@ -0,0 +1,69 @@
+// Prompt construction shared by all providers
+
+use crate::context::prompt_context;
+use crate::language::{configured_language, Language, KEEP_IN_ENGLISH};
+use crate::preset::{configured_preset, Preset};
+use crate::scope::{scope_choice, scope_instruction};
+use crate::settings::Settings;
+use crate::style::StyleProfile;
+
+/// Which prompt template a provider uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptStyle {
+    /// Full instructions for hosted chat models (OpenRouter, OpenAI compatible, Simple Free)
+    Detailed,
+    /// One-line instructions for local models and CLI tools (Ollama, Claude Code, OpenCode)
+    Compact,
+}
+
+/// Build the commit message prompt for an already processed diff
+pub fn build_commit_prompt(processed_diff: &str, style: PromptStyle, settings: &Settings) -> String {
+    // An explicitly chosen preset wins over a learned style profile
+    let mut prompt = match (configured_preset(settings), StyleProfile::load()) {
+        (Some(preset), _) => preset.instructions(style),
+        (None, Some(profile)) => style_instructions(&profile, style),
+        (None, None) => Preset::Conventional.instructions(style),
+    };
+
+    if let Some(instructions) = settings.get_str("prompt.instructions") {
+        prompt.push_str(&format!("\n\nAdditional instructions:\n{}", instructions.trim()));
+    }
+
+    if let Some(language) = configured_language(settings) {
+        prompt.push_str(&format!("\n\nLanguage:\n{}", language.instruction()));
+    }
+
+    if let Some(instruction) = scope_instruction(&scope_choice(processed_diff, settings)) {
+        prompt.push_str(&format!("\n\nScope:\n{}", instruction));
+    }
+
+    let context = prompt_context(processed_diff, settings);
+    if !context.is_empty() {
+        prompt.push_str(&format!("\n\nRepository context:\n{}", context));
+    }
+
+    prompt.push_str(&format!("\n\nGit Diff:\n```diff\n{}\n```\nCommit Message ONLY:", processed_diff));
+    prompt
+}
+
+/// Prompt to rewrite an existing commit message in another language
+pub fn build_translation_prompt(message: &str, language: &Language) -> String {
+    format!("Translate the following git commit message into {}. {}. Keep the format and line breaks. Output ONLY the translated commit message, without any introductory text, explanations, or ```.\n\nCommit message:\n```\n{}\n```\nTranslated Commit Message ONLY:",
+        language.name, KEEP_IN_ENGLISH, message.trim())
+}
+
+/// Instructions following the style learned by `aicommit style learn`, with examples from the
+/// repository's history instead of the preset ones
+fn style_instructions(profile: &StyleProfile, style: PromptStyle) -> String {
+    let mut instructions = match style {
+        PromptStyle::Detailed => format!("Generate ONLY the git commit message string based on the provided diff. Follow the commit style of this repository: {}. Do NOT include any introductory phrases, explanations, or markdown formatting like ```.", profile.describe()),
+        PromptStyle::Compact => format!("Generate ONLY the raw git commit message string (one line, max {} chars) based on the diff. Follow this repository's commit style: {}. Do NOT include any introductory text, explanations, or ```.", profile.max_length, profile.describe()),
+    };
+    if !profile.examples.is_empty() {
+        instructions.push_str("\nExamples from the repository history:");
+        for example in &profile.examples {
+            instructions.push_str(&format!("\n- {}", example));
+        }
+    }
+    instructions
+}
@@ -1,16 +1,76 @@
 // AI provider functions
 
+use std::fs;
+use std::process::Command;
 use crate::types::*;
-use dialoguer::{Input, Select};
+use crate::secrets::{delete_from_keyring, is_secret_reference, redact_secret, resolve_secret, store_in_keyring};
+use crate::settings::Settings;
+use crate::state::move_provider_state;
+use crate::utils::write_private_file;
+use crate::git::generate_with_provider;
+use crate::models::FailureKind;
+use dialoguer::{Confirm, Input, Select};
 use uuid::Uuid;
 
-// From: 018_function_setup_openrouter_provider.rs
-pub async fn setup_openrouter_provider() -> Result<OpenRouterConfig, String> {
+/// Ask for an API key. The user may enter a plaintext key or a reference
+/// (env:VAR, file:/path, cmd:..., keyring:account). Plaintext keys can optionally
+/// be moved into the OS keyring so they never touch ~/.aicommit.json.
+pub fn prompt_api_key(prompt: &str, provider_id: &str) -> Result<String, String> {
     let api_key: String = Input::new()
-        .with_prompt("Enter OpenRouter API key")
+        .with_prompt(format!("{} (or env:VAR, file:/path, cmd:..., keyring:account)", prompt))
         .interact_text()
         .map_err(|e| format!("Failed to get API key: {}", e))?;
 
+    if is_secret_reference(&api_key) {
+        return Ok(api_key);
+    }
+
+    let use_keyring = Confirm::new()
+        .with_prompt("Store this key in the OS keyring instead of the config file?")
+        .default(false)
+        .interact()
+        .map_err(|e| format!("Failed to get keyring choice: {}", e))?;
+
+    if !use_keyring {
+        return Ok(api_key);
+    }
+
+    match store_in_keyring(provider_id, &api_key) {
+        Ok(()) => Ok(format!("keyring:{}", provider_id)),
+        Err(e) => {
+            eprintln!("Could not store the key in the OS keyring: {}", e);
+            keyring_fallback(api_key)
+        }
+    }
+}
+
+/// Where to keep a key the keyring couldn't take: the config file or an environment variable
+fn keyring_fallback(api_key: String) -> Result<String, String> {
+    let choice = Select::new()
+        .with_prompt("Where should the key go instead?")
+        .items(&["Config file (plain text, readable only by you)", "An environment variable (env:VAR reference)"])
+        .default(0)
+        .interact()
+        .map_err(|e| format!("Failed to get key storage choice: {}", e))?;
+
+    if choice == 0 {
+        return Ok(api_key);
+    }
+
+    let variable: String = Input::new()
+        .with_prompt("Environment variable name")
+        .default("AICOMMIT_API_KEY".into())
+        .interact_text()
+        .map_err(|e| format!("Failed to get variable name: {}", e))?;
+    println!("Set {} to the key in your shell profile; the config file only refers to it.", variable);
+    Ok(format!("env:{}", variable))
+}
+
+// From: 018_function_setup_openrouter_provider.rs
+pub async fn setup_openrouter_provider() -> Result<OpenRouterConfig, String> {
+    let id = Uuid::new_v4().to_string();
+    let api_key = prompt_api_key("Enter OpenRouter API key", &id)?;
+
     let model: String = Input::new()
         .with_prompt("Enter model name")
         .default("mistralai/mistral-tiny".into())
@@ -34,7 +94,7 @@ pub async fn setup_openrouter_provider() -> Result<OpenRouterConfig, String> {
         .map_err(|e| format!("Failed to parse temperature: {}", e))?;
 
     Ok(OpenRouterConfig {
-        id: Uuid::new_v4().to_string(),
+        id,
         provider: "openrouter".to_string(),
         api_key,
         model,
@@ -45,10 +105,8 @@ pub async fn setup_openrouter_provider() -> Result<OpenRouterConfig, String> {
 
 // From: 019_function_setup_openai_compatible_provider.rs
 pub async fn setup_openai_compatible_provider() -> Result<OpenAICompatibleConfig, String> {
-    let api_key: String = Input::new()
-        .with_prompt("Enter API key (can be any non-empty string for local models like LM Studio)")
-        .interact_text()
-        .map_err(|e| format!("Failed to get API key: {}", e))?;
+    let id = Uuid::new_v4().to_string();
+    let api_key = prompt_api_key("Enter API key (can be any non-empty string for local models like LM Studio)", &id)?;
 
     let api_url: String = Input::new()
         .with_prompt("Enter complete API URL (e.g., https://api.example.com/v1/chat/completions)")
@@ -99,7 +157,7 @@ pub async fn setup_openai_compatible_provider() -> Result<OpenAICompatibleConfig
         .map_err(|e| format!("Failed to parse temperature: {}", e))?;
 
     Ok(OpenAICompatibleConfig {
-        id: Uuid::new_v4().to_string(),
+        id,
         provider: "openai_compatible".to_string(),
         api_key,
         api_url,
@@ -109,3 +167,211 @@ pub async fn setup_openai_compatible_provider() -> Result<OpenAICompatibleConfig
     })
 }
 
+
+/// Small diff used by `aicommit provider test`
+const TEST_DIFF: &str = "diff --git a/src/greeting.rs b/src/greeting.rs
+--- a/src/greeting.rs
++++ b/src/greeting.rs
+@@ -1,3 +1,3 @@
+ pub fn greeting(name: &str) -> String {
+-    format!(\"Hello {}\", name)
++    format!(\"Hello, {}!\", name)
+ }
+";
+
+/// Whether the provider's key is the keyring entry `prompt_api_key` stored for it, which is
+/// renamed and deleted along with the provider
+fn owns_keyring_entry(provider: &ProviderConfig) -> bool {
+    provider.api_key() == Some(format!("keyring:{}", provider.id()).as_str())
+}
+
+/// Remove a provider; if it was active, the first remaining provider becomes active
+pub fn remove_provider(id: &str) -> Result<(), String> {
+    let mut removed = None;
+    let config = Config::update(|config| {
+        let index = config.providers.iter().position(|p| p.id() == id)
+            .ok_or_else(|| format!("Provider '{}' not found", id))?;
+        removed = Some(config.providers.remove(index));
+
+        if config.active_provider == id {
+            config.active_provider = config.providers.first()
+                .map(|p| p.id().to_string())
+                .unwrap_or_default();
+        }
+        Ok(())
+    })?;
+    move_provider_state(id, None)?;
+    if removed.as_ref().is_some_and(owns_keyring_entry) {
+        if let Err(e) = delete_from_keyring(id) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    println!("Provider {} removed", id);
+    if config.active_provider.is_empty() {
+        println!("No providers left. Add one with 'aicommit provider add'");
+    } else {
+        println!("Active provider: {}", config.active_provider);
+    }
+    Ok(())
+}
+
+/// Change the ID of a provider, keeping it active if it was
+pub fn rename_provider(id: &str, new_id: &str) -> Result<(), String> {
+    let new_id = new_id.trim();
+    if new_id.is_empty() {
+        return Err("New provider ID must not be empty".to_string());
+    }
+
+    // The key moves to an entry under the new ID before the config refers to it
+    let current = Config::load()?;
+    if current.providers.iter().any(|p| p.id() == new_id) {
+        return Err(format!("Provider '{}' already exists", new_id));
+    }
+    let moves_key = current.providers.iter().find(|p| p.id() == id).is_some_and(owns_keyring_entry);
+    if moves_key {
+        store_in_keyring(new_id, &resolve_secret(&format!("keyring:{}", id))?)?;
+    }
+
+    let renamed = Config::update(|config| {
+        if config.providers.iter().any(|p| p.id() == new_id) {
+            return Err(format!("Provider '{}' already exists", new_id));
+        }
+        let provider = config.providers.iter_mut().find(|p| p.id() == id)
+            .ok_or_else(|| format!("Provider '{}' not found", id))?;
+        provider.set_id(new_id);
+        if moves_key {
+            provider.set_api_key(&format!("keyring:{}", new_id));
+        }
+
+        if config.active_provider == id {
+            config.active_provider = new_id.to_string();
+        }
+        Ok(())
+    });
+    if let Err(e) = renamed {
+        if moves_key {
+            let _ = delete_from_keyring(new_id);
+        }
+        return Err(e);
+    }
+    move_provider_state(id, Some(new_id))?;
+    if moves_key {
+        if let Err(e) = delete_from_keyring(id) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    println!("Provider {} renamed to {}", id, new_id);
+    Ok(())
+}
+
+/// Print one provider's configuration with its API key redacted
+pub fn show_provider(config: &Config, provider: &ProviderConfig, settings: &Settings) -> Result<(), String> {
+    // A copy of the provider, so the key is redacted before it is serialized
+    let mut redacted: ProviderConfig = serde_json::to_value(provider)
+        .and_then(serde_json::from_value)
+        .map_err(|e| format!("Failed to serialize provider: {}", e))?;
+    if let Some(api_key) = provider.api_key() {
+        redacted.set_api_key(&redact_secret(api_key));
+    }
+    let content = serde_json::to_string_pretty(&redacted)
+        .map_err(|e| format!("Failed to serialize provider: {}", e))?;
+
+    let active = config.active_provider_config(settings).map(|p| p.id()) == Some(provider.id());
+    println!("{}: {}{}", provider.kind_name(), provider.id(), if active { " (active)" } else { "" });
+    println!("Model: {}", provider.model().unwrap_or("auto"));
+    println!("Endpoint: {}", provider.endpoint());
+    if let ProviderConfig::SimpleFreeOpenRouter(c) = provider {
+        println!("Tracked models: {} (last used: {})", c.model_stats.len(), c.last_used_model.as_deref().unwrap_or("none"));
+    }
+    println!("{}", content);
+    Ok(())
+}
+
+/// Open a single provider in $EDITOR and write it back if it still parses
+pub fn edit_provider(id: &str) -> Result<(), String> {
+    let mut provider = Config::load()?.providers.into_iter().find(|p| p.id() == id)
+        .ok_or_else(|| format!("Provider '{}' not found", id))?;
+
+    // A plaintext key stays out of the temp file: it's shown redacted and kept unless replaced.
+    // References (keyring:, env:, ...) are written as they are.
+    let plaintext_key = provider.api_key().filter(|key| !is_secret_reference(key)).map(str::to_string);
+    if let Some(key) = &plaintext_key {
+        provider.set_api_key(&redact_secret(key));
+    }
+
+    let content = serde_json::to_string_pretty(&provider)
+        .map_err(|e| format!("Failed to serialize provider: {}", e))?;
+    let temp_path = std::env::temp_dir().join(format!("aicommit-provider-{}.json", Uuid::new_v4()));
+    write_private_file(&temp_path, &content)?;
+
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
+    let status = Command::new(editor)
+        .arg(&temp_path)
+        .status();
+    let edited = fs::read_to_string(&temp_path);
+    let _ = fs::remove_file(&temp_path);
+
+    let status = status.map_err(|e| format!("Failed to open editor: {}", e))?;
+    if !status.success() {
+        return Err("Editor exited with error".to_string());
+    }
+    let edited = edited.map_err(|e| format!("Failed to read edited provider: {}", e))?;
+
+    let mut updated: ProviderConfig = serde_json::from_str(&edited)
+        .map_err(|e| format!("Edited provider is not valid, nothing was changed: {}", e))?;
+    if let Some(key) = &plaintext_key {
+        if updated.api_key() == Some(redact_secret(key).as_str()) {
+            updated.set_api_key(key);
+        }
+    }
+    let new_id = updated.id().to_string();
+
+    Config::update(|config| {
+        if new_id != id && config.providers.iter().any(|p| p.id() == new_id) {
+            return Err(format!("Provider '{}' already exists", new_id));
+        }
+        let slot = config.providers.iter_mut().find(|p| p.id() == id)
+            .ok_or_else(|| format!("Provider '{}' not found", id))?;
+        *slot = updated;
+
+        if new_id != id && config.active_provider == id {
+            config.active_provider = new_id.clone();
+        }
+        Ok(())
+    })?;
+
+    if new_id != id {
+        move_provider_state(id, Some(&new_id))?;
+    }
+    println!("Provider {} updated", new_id);
+    Ok(())
+}
+
+/// Send a small canned diff through a provider and report latency and the answer
+pub async fn test_provider(provider: &ProviderConfig, cli: &Cli, settings: &Settings) -> Result<(), String> {
+    println!("Testing {} provider {}", provider.kind_name(), provider.id());
+    println!("Model: {}", provider.model().unwrap_or("auto"));
+    println!("Endpoint: {}", provider.endpoint());
+
+    let started = std::time::Instant::now();
+    let result = generate_with_provider(provider, TEST_DIFF, cli, settings).await;
+    let elapsed = started.elapsed().as_secs_f64();
+
+    match result {
+        Ok((message, usage)) => {
+            println!("OK in {:.2}s", elapsed);
+            if let Some(model) = usage.model_used {
+                println!("Answered by: {}", model);
+            }
+            println!("Answer: {}", message);
+            println!("Tokens: {} in, {} out", usage.input_tokens, usage.output_tokens);
+            Ok(())
+        }
+        Err(e) if e.kind == FailureKind::Auth => {
+            Err(format!("Authentication failed after {:.2}s, check the API key: {}", elapsed, e))
+        }
+        Err(e) => Err(format!("Request failed after {:.2}s: {}", elapsed, e)),
+    }
+}
@@ -0,0 +1,218 @@
+// Retry policy shared by commit message generation and the OpenRouter catalogue fetch:
+// exponential backoff with jitter, an overall deadline, and the failure kinds worth retrying
+
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use crate::models::{Failure, FailureKind};
+use crate::settings::Settings;
+use crate::types::ProviderConfig;
+use crate::utils::default_retry_attempts;
+use crate::{RETRY_BASE_DELAY_MS, RETRY_MULTIPLIER, RETRY_MAX_DELAY_SECS, RETRY_JITTER_PERCENT, RETRY_DEADLINE_SECS};
+
+/// Failure kinds retried by default (`retry.retryable`)
+const DEFAULT_RETRYABLE: &[&str] = &["rate_limit", "outage", "content_filter", "bad_output", "transport"];
+
+/// Configurable in the [retry] section of the layered settings
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Tries including the first one
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub multiplier: u32,
+    pub max_delay: Duration,
+    /// Share of each delay (0.0-1.0) that is randomized
+    pub jitter: f64,
+    /// No retry starts after this much time since the first try
+    pub deadline: Duration,
+    /// Retried failure kinds, as `FailureKind::key` values
+    pub retryable: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: default_retry_attempts(),
+            base_delay: Duration::from_millis(RETRY_BASE_DELAY_MS),
+            multiplier: RETRY_MULTIPLIER,
+            max_delay: Duration::from_secs(RETRY_MAX_DELAY_SECS),
+            jitter: RETRY_JITTER_PERCENT as f64 / 100.0,
+            deadline: Duration::from_secs(RETRY_DEADLINE_SECS),
+            retryable: DEFAULT_RETRYABLE.iter().map(|kind| kind.to_string()).collect(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let non_negative = |key: &str| settings.get_int(key).map(|n| n.max(0) as u64);
+        Self {
+            attempts: defaults.attempts,
+            base_delay: non_negative("retry.base_delay_ms").map_or(defaults.base_delay, Duration::from_millis),
+            multiplier: non_negative("retry.multiplier").map_or(defaults.multiplier, |n| n.max(1) as u32),
+            max_delay: non_negative("retry.max_delay_secs").map_or(defaults.max_delay, Duration::from_secs),
+            jitter: non_negative("retry.jitter_percent").map_or(defaults.jitter, |n| n.min(100) as f64 / 100.0),
+            deadline: non_negative("retry.deadline_secs").map_or(defaults.deadline, Duration::from_secs),
+            retryable: match settings.get("retry.retryable") {
+                Some(_) => settings.get_list("retry.retryable"),
+                None => defaults.retryable,
+            },
+        }
+    }
+
+    /// The policy with the number of tries from ~/.aicommit.json (`retry_attempts`)
+    pub fn with_attempts(self, attempts: u32) -> Self {
+        Self { attempts: attempts.max(1), ..self }
+    }
+
+    /// A single try for Simple Free, which moves between models until its own deadline, so
+    /// retrying it would run the whole failover again
+    pub fn for_provider(self, provider: &ProviderConfig) -> Self {
+        match provider {
+            ProviderConfig::SimpleFreeOpenRouter(_) => Self { attempts: 1, ..self },
+            _ => self,
+        }
+    }
+
+    /// Whether a failure is worth another try. Fatal and auth failures never are.
+    pub fn is_retryable(&self, kind: FailureKind) -> bool {
+        match kind {
+            FailureKind::Fatal | FailureKind::Auth => false,
+            kind => self.retryable.iter().any(|retryable| retryable == kind.key()),
+        }
+    }
+
+    /// Delay before the retry that follows the `failures`-th failed try. A rate limit waits at
+    /// least as long as the server asked.
+    pub fn delay(&self, failures: u32, kind: FailureKind) -> Duration {
+        let exponent = failures.saturating_sub(1).min(32);
+        let backoff = self.base_delay
+            .saturating_mul(self.multiplier.saturating_pow(exponent))
+            .min(self.max_delay);
+        let jittered = backoff.mul_f64(1.0 - self.jitter * random_fraction());
+        match kind {
+            FailureKind::RateLimit { retry_after: Some(retry_after) } => jittered.max(retry_after),
+            _ => jittered,
+        }
+    }
+}
+
+/// A random number in [0, 1) without pulling in a random number crate: `RandomState` is seeded
+/// randomly per instance, the clock makes consecutive calls differ even if it isn't.
+fn random_fraction() -> f64 {
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos()));
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Run `operation` until it succeeds, fails with an error the policy doesn't retry, runs out
+/// of attempts, or the next retry would start after the deadline. `what` names the operation
+/// in the messages ("generate commit message").
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, what: &str, mut operation: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Failure>>,
+{
+    let started = tokio::time::Instant::now();
+    let mut failures = 0;
+    loop {
+        match operation().await {
+            Ok(result) => {
+                if failures > 0 {
+                    eprintln!("Attempt {} succeeded", failures + 1);
+                }
+                return Ok(result);
+            }
+            Err(Failure { kind, message: e }) => {
+                failures += 1;
+                if !policy.is_retryable(kind) || policy.attempts <= 1 {
+                    return Err(e);
+                }
+                if failures >= policy.attempts {
+                    return Err(format!("Failed to {} after {} attempts. Last error: {}", what, failures, e));
+                }
+                let delay = policy.delay(failures, kind);
+                if started.elapsed() + delay > policy.deadline {
+                    return Err(format!("Failed to {} within {}s. Last error: {}", what, policy.deadline.as_secs(), e));
+                }
+
+                eprintln!("Attempt {} failed: {}", failures, e);
+                eprintln!("Retry attempt {} of {} in {:.1}s", failures + 1, policy.attempts, delay.as_secs_f64());
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::from_millis(100), max_delay: Duration::from_millis(500), jitter: 0.0, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn test_backoff_grows_caps_and_jitters() {
+        let policy = policy();
+        let delays: Vec<u128> = (1..=5).map(|n| policy.delay(n, FailureKind::Outage).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        let rate_limit = FailureKind::RateLimit { retry_after: Some(Duration::from_secs(2)) };
+        assert_eq!(policy.delay(1, rate_limit), Duration::from_secs(2));
+
+        let jittered = RetryPolicy { jitter: 0.5, ..policy };
+        for _ in 0..20 {
+            let delay = jittered.delay(2, FailureKind::Outage).as_millis();
+            assert!((100..=200).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let policy = policy();
+        assert!(policy.is_retryable(FailureKind::Transport));
+        assert!(policy.is_retryable(FailureKind::Outage));
+        assert!(!policy.is_retryable(FailureKind::Auth));
+        assert!(!policy.is_retryable(FailureKind::Fatal));
+
+        let transport_only = RetryPolicy { retryable: vec!["transport".to_string(), "fatal".to_string()], ..policy };
+        assert!(!transport_only.is_retryable(FailureKind::Outage));
+        assert!(!transport_only.is_retryable(FailureKind::Fatal));
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_at_attempts_and_on_fatal_errors() {
+        let policy = RetryPolicy { base_delay: Duration::ZERO, ..policy() }.with_attempts(3);
+
+        let mut calls = 0;
+        let result: Result<(), String> = retry(&policy, "test", || { calls += 1; async { Err("Request timed out".into()) } }).await;
+        assert!(result.unwrap_err().starts_with("Failed to test after 3 attempts"));
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: Result<(), String> = retry(&policy, "test", || { calls += 1; async { Err("401 Unauthorized".into()) } }).await;
+        assert_eq!(result.unwrap_err(), "401 Unauthorized");
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: Result<(), String> = retry(&policy, "test", || { calls += 1; async { Err(Failure::fatal("Egress policy: no".to_string())) } }).await;
+        assert_eq!(result.unwrap_err(), "Egress policy: no");
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_secret_resolution_failure_is_not_retried() {
+        let policy = RetryPolicy { base_delay: Duration::ZERO, ..policy() }.with_attempts(3);
+        let mut calls = 0;
+        let result: Result<String, String> = retry(&policy, "test", || {
+            calls += 1;
+            async { crate::secrets::resolve_secret("env:AICOMMIT_TEST_RETRY_SECRET_MISSING").map_err(Failure::fatal) }
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+}
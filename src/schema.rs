@@ -0,0 +1,429 @@
+// Config schema versioning - migrations for ~/.aicommit.json and `aicommit config validate`
+
+use std::collections::{HashMap, HashSet};
+use serde_json::{json, Map, Value};
+use crate::types::{Config, ProviderConfig};
+
+/// Version written by this build of aicommit
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+const TOP_LEVEL_FIELDS: &[&str] = &["schema_version", "providers", "active_provider", "retry_attempts"];
+
+/// Known fields for each provider variant (the JSON tag wrapping the provider object)
+const PROVIDER_FIELDS: &[(&str, &[&str])] = &[
+    ("OpenRouter", &["id", "provider", "api_key", "model", "max_tokens", "temperature"]),
+    ("Ollama", &["id", "provider", "model", "url", "max_tokens", "temperature"]),
+    ("OpenAICompatible", &["id", "provider", "api_key", "api_url", "model", "max_tokens", "temperature"]),
+    ("SimpleFreeOpenRouter", &["id", "provider", "api_key", "max_tokens", "temperature"]),
+    ("ClaudeCode", &["id", "provider"]),
+    ("OpenCode", &["id", "provider"]),
+];
+
+/// Provider fields that older schema versions stored in the config and migration moves away
+const LEGACY_PROVIDER_FIELDS: &[&str] = &["failed_models", "model_stats", "last_used_model", "last_config_update"];
+
+/// Outcome of upgrading a raw config document
+#[derive(Debug)]
+pub struct Migration {
+    /// Version the document had before migration
+    pub from_version: u32,
+    /// Runtime state taken out of the document, keyed by provider id (destined for the state file)
+    pub runtime_state: Map<String, Value>,
+}
+
+/// Read the schema version of a raw config document (files without the field are version 0)
+pub fn schema_version(value: &Value) -> u32 {
+    value.get("schema_version").and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+/// Upgrade a raw config document to CURRENT_SCHEMA_VERSION in place
+pub fn migrate(value: &mut Value) -> Result<Migration, String> {
+    let original = schema_version(value);
+    if original > CURRENT_SCHEMA_VERSION {
+        return Err(format!(
+            "Config file uses schema version {} but this aicommit only understands up to {}. Please upgrade aicommit.",
+            original, CURRENT_SCHEMA_VERSION
+        ));
+    }
+
+    let mut runtime_state = Map::new();
+    let mut version = original;
+    while version < CURRENT_SCHEMA_VERSION {
+        match version {
+            0 => migrate_v0_to_v1(value),
+            1 => migrate_v1_to_v2(value, &mut runtime_state),
+            _ => return Err(format!("No migration available from schema version {}", version)),
+        }
+        version += 1;
+        value["schema_version"] = json!(version);
+    }
+
+    Ok(Migration { from_version: original, runtime_state })
+}
+
+/// v0 -> v1: Simple Free providers tracked failures in a flat `failed_models` list.
+/// Fold those entries into `model_stats` so the jail logic keeps seeing them.
+fn migrate_v0_to_v1(value: &mut Value) {
+    let now = chrono::Utc::now().timestamp();
+    let Some(providers) = value.get_mut("providers").and_then(|p| p.as_array_mut()) else { return };
+
+    for provider in providers {
+        let Some(simple) = provider.get_mut("SimpleFreeOpenRouter").and_then(|p| p.as_object_mut()) else { continue };
+
+        let failed: Vec<String> = simple.remove("failed_models")
+            .and_then(|v| v.as_array().cloned())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect();
+
+        if failed.is_empty() {
+            continue;
+        }
+
+        let stats = simple.entry("model_stats").or_insert_with(|| json!({}));
+        if let Some(stats) = stats.as_object_mut() {
+            for model in failed {
+                stats.entry(model).or_insert_with(|| json!({
+                    "success_count": 0,
+                    "failure_count": 1,
+                    "last_success": null,
+                    "last_failure": now,
+                    "jail_until": null,
+                    "jail_count": 0,
+                    "blacklisted": false,
+                    "blacklisted_since": null,
+                }));
+            }
+        }
+    }
+}
+
+/// v1 -> v2: model statistics change on every run, so they move out of the user-edited config
+/// into the state file. The removed fields are collected into `runtime_state`.
+fn migrate_v1_to_v2(value: &mut Value, runtime_state: &mut Map<String, Value>) {
+    let Some(providers) = value.get_mut("providers").and_then(|p| p.as_array_mut()) else { return };
+
+    for provider in providers {
+        let Some(simple) = provider.get_mut("SimpleFreeOpenRouter").and_then(|p| p.as_object_mut()) else { continue };
+
+        let mut state = Map::new();
+        for field in ["model_stats", "last_used_model", "last_config_update"] {
+            if let Some(field_value) = simple.remove(field) {
+                state.insert(field.to_string(), field_value);
+            }
+        }
+
+        if let (false, Some(id)) = (state.is_empty(), simple.get("id").and_then(|v| v.as_str())) {
+            runtime_state.insert(id.to_string(), Value::Object(state));
+        }
+    }
+}
+
+/// A problem found by `aicommit config validate`
+#[derive(Debug)]
+pub struct ValidationIssue {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Validate the text of a config file, reporting unknown fields, type errors,
+/// duplicate provider IDs and a dangling active_provider, with line numbers where possible
+pub fn validate_config(content: &str) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let value: Value = match serde_json::from_str(content) {
+        Ok(value) => value,
+        Err(e) => {
+            issues.push(ValidationIssue { line: Some(e.line()), message: format!("invalid JSON: {}", e) });
+            return issues;
+        }
+    };
+    let lines = key_lines(content);
+    let line_of = |path: &str| lines.get(path).copied();
+
+    let version = schema_version(&value);
+    if version > CURRENT_SCHEMA_VERSION {
+        issues.push(ValidationIssue {
+            line: line_of("/schema_version"),
+            message: format!("schema_version {} is newer than supported version {}", version, CURRENT_SCHEMA_VERSION),
+        });
+    }
+
+    let Some(object) = value.as_object() else {
+        issues.push(ValidationIssue { line: Some(1), message: "config must be a JSON object".to_string() });
+        return issues;
+    };
+
+    for key in object.keys() {
+        if !TOP_LEVEL_FIELDS.contains(&key.as_str()) {
+            issues.push(ValidationIssue { line: line_of(&format!("/{}", key)), message: format!("unknown field '{}'", key) });
+        }
+    }
+
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let providers = object.get("providers").and_then(|p| p.as_array()).cloned().unwrap_or_default();
+
+    for (index, provider) in providers.iter().enumerate() {
+        let path = format!("/providers/{}", index);
+        let Some((kind, body)) = provider.as_object().and_then(|o| o.iter().next()) else {
+            issues.push(ValidationIssue { line: line_of(&path), message: format!("provider #{} is not an object", index + 1) });
+            continue;
+        };
+        let body_path = format!("{}/{}", path, kind);
+
+        let Some((_, known)) = PROVIDER_FIELDS.iter().find(|(name, _)| name == kind) else {
+            issues.push(ValidationIssue { line: line_of(&body_path), message: format!("unknown provider type '{}'", kind) });
+            continue;
+        };
+
+        if let Some(fields) = body.as_object() {
+            for field in fields.keys() {
+                let legacy = version < CURRENT_SCHEMA_VERSION && LEGACY_PROVIDER_FIELDS.contains(&field.as_str());
+                if !known.contains(&field.as_str()) && !legacy {
+                    issues.push(ValidationIssue {
+                        line: line_of(&format!("{}/{}", body_path, field)),
+                        message: format!("unknown field '{}' in {} provider", field, kind),
+                    });
+                }
+            }
+        }
+
+        // Migrated documents may still carry legacy fields; strip them before the type check
+        let mut typed = provider.clone();
+        if let Some(fields) = typed.get_mut(kind).and_then(|b| b.as_object_mut()) {
+            fields.retain(|field, _| known.contains(&field.as_str()));
+        }
+        if let Err(e) = serde_json::from_value::<ProviderConfig>(typed) {
+            issues.push(ValidationIssue { line: line_of(&body_path), message: format!("invalid {} provider: {}", kind, e) });
+        }
+
+        if let Some(id) = body.get("id").and_then(|v| v.as_str()) {
+            let id_line = line_of(&format!("{}/id", body_path));
+            if let Some(first) = ids.get(id) {
+                issues.push(ValidationIssue {
+                    line: id_line,
+                    message: format!("duplicate provider id '{}' (first defined by provider #{})", id, first + 1),
+                });
+            } else {
+                ids.insert(id.to_string(), index);
+            }
+        }
+    }
+
+    match object.get("active_provider").and_then(|v| v.as_str()) {
+        Some(active) if !active.is_empty() && !ids.contains_key(active) => issues.push(ValidationIssue {
+            line: line_of("/active_provider"),
+            message: format!("active_provider '{}' does not match any provider id", active),
+        }),
+        Some("") | None if !providers.is_empty() => issues.push(ValidationIssue {
+            line: line_of("/active_provider"),
+            message: "active_provider is not set".to_string(),
+        }),
+        _ => {}
+    }
+
+    // Top-level type errors (e.g. retry_attempts as a string)
+    let mut top_level = value.clone();
+    if let Some(fields) = top_level.as_object_mut() {
+        fields.retain(|field, _| TOP_LEVEL_FIELDS.contains(&field.as_str()));
+        fields.insert("providers".to_string(), json!([]));
+    }
+    if let Err(e) = serde_json::from_value::<Config>(top_level) {
+        issues.push(ValidationIssue { line: None, message: format!("invalid config: {}", e) });
+    }
+
+    let mut seen = HashSet::new();
+    issues.retain(|issue| seen.insert((issue.line, issue.message.clone())));
+    issues
+}
+
+/// Map each JSON pointer path in a (valid) JSON document to the line where its key or element starts
+fn key_lines(content: &str) -> HashMap<String, usize> {
+    let mut scanner = LineScanner { chars: content.chars().collect(), pos: 0, line: 1, lines: HashMap::new() };
+    scanner.value("");
+    scanner.lines
+}
+
+struct LineScanner {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    lines: HashMap<String, usize>,
+}
+
+impl LineScanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        if c == '\n' {
+            self.line += 1;
+        }
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut out = String::new();
+        self.bump(); // opening quote
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => break,
+                '\\' => {
+                    if let Some(escaped) = self.bump() {
+                        out.push(escaped);
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+
+    fn value(&mut self, path: &str) {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.bump();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some('"') => {
+                            let line = self.line;
+                            let key = self.string();
+                            let child = format!("{}/{}", path, key);
+                            self.lines.entry(child.clone()).or_insert(line);
+                            self.skip_whitespace();
+                            self.bump(); // ':'
+                            self.value(&child);
+                        }
+                        Some(',') => { self.bump(); }
+                        Some('}') => { self.bump(); break; }
+                        _ => break,
+                    }
+                }
+            }
+            Some('[') => {
+                self.bump();
+                let mut index = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => { self.bump(); }
+                        Some(']') => { self.bump(); break; }
+                        None => break,
+                        _ => {
+                            let child = format!("{}/{}", path, index);
+                            self.lines.entry(child.clone()).or_insert(self.line);
+                            self.value(&child);
+                            index += 1;
+                        }
+                    }
+                }
+            }
+            Some('"') => { self.string(); }
+            Some(_) => {
+                while self.peek().is_some_and(|c| !matches!(c, ',' | '}' | ']') && !c.is_whitespace()) {
+                    self.bump();
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_failed_models_into_model_stats() {
+        let mut value = json!({
+            "providers": [{"SimpleFreeOpenRouter": {
+                "id": "free", "provider": "simple_free_openrouter", "api_key": "k",
+                "max_tokens": 200, "temperature": 0.2,
+                "failed_models": ["broken/model:free"]
+            }}],
+            "active_provider": "free"
+        });
+
+        let migration = migrate(&mut value).unwrap();
+        assert_eq!(migration.from_version, 0);
+        assert_eq!(schema_version(&value), CURRENT_SCHEMA_VERSION);
+
+        let simple = &value["providers"][0]["SimpleFreeOpenRouter"];
+        assert!(simple.get("failed_models").is_none());
+        assert!(simple.get("model_stats").is_none());
+        assert_eq!(migration.runtime_state["free"]["model_stats"]["broken/model:free"]["failure_count"], 1);
+
+        let config: Config = serde_json::from_value(value).unwrap();
+        assert_eq!(config.providers.len(), 1);
+    }
+
+    #[test]
+    fn test_known_fields_match_serialized_providers() {
+        let providers = vec![
+            ProviderConfig::OpenRouter(crate::types::OpenRouterConfig {
+                id: "a".into(), provider: "openrouter".into(), api_key: "k".into(),
+                model: "m".into(), max_tokens: 1, temperature: 0.1,
+            }),
+            ProviderConfig::SimpleFreeOpenRouter(crate::types::SimpleFreeOpenRouterConfig {
+                id: "b".into(), provider: "simple_free_openrouter".into(), api_key: "k".into(),
+                max_tokens: 1, temperature: 0.1, model_stats: Default::default(),
+                last_used_model: None, last_config_update: chrono::Utc::now(),
+            }),
+        ];
+
+        for provider in providers {
+            let value = serde_json::to_value(&provider).unwrap();
+            let (kind, body) = value.as_object().unwrap().iter().next().unwrap();
+            let known = PROVIDER_FIELDS.iter().find(|(name, _)| name == kind).unwrap().1;
+            for field in body.as_object().unwrap().keys() {
+                assert!(known.contains(&field.as_str()), "{} field '{}' missing from PROVIDER_FIELDS", kind, field);
+            }
+        }
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let mut value = json!({"schema_version": CURRENT_SCHEMA_VERSION + 1, "providers": [], "active_provider": ""});
+        assert!(migrate(&mut value).is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_lines() {
+        let content = r#"{
+  "schema_version": 1,
+  "providers": [
+    {"Ollama": {"id": "local", "provider": "ollama", "model": "llama2", "url": "http://localhost:11434", "max_tokens": 200, "temperature": 0.3}},
+    {"Ollama": {
+      "id": "local",
+      "provider": "ollama",
+      "model": "llama2",
+      "url": "http://localhost:11434",
+      "max_tokens": 200,
+      "temperature": 0.3,
+      "colour": "blue"
+    }}
+  ],
+  "active_provider": "missing",
+  "retry": 3
+}"#;
+        let issues = validate_config(content);
+        let find = |needle: &str| issues.iter().find(|i| i.message.contains(needle)).map(|i| i.line);
+
+        assert_eq!(find("unknown field 'colour'"), Some(Some(12)));
+        assert_eq!(find("duplicate provider id 'local'"), Some(Some(6)));
+        assert_eq!(find("active_provider 'missing'"), Some(Some(15)));
+        assert_eq!(find("unknown field 'retry'"), Some(Some(16)));
+    }
+}
@@ -0,0 +1,354 @@
+// Conventional Commits scope inference - maps changed paths to scopes (configured globs, Cargo
+// or npm workspace members, or top-level directories) so the scope doesn't vary between runs
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::context::changed_files;
+use crate::settings::{repository_root, Settings};
+
+/// Where a split commit keeps the staged changes, in the git directory
+const SPLIT_PATCH_NAME: &str = "aicommit-split.patch";
+
+/// Where scopes come from when no `scope.map` entry matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Detect {
+    Off,
+    /// Members of a Cargo or npm workspace, named after their directory
+    Workspace,
+    /// The top-level directory of each path
+    Directories,
+    /// Workspace members if the repository has any, otherwise top-level directories
+    Auto,
+}
+
+/// What to do when a change touches several scopes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MultiScope {
+    /// `feat: ...`
+    Omit,
+    /// `feat(api,ui): ...`
+    List,
+    /// One commit per scope
+    Split,
+}
+
+/// The scope a message must use
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeChoice {
+    /// No rule applies, the model decides
+    Free,
+    Exact(String),
+    None,
+}
+
+/// Configurable in the [scope] section of the layered settings
+#[derive(Debug, Clone)]
+pub struct ScopeRules {
+    /// `pattern = scope` entries, first match wins
+    pub map: Vec<(String, String)>,
+    pub detect: Detect,
+    pub multiple: MultiScope,
+}
+
+impl Default for ScopeRules {
+    fn default() -> Self {
+        Self { map: Vec::new(), detect: Detect::Off, multiple: MultiScope::Omit }
+    }
+}
+
+impl ScopeRules {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let defaults = Self::default();
+        let map = settings.get_list("scope.map").iter().filter_map(|entry| {
+            let parsed = entry.rsplit_once('=').map(|(pattern, scope)| (pattern.trim().to_string(), scope.trim().to_string()));
+            if parsed.is_none() {
+                eprintln!("Warning: ignoring scope.map entry '{}' (expected \"pattern = scope\")", entry);
+            }
+            parsed
+        }).collect();
+        Self {
+            map,
+            detect: match settings.get_str("scope.detect").map(str::to_lowercase).as_deref() {
+                Some("workspace") => Detect::Workspace,
+                Some("directories") => Detect::Directories,
+                Some("auto") => Detect::Auto,
+                _ => defaults.detect,
+            },
+            multiple: match settings.get_str("scope.multiple").map(str::to_lowercase).as_deref() {
+                Some("list") => MultiScope::List,
+                Some("split") => MultiScope::Split,
+                _ => defaults.multiple,
+            },
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.map.is_empty() || self.detect != Detect::Off
+    }
+
+    /// Changed paths grouped by scope; paths without a scope are under ""
+    pub fn group(&self, root: &Path, paths: &[String]) -> BTreeMap<String, Vec<String>> {
+        let map: Vec<(Gitignore, &str)> = self.map.iter()
+            .map(|(pattern, scope)| (matcher(root, std::slice::from_ref(pattern)), scope.as_str()))
+            .collect();
+        let members = match self.detect {
+            Detect::Workspace | Detect::Auto => workspace_members(root),
+            _ => Vec::new(),
+        };
+        let members = (!members.is_empty()).then(|| matcher(root, &members));
+        let directories = self.detect == Detect::Directories || (self.detect == Detect::Auto && members.is_none());
+
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for path in paths {
+            let full = root.join(path);
+            let scope = map.iter()
+                .find(|(matcher, _)| matcher.matched_path_or_any_parents(&full, false).is_ignore())
+                .map(|(_, scope)| scope.to_string())
+                .or_else(|| members.as_ref().and_then(|members| member_of(members, root, path)))
+                .or_else(|| directories.then(|| top_level_directory(path)).flatten())
+                .unwrap_or_default();
+            groups.entry(scope).or_default().push(path.clone());
+        }
+        groups
+    }
+
+    /// The scope for a message about `paths`
+    pub fn choose(&self, root: &Path, paths: &[String]) -> ScopeChoice {
+        if !self.is_active() {
+            return ScopeChoice::Free;
+        }
+        let scopes: Vec<String> = self.group(root, paths).into_keys().filter(|scope| !scope.is_empty()).collect();
+        match scopes.len() {
+            0 => ScopeChoice::Free,
+            1 => ScopeChoice::Exact(scopes[0].clone()),
+            _ if self.multiple == MultiScope::List => ScopeChoice::Exact(scopes.join(",")),
+            _ => ScopeChoice::None,
+        }
+    }
+}
+
+fn matcher(root: &Path, patterns: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in patterns {
+        if let Err(e) = builder.add_line(None, pattern) {
+            eprintln!("Warning: ignoring invalid scope pattern '{}': {}", pattern, e);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Workspace member patterns from Cargo.toml (`[workspace] members`) and package.json
+/// (`workspaces`), as anchored directory patterns
+fn workspace_members(root: &Path) -> Vec<String> {
+    let mut members = Vec::new();
+    if let Some(cargo) = std::fs::read_to_string(root.join("Cargo.toml")).ok().and_then(|c| c.parse::<toml::Table>().ok()) {
+        if let Some(list) = cargo.get("workspace").and_then(|w| w.get("members")).and_then(|m| m.as_array()) {
+            members.extend(list.iter().filter_map(|m| m.as_str()).map(str::to_string));
+        }
+    }
+    if let Some(npm) = std::fs::read_to_string(root.join("package.json")).ok().and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok()) {
+        let workspaces = npm.get("workspaces");
+        let list = workspaces.and_then(|w| w.as_array()).or_else(|| workspaces.and_then(|w| w.get("packages")).and_then(|p| p.as_array()));
+        members.extend(list.into_iter().flatten().filter_map(|m| m.as_str()).map(str::to_string));
+    }
+    members.iter()
+        .map(|member| member.trim_start_matches("./").trim_end_matches('/'))
+        .filter(|member| !member.is_empty() && *member != ".")
+        .map(|member| format!("/{}/", member))
+        .collect()
+}
+
+/// Name of the workspace member directory containing `path`
+fn member_of(members: &Gitignore, root: &Path, path: &str) -> Option<String> {
+    let mut dir = PathBuf::new();
+    for component in Path::new(path).parent()?.components() {
+        dir.push(component);
+        if members.matched(root.join(&dir), true).is_ignore() {
+            return dir.file_name().map(|name| name.to_string_lossy().to_string());
+        }
+    }
+    None
+}
+
+fn top_level_directory(path: &str) -> Option<String> {
+    let (directory, _) = path.split_once('/')?;
+    Some(directory.trim_start_matches('.').to_string()).filter(|directory| !directory.is_empty())
+}
+
+/// The scope for the changes in `diff`, by the rules in the settings
+pub fn scope_choice(diff: &str, settings: &Settings) -> ScopeChoice {
+    let rules = ScopeRules::from_settings(settings);
+    if !rules.is_active() {
+        return ScopeChoice::Free;
+    }
+    let paths: Vec<String> = changed_files(diff).into_iter().map(|(_, path)| path).collect();
+    let root = repository_root().unwrap_or_else(|| PathBuf::from("."));
+    rules.choose(&root, &paths)
+}
+
+/// Prompt instruction for a scope choice
+pub fn scope_instruction(choice: &ScopeChoice) -> Option<String> {
+    match choice {
+        ScopeChoice::Free => None,
+        ScopeChoice::Exact(scope) => Some(format!("Use exactly the scope \"{}\" in the subject, e.g. feat({}): description.", scope, scope)),
+        ScopeChoice::None => Some("The change spans several components: don't put a scope in the subject.".to_string()),
+    }
+}
+
+/// Make a Conventional Commits subject use the chosen scope. Other subjects are left alone.
+pub fn enforce_scope(message: &str, choice: &ScopeChoice) -> String {
+    static SUBJECT: OnceLock<Regex> = OnceLock::new();
+    let subject = SUBJECT.get_or_init(|| Regex::new(r"^([a-z]+)(?:\([^)]*\))?(!?): ").unwrap());
+    let Some(captures) = subject.captures(message) else {
+        return message.to_string();
+    };
+    let scope = match choice {
+        ScopeChoice::Free => return message.to_string(),
+        ScopeChoice::Exact(scope) => format!("({})", scope),
+        ScopeChoice::None => String::new(),
+    };
+    let rest = &message[captures.get(0).unwrap().end()..];
+    format!("{}{}{}: {}", &captures[1], scope, &captures[2], rest)
+}
+
+/// With `scope.multiple = "split"`: the changed paths of `diff` per scope when there are
+/// several, unscoped paths last. None when the change stays in one commit.
+pub fn split_groups(diff: &str, settings: &Settings) -> Option<Vec<(String, Vec<String>)>> {
+    let rules = ScopeRules::from_settings(settings);
+    if !rules.is_active() || rules.multiple != MultiScope::Split {
+        return None;
+    }
+    let paths: Vec<String> = changed_files(diff).into_iter().map(|(_, path)| path).collect();
+    let root = repository_root().unwrap_or_else(|| PathBuf::from("."));
+    let mut groups = rules.group(&root, &paths);
+    if groups.keys().filter(|scope| !scope.is_empty()).count() < 2 {
+        return None;
+    }
+    let unscoped = groups.remove("");
+    let mut groups: Vec<(String, Vec<String>)> = groups.into_iter().collect();
+    groups.extend(unscoped.map(|paths| (String::new(), paths)));
+    Some(groups)
+}
+
+/// The sections of `diff` for the given paths
+pub fn diff_for_paths(diff: &str, paths: &[String]) -> String {
+    diff.split("diff --git ")
+        .skip(1)
+        .filter(|section| {
+            let header = section.lines().next().unwrap_or("");
+            header.rsplit_once(" b/").is_some_and(|(_, path)| paths.iter().any(|p| p == path.trim()))
+        })
+        .map(|section| format!("diff --git {}", section))
+        .collect()
+}
+
+/// Save the staged changes to `.git/aicommit-split.patch` and unstage everything, so they can
+/// be staged again group by group with `stage_paths`. The file keeps the exact staging if the
+/// process dies halfway; remove it with `discard_patch` once all of it is committed.
+pub fn unstage_all() -> Result<PathBuf, String> {
+    let path = PathBuf::from(String::from_utf8_lossy(&git(&["rev-parse", "--absolute-git-dir"])?).trim()).join(SPLIT_PATCH_NAME);
+    if path.exists() {
+        return Err(format!(
+            "{} is left over from an interrupted split commit. Restore what was staged with 'git apply --cached {}', then delete the file",
+            path.display(), path.display()));
+    }
+    let patch = git(&["diff", "--cached", "--binary"])?;
+    std::fs::write(&path, patch).map_err(|e| format!("Failed to save the staged changes to {}: {}", path.display(), e))?;
+    git(&["reset", "-q"])?;
+    Ok(path)
+}
+
+/// Stage the parts of the saved patch that touch `paths`
+pub fn stage_paths(patch: &Path, paths: &[String]) -> Result<(), String> {
+    let mut args = vec!["apply".to_string(), "--cached".to_string()];
+    args.extend(paths.iter().map(|path| format!("--include={}", escape_wildmatch(path))));
+    args.push(patch.to_string_lossy().to_string());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    git(&args).map(|_| ())
+}
+
+/// Unstage everything, then stage the parts of the saved patch that touch `paths`
+pub fn restage_paths(patch: &Path, paths: &[String]) -> Result<(), String> {
+    git(&["reset", "-q"])?;
+    stage_paths(patch, paths)
+}
+
+pub fn discard_patch(patch: &Path) {
+    let _ = std::fs::remove_file(patch);
+}
+
+/// `--include` takes wildmatch patterns; make a path match only itself
+fn escape_wildmatch(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Run git in the repository root: in a subdirectory `git apply` skips the paths outside it
+fn git(args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repository_root().unwrap_or_else(|| PathBuf::from(".")))
+        .output()
+        .map_err(|e| format!("Failed to run git {}: {}", args[0], e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_from_map_workspace_and_directories() {
+        let repo = tempfile::tempdir().unwrap();
+        let root = repo.path();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\", \"tools/xtask\"]\n").unwrap();
+
+        let rules = ScopeRules {
+            map: vec![("docs/**".to_string(), "docs".to_string())],
+            detect: Detect::Auto,
+            multiple: MultiScope::Omit,
+        };
+        let paths = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let groups = rules.group(root, &paths(&["crates/api/src/lib.rs", "crates/api/Cargo.toml", "tools/xtask/main.rs", "docs/guide/intro.md", "Cargo.lock"]));
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["", "api", "docs", "xtask"]);
+        assert_eq!(groups["api"].len(), 2);
+
+        assert_eq!(rules.choose(root, &paths(&["crates/api/src/lib.rs", "Cargo.lock"])), ScopeChoice::Exact("api".to_string()));
+        assert_eq!(rules.choose(root, &paths(&["crates/api/src/lib.rs", "crates/web/src/lib.rs"])), ScopeChoice::None);
+        let list = ScopeRules { multiple: MultiScope::List, ..rules.clone() };
+        assert_eq!(list.choose(root, &paths(&["crates/web/a.rs", "crates/api/b.rs"])), ScopeChoice::Exact("api,web".to_string()));
+
+        // Without workspace members, auto falls back to top-level directories
+        std::fs::remove_file(root.join("Cargo.toml")).unwrap();
+        assert_eq!(rules.choose(root, &paths(&["server/main.go"])), ScopeChoice::Exact("server".to_string()));
+    }
+
+    #[test]
+    fn test_enforce_scope() {
+        let api = ScopeChoice::Exact("api".to_string());
+        assert_eq!(enforce_scope("feat(server): add login", &api), "feat(api): add login");
+        assert_eq!(enforce_scope("fix!: drop v1\n\nBody", &api), "fix(api)!: drop v1\n\nBody");
+        assert_eq!(enforce_scope("feat(api,ui): add login", &ScopeChoice::None), "feat: add login");
+        assert_eq!(enforce_scope("Add login", &api), "Add login");
+        assert_eq!(enforce_scope("feat(x): y", &ScopeChoice::Free), "feat(x): y");
+    }
+
+    #[test]
+    fn test_include_patterns_match_only_the_path() {
+        assert_eq!(escape_wildmatch("src/main.rs"), "src/main.rs");
+        assert_eq!(escape_wildmatch("docs/[draft] *notes?.md"), "docs/\\[draft\\] \\*notes\\?.md");
+    }
+}
@@ -0,0 +1,218 @@
+// Secret storage module - resolves API key references so keys don't have to live in ~/.aicommit.json
+//
+// An `api_key` value in the config can be either a plaintext key (legacy behaviour) or a reference:
+//   env:VAR               - read from the environment variable VAR
+//   file:/path/to/key     - read from a file (a leading ~ is expanded to the home directory)
+//   cmd:pass show aicommit - run a shell command and use its stdout
+//   keyring:account       - read from the OS keyring (Secret Service on Linux, Keychain on macOS)
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::io::Write;
+
+/// Service name used for entries stored in the OS keyring
+pub const KEYRING_SERVICE: &str = "aicommit";
+
+const REFERENCE_PREFIXES: &[&str] = &["env:", "file:", "cmd:", "keyring:"];
+
+/// Check whether a configured value is a secret reference rather than a plaintext key
+pub fn is_secret_reference(value: &str) -> bool {
+    REFERENCE_PREFIXES.iter().any(|prefix| value.starts_with(prefix))
+}
+
+/// Resolve a configured API key value to the actual secret.
+/// Plaintext values are returned unchanged so existing configs keep working.
+pub fn resolve_secret(value: &str) -> Result<String, String> {
+    let secret = if let Some(var) = value.strip_prefix("env:") {
+        std::env::var(var.trim())
+            .map_err(|_| format!("Environment variable '{}' referenced by API key is not set", var.trim()))?
+    } else if let Some(path) = value.strip_prefix("file:") {
+        let path = expand_home(path.trim());
+        fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read API key file '{}': {}", path, e))?
+    } else if let Some(cmd) = value.strip_prefix("cmd:") {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(cmd.trim())
+            .output()
+            .map_err(|e| format!("Failed to run API key command '{}': {}", cmd.trim(), e))?;
+
+        if !output.status.success() {
+            return Err(format!("API key command '{}' failed: {}",
+                cmd.trim(), String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        String::from_utf8_lossy(&output.stdout).to_string()
+    } else if let Some(account) = value.strip_prefix("keyring:") {
+        read_from_keyring(account.trim())?
+    } else {
+        value.to_string()
+    };
+
+    let secret = secret.trim().to_string();
+    if secret.is_empty() {
+        return Err(format!("API key resolved from '{}' is empty", redact_secret(value)));
+    }
+
+    Ok(secret)
+}
+
+/// Produce a display-safe form of a configured API key value.
+/// References are shown as-is (they contain no secret), plaintext keys are masked.
+pub fn redact_secret(value: &str) -> String {
+    if is_secret_reference(value) {
+        return value.to_string();
+    }
+
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 12 {
+        return "****".to_string();
+    }
+
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+/// Store a secret in the OS keyring under the given account name. On Linux the secret goes
+/// through stdin; macOS `security` only takes it on the command line, where other local users
+/// can see it in `ps` while the command runs.
+pub fn store_in_keyring(account: &str, secret: &str) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        let output = Command::new("security")
+            .args(keychain_store_args(account, secret))
+            .output()
+            .map_err(|e| format!("Failed to run 'security': {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!("Failed to store key in Keychain: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    } else if cfg!(target_os = "linux") {
+        let mut command = Command::new("secret-tool");
+        command.args(["store", "--label", &format!("aicommit ({})", account), "service", KEYRING_SERVICE, "account", account]);
+        let output = run_with_input(command, secret, "secret-tool (is libsecret-tools installed?)")?;
+
+        if !output.status.success() {
+            return Err(format!("Failed to store key in keyring: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    } else {
+        Err("OS keyring storage is only supported on Linux and macOS".to_string())
+    }
+}
+
+/// `security add-generic-password` arguments. `-w` needs the password as its value: without one
+/// `security` prompts on the terminal instead of reading stdin.
+fn keychain_store_args<'a>(account: &'a str, secret: &'a str) -> [&'a str; 8] {
+    ["add-generic-password", "-U", "-s", KEYRING_SERVICE, "-a", account, "-w", secret]
+}
+
+fn run_with_input(mut command: Command, input: &str, name: &str) -> Result<std::process::Output, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", name, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())
+            .map_err(|e| format!("Failed to pass key to {}: {}", name, e))?;
+    }
+
+    child.wait_with_output()
+        .map_err(|e| format!("Failed to wait for {}: {}", name, e))
+}
+
+/// Delete the OS keyring entry of an account
+pub fn delete_from_keyring(account: &str) -> Result<(), String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["delete-generic-password", "-s", KEYRING_SERVICE, "-a", account])
+            .output()
+            .map_err(|e| format!("Failed to run 'security': {}", e))?
+    } else if cfg!(target_os = "linux") {
+        Command::new("secret-tool")
+            .args(["clear", "service", KEYRING_SERVICE, "account", account])
+            .output()
+            .map_err(|e| format!("Failed to run 'secret-tool' (is libsecret-tools installed?): {}", e))?
+    } else {
+        return Err("OS keyring storage is only supported on Linux and macOS".to_string());
+    };
+
+    if !output.status.success() {
+        return Err(format!("Failed to delete keyring entry '{}': {}", account, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+fn read_from_keyring(account: &str) -> Result<String, String> {
+    let output = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args(["find-generic-password", "-s", KEYRING_SERVICE, "-a", account, "-w"])
+            .output()
+            .map_err(|e| format!("Failed to run 'security': {}", e))?
+    } else if cfg!(target_os = "linux") {
+        Command::new("secret-tool")
+            .args(["lookup", "service", KEYRING_SERVICE, "account", account])
+            .output()
+            .map_err(|e| format!("Failed to run 'secret-tool' (is libsecret-tools installed?): {}", e))?
+    } else {
+        return Err("OS keyring storage is only supported on Linux and macOS".to_string());
+    };
+
+    if !output.status.success() {
+        return Err(format!("No keyring entry found for account '{}'", account));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub fn expand_home(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest).to_string_lossy().to_string();
+        }
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plaintext_key_passthrough() {
+        assert_eq!(resolve_secret("sk-or-v1-abcdef").unwrap(), "sk-or-v1-abcdef");
+        assert!(!is_secret_reference("sk-or-v1-abcdef"));
+    }
+
+    #[test]
+    fn test_env_and_file_references() {
+        std::env::set_var("AICOMMIT_TEST_SECRET_REF", "from-env\n");
+        assert_eq!(resolve_secret("env:AICOMMIT_TEST_SECRET_REF").unwrap(), "from-env");
+        assert!(resolve_secret("env:AICOMMIT_TEST_SECRET_MISSING").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let key_path = temp_dir.path().join("key");
+        fs::write(&key_path, "from-file\n").unwrap();
+        assert_eq!(resolve_secret(&format!("file:{}", key_path.display())).unwrap(), "from-file");
+
+        assert_eq!(resolve_secret("cmd:echo from-cmd").unwrap(), "from-cmd");
+    }
+
+    #[test]
+    fn test_redact_secret() {
+        assert_eq!(redact_secret("sk-or-v1-1234567890abcdef"), "sk-o...cdef");
+        assert_eq!(redact_secret("short"), "****");
+        assert_eq!(redact_secret("env:OPENROUTER_API_KEY"), "env:OPENROUTER_API_KEY");
+    }
+
+    #[test]
+    fn test_keychain_password_is_the_value_of_w() {
+        let args = keychain_store_args("p1", "sk-secret");
+        let w = args.iter().position(|arg| *arg == "-w").unwrap();
+        assert_eq!(args.get(w + 1), Some(&"sk-secret"));
+    }
+}
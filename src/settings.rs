@@ -0,0 +1,420 @@
+// Layered settings - merges system, user, repository, environment and command line layers
+//
+// Precedence (lowest to highest):
+//   1. built-in defaults
+//   2. /etc/aicommit/config.toml                  (system)
+//   3. ~/.config/aicommit/config.toml             (user, honours XDG_CONFIG_HOME)
+//   4. <repository root>/.aicommit.toml           (repository)
+//   5. AICOMMIT_* environment variables           (e.g. AICOMMIT_GIT_PUSH=true)
+//   6. command line flags                         (e.g. --push)
+//
+// Provider definitions themselves stay in ~/.aicommit.json; these layers only hold preferences.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::types::Cli;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Bool,
+    Integer,
+    String,
+    List,
+}
+
+/// Definition of a known setting
+pub struct SettingSpec {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    /// Default as a TOML literal; empty means "unset"
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+/// All settings understood by the layered configuration
+pub const KNOWN_SETTINGS: &[SettingSpec] = &[
+    SettingSpec { key: "active_provider", kind: SettingKind::String, default: "", description: "Provider ID to use (overrides active_provider in ~/.aicommit.json)" },
+    SettingSpec { key: "prompt.instructions", kind: SettingKind::String, default: "", description: "Extra instructions appended to the commit message prompt" },
+    SettingSpec { key: "language", kind: SettingKind::String, default: "", description: "Language of generated commit messages, as a code (de) or name (German); default English" },
+    SettingSpec { key: "ignore.patterns", kind: SettingKind::List, default: "[]", description: "Additional .aicommitignore-style patterns" },
+    SettingSpec { key: "git.push", kind: SettingKind::Bool, default: "false", description: "Push after committing" },
+    SettingSpec { key: "git.push_all", kind: SettingKind::Bool, default: "false", description: "Push to all configured remotes after committing" },
+    SettingSpec { key: "git.pull", kind: SettingKind::Bool, default: "false", description: "Pull from upstream after committing" },
+    SettingSpec { key: "context.branch", kind: SettingKind::Bool, default: "true", description: "Put the branch name and its ticket ID in the prompt" },
+    SettingSpec { key: "context.ticket_pattern", kind: SettingKind::String, default: "", description: "Regular expression of ticket IDs in branch names (default: PROJ-123 style)" },
+    SettingSpec { key: "context.ticket_placement", kind: SettingKind::String, default: "\"footer\"", description: "Where a ticket ID from the branch goes in the message: footer, scope or off" },
+    SettingSpec { key: "context.recent_commits", kind: SettingKind::Integer, default: "5", description: "Recent commit subjects in the prompt, so messages match their style (0 disables)" },
+    SettingSpec { key: "context.files", kind: SettingKind::Bool, default: "true", description: "List the changed files with their status in the prompt" },
+    SettingSpec { key: "context.readme_lines", kind: SettingKind::Integer, default: "0", description: "Lines of the README in the prompt (0 disables)" },
+    SettingSpec { key: "context.max_chars", kind: SettingKind::Integer, default: "2000", description: "Budget for all repository context in the prompt, in characters" },
+    SettingSpec { key: "scope.map", kind: SettingKind::List, default: "[]", description: "\"pattern = scope\" entries mapping changed paths to Conventional Commits scopes" },
+    SettingSpec { key: "scope.detect", kind: SettingKind::String, default: "\"off\"", description: "Scopes for paths scope.map doesn't cover: off, workspace, directories or auto" },
+    SettingSpec { key: "scope.multiple", kind: SettingKind::String, default: "\"omit\"", description: "Changes spanning several scopes: omit the scope, list them, or split the commit" },
+    SettingSpec { key: "convention.preset", kind: SettingKind::String, default: "\"conventional\"", description: "Commit convention: conventional, angular, karma, gitmoji, kernel or freeform" },
+    SettingSpec { key: "lint.max_subject_length", kind: SettingKind::Integer, default: "72", description: "Maximum length of the commit subject line" },
+    SettingSpec { key: "lint.conventional", kind: SettingKind::Bool, default: "true", description: "Require Conventional Commits subjects (type: description)" },
+    SettingSpec { key: "jail.max_consecutive_failures", kind: SettingKind::Integer, default: "3", description: "Consecutive failures that put a Simple Free model in jail" },
+    SettingSpec { key: "jail.initial_hours", kind: SettingKind::Integer, default: "24", description: "Length of a model's first jail term in hours" },
+    SettingSpec { key: "jail.multiplier", kind: SettingKind::Integer, default: "2", description: "Factor applied to the jail term for every previous jail" },
+    SettingSpec { key: "jail.max_hours", kind: SettingKind::Integer, default: "168", description: "Longest jail term in hours" },
+    SettingSpec { key: "jail.blacklist_after", kind: SettingKind::Integer, default: "3", description: "Jail terms after which a model is blacklisted" },
+    SettingSpec { key: "jail.blacklist_retry_days", kind: SettingKind::Integer, default: "7", description: "Days after which a blacklisted model is tried again" },
+    SettingSpec { key: "jail.rate_limit_minutes", kind: SettingKind::Integer, default: "5", description: "Cool-down of a rate limited model when the response has no Retry-After" },
+    SettingSpec { key: "jail.outage_minutes", kind: SettingKind::Integer, default: "15", description: "Cool-down of a model whose provider returned a server error" },
+    SettingSpec { key: "jail.content_filter_minutes", kind: SettingKind::Integer, default: "60", description: "Cool-down of a model whose moderation refused the diff" },
+    SettingSpec { key: "failover.max_models", kind: SettingKind::Integer, default: "4", description: "Free models tried within one Simple Free attempt" },
+    SettingSpec { key: "failover.hedge_after_secs", kind: SettingKind::Integer, default: "12", description: "Seconds after which a second model is raced against a slow one (0 disables hedging)" },
+    SettingSpec { key: "failover.deadline_secs", kind: SettingKind::Integer, default: "90", description: "Time limit of one Simple Free attempt in seconds" },
+    SettingSpec { key: "retry.base_delay_ms", kind: SettingKind::Integer, default: "1000", description: "Delay before the first retry in milliseconds" },
+    SettingSpec { key: "retry.multiplier", kind: SettingKind::Integer, default: "2", description: "Factor applied to the retry delay for every further retry" },
+    SettingSpec { key: "retry.max_delay_secs", kind: SettingKind::Integer, default: "30", description: "Longest delay between two tries in seconds" },
+    SettingSpec { key: "retry.jitter_percent", kind: SettingKind::Integer, default: "30", description: "Randomized share of each retry delay in percent" },
+    SettingSpec { key: "retry.deadline_secs", kind: SettingKind::Integer, default: "180", description: "No retry starts later than this many seconds after the first try" },
+    SettingSpec { key: "retry.retryable", kind: SettingKind::List, default: "[\"rate_limit\", \"outage\", \"content_filter\", \"bad_output\", \"transport\"]", description: "Failure kinds that are retried (auth errors never are)" },
+    SettingSpec { key: "scan.mode", kind: SettingKind::String, default: "\"redact\"", description: "What to do with likely secrets in the diff: redact, block, warn or off" },
+    SettingSpec { key: "scan.patterns", kind: SettingKind::List, default: "[]", description: "Extra secret patterns (regular expressions; a capture group marks the secret)" },
+    SettingSpec { key: "scan.allow", kind: SettingKind::List, default: "[]", description: "Patterns of matches that are not secrets, e.g. test fixtures" },
+    SettingSpec { key: "scan.entropy", kind: SettingKind::Bool, default: "true", description: "Also flag long random-looking strings" },
+    SettingSpec { key: "scan.skip_local", kind: SettingKind::Bool, default: "true", description: "Don't scan diffs sent to providers on localhost" },
+    SettingSpec { key: "egress.allow_remote", kind: SettingKind::Bool, default: "true", description: "Allow sending diffs to providers that aren't on localhost" },
+    SettingSpec { key: "egress.only_providers", kind: SettingKind::List, default: "[]", description: "Provider ids or types allowed to receive diffs (empty allows all)" },
+    SettingSpec { key: "egress.local_only_paths", kind: SettingKind::List, default: "[]", description: "Gitignore-style patterns of paths whose changes only local providers may see" },
+    SettingSpec { key: "http.proxy", kind: SettingKind::String, default: "", description: "Proxy URL for all requests (default: HTTP(S)_PROXY from the environment)" },
+    SettingSpec { key: "http.no_proxy", kind: SettingKind::List, default: "[\"localhost\", \"127.0.0.1\", \"::1\"]", description: "Hosts reached without http.proxy" },
+    SettingSpec { key: "http.ca_certs", kind: SettingKind::List, default: "[]", description: "PEM files with extra root certificates" },
+    SettingSpec { key: "http.client_cert", kind: SettingKind::String, default: "", description: "PEM client certificate for mutual TLS" },
+    SettingSpec { key: "http.client_key", kind: SettingKind::String, default: "", description: "PEM private key of http.client_cert" },
+    SettingSpec { key: "http.connect_timeout_secs", kind: SettingKind::Integer, default: "10", description: "Connection timeout in seconds" },
+    SettingSpec { key: "http.timeout_secs", kind: SettingKind::Integer, default: "120", description: "Request timeout in seconds" },
+    SettingSpec { key: "http.user_agent", kind: SettingKind::String, default: "", description: "User-Agent header (default: aicommit/<version>)" },
+];
+
+/// Settings a cloned repository's .aicommit.toml may not set: they decide where requests,
+/// the API key among them, go and which diffs may leave the machine
+const NOT_FROM_REPOSITORY: &[&str] = &["http.proxy", "http.no_proxy", "http.ca_certs", "http.client_cert", "http.client_key", "egress."];
+
+fn allowed_from_repository(key: &str) -> bool {
+    !NOT_FROM_REPOSITORY.iter().any(|denied| if denied.ends_with('.') { key.starts_with(denied) } else { key == *denied })
+}
+
+/// Where an effective setting value came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingOrigin {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    Repository(PathBuf),
+    Environment(String),
+    CommandLine(String),
+}
+
+impl fmt::Display for SettingOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingOrigin::Default => write!(f, "default"),
+            SettingOrigin::System(path) => write!(f, "system ({})", path.display()),
+            SettingOrigin::User(path) => write!(f, "user ({})", path.display()),
+            SettingOrigin::Repository(path) => write!(f, "repository ({})", path.display()),
+            SettingOrigin::Environment(var) => write!(f, "environment ({})", var),
+            SettingOrigin::CommandLine(flag) => write!(f, "command line ({})", flag),
+        }
+    }
+}
+
+/// The merged, effective settings
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    values: BTreeMap<String, (toml::Value, SettingOrigin)>,
+}
+
+impl Settings {
+    /// Load all layers including command line flags
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let mut settings = Self::load_without_cli()?;
+        settings.apply_cli(cli);
+        Ok(settings)
+    }
+
+    /// Load defaults, files and environment (for code paths that have no parsed command line)
+    pub fn load_without_cli() -> Result<Self, String> {
+        let mut settings = Self::defaults();
+
+        let system_path = PathBuf::from("/etc/aicommit/config.toml");
+        settings.merge_file(&system_path, SettingOrigin::System(system_path.clone()))?;
+
+        if let Some(user_path) = user_settings_path() {
+            settings.merge_file(&user_path, SettingOrigin::User(user_path.clone()))?;
+        }
+
+        if let Some(repo_path) = repository_settings_path() {
+            settings.merge_file(&repo_path, SettingOrigin::Repository(repo_path.clone()))?;
+        }
+
+        settings.merge_env(|name| std::env::var(name).ok())?;
+        Ok(settings)
+    }
+
+    /// Only the built-in defaults
+    pub fn defaults() -> Self {
+        let mut settings = Settings::default();
+        for spec in KNOWN_SETTINGS {
+            if spec.default.is_empty() {
+                continue;
+            }
+            if let Ok(value) = parse_toml_literal(spec.default) {
+                settings.values.insert(spec.key.to_string(), (value, SettingOrigin::Default));
+            }
+        }
+        settings
+    }
+
+    fn merge_file(&mut self, path: &Path, origin: SettingOrigin) -> Result<(), String> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        self.merge_toml(&content, origin)
+            .map_err(|e| format!("Invalid settings in {}: {}", path.display(), e))
+    }
+
+    fn merge_toml(&mut self, content: &str, origin: SettingOrigin) -> Result<(), String> {
+        let table: toml::Table = content.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut flat = Vec::new();
+        flatten_table("", &table, &mut flat)?;
+
+        for (key, value) in flat {
+            let spec = find_spec(&key).ok_or_else(|| format!("unknown setting '{}'", key))?;
+            check_kind(spec, &value)?;
+            if matches!(origin, SettingOrigin::Repository(_)) && !allowed_from_repository(&key) {
+                eprintln!("Warning: ignoring {} from {}, set it in the user or system settings instead", key, origin);
+                continue;
+            }
+            self.values.insert(key, (value, origin.clone()));
+        }
+        Ok(())
+    }
+
+    fn merge_env<F: Fn(&str) -> Option<String>>(&mut self, lookup: F) -> Result<(), String> {
+        for spec in KNOWN_SETTINGS {
+            let var = env_var_name(spec.key);
+            let Some(raw) = lookup(&var) else { continue };
+
+            let value = match spec.kind {
+                SettingKind::Bool => match raw.trim().to_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => toml::Value::Boolean(true),
+                    "0" | "false" | "no" | "off" => toml::Value::Boolean(false),
+                    _ => return Err(format!("{} must be true or false, got '{}'", var, raw)),
+                },
+                SettingKind::Integer => toml::Value::Integer(raw.trim().parse()
+                    .map_err(|_| format!("{} must be an integer, got '{}'", var, raw))?),
+                SettingKind::String => toml::Value::String(raw),
+                SettingKind::List => toml::Value::Array(raw.split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| toml::Value::String(s.to_string()))
+                    .collect()),
+            };
+            self.values.insert(spec.key.to_string(), (value, SettingOrigin::Environment(var)));
+        }
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        let git = cli.git_args();
+        let flags = [
+            ("git.push", git.push, "--push"),
+            ("git.push_all", git.push_all, "--push-all"),
+            ("git.pull", git.pull, "--pull"),
+        ];
+        for (key, enabled, flag) in flags {
+            if enabled {
+                self.values.insert(key.to_string(), (toml::Value::Boolean(true), SettingOrigin::CommandLine(flag.to_string())));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.values.get(key).map(|(value, _)| value)
+    }
+
+    pub fn origin(&self, key: &str) -> Option<&SettingOrigin> {
+        self.values.get(key).map(|(_, origin)| origin)
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get(key).and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(|v| v.as_integer())
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty())
+    }
+
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .and_then(|v| v.as_array())
+            .map(|items| items.iter().filter_map(|i| i.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default()
+    }
+
+    /// Print every known setting with its effective value, optionally with its origin
+    pub fn print(&self, with_origin: bool) {
+        for spec in KNOWN_SETTINGS {
+            let value = self.get(spec.key)
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "<unset>".to_string());
+
+            println!("# {}", spec.description);
+            if with_origin {
+                let origin = self.origin(spec.key)
+                    .map(|o| o.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                println!("{} = {}  # {}", spec.key, value, origin);
+            } else {
+                println!("{} = {}", spec.key, value);
+            }
+        }
+    }
+}
+
+fn find_spec(key: &str) -> Option<&'static SettingSpec> {
+    KNOWN_SETTINGS.iter().find(|spec| spec.key == key)
+}
+
+fn check_kind(spec: &SettingSpec, value: &toml::Value) -> Result<(), String> {
+    let ok = match spec.kind {
+        SettingKind::Bool => value.is_bool(),
+        SettingKind::Integer => value.is_integer(),
+        SettingKind::String => value.is_str(),
+        SettingKind::List => value.as_array().is_some_and(|items| items.iter().all(|i| i.is_str())),
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("setting '{}' expects a {:?} value", spec.key, spec.kind).to_lowercase())
+    }
+}
+
+/// Flatten nested tables into dotted keys ("git.push")
+fn flatten_table(prefix: &str, table: &toml::Table, out: &mut Vec<(String, toml::Value)>) -> Result<(), String> {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match value {
+            toml::Value::Table(inner) => flatten_table(&key, inner, out)?,
+            _ => out.push((key, value.clone())),
+        }
+    }
+    Ok(())
+}
+
+fn parse_toml_literal(literal: &str) -> Result<toml::Value, String> {
+    let table: toml::Table = format!("v = {}", literal).parse()
+        .map_err(|e: toml::de::Error| e.to_string())?;
+    table.get("v").cloned().ok_or_else(|| "empty literal".to_string())
+}
+
+/// Environment variable name for a setting key: "git.push" -> "AICOMMIT_GIT_PUSH"
+pub fn env_var_name(key: &str) -> String {
+    format!("AICOMMIT_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// ~/.config/aicommit (or $XDG_CONFIG_HOME/aicommit)
+pub fn user_config_dir() -> Option<PathBuf> {
+    if let Ok(xdg) = std::env::var("XDG_CONFIG_HOME") {
+        if !xdg.is_empty() {
+            return Some(PathBuf::from(xdg).join("aicommit"));
+        }
+    }
+    dirs::home_dir().map(|home| home.join(".config").join("aicommit"))
+}
+
+fn user_settings_path() -> Option<PathBuf> {
+    user_config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Root directory of the current git repository, if any
+pub fn repository_root() -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if root.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(root))
+    }
+}
+
+fn repository_settings_path() -> Option<PathBuf> {
+    repository_root().map(|root| root.join(".aicommit.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_precedence_and_origin() {
+        let mut settings = Settings::defaults();
+        assert_eq!(settings.get_int("lint.max_subject_length"), Some(72));
+        assert_eq!(settings.origin("git.push"), Some(&SettingOrigin::Default));
+
+        let user = PathBuf::from("/home/me/.config/aicommit/config.toml");
+        settings.merge_toml("[git]\npush = true\n[lint]\nmax_subject_length = 50\n", SettingOrigin::User(user.clone())).unwrap();
+
+        let repo = PathBuf::from("/repo/.aicommit.toml");
+        settings.merge_toml("[lint]\nmax_subject_length = 60\n", SettingOrigin::Repository(repo.clone())).unwrap();
+
+        settings.merge_env(|name| (name == "AICOMMIT_GIT_PUSH").then(|| "false".to_string())).unwrap();
+
+        assert_eq!(settings.get_int("lint.max_subject_length"), Some(60));
+        assert_eq!(settings.origin("lint.max_subject_length"), Some(&SettingOrigin::Repository(repo)));
+        assert!(!settings.get_bool("git.push"));
+        assert_eq!(settings.origin("git.push"), Some(&SettingOrigin::Environment("AICOMMIT_GIT_PUSH".to_string())));
+    }
+
+    #[test]
+    fn test_repository_cannot_redirect_requests() {
+        let mut settings = Settings::defaults();
+        let user = PathBuf::from("/home/me/.config/aicommit/config.toml");
+        settings.merge_toml("[egress]\nallow_remote = false\n", SettingOrigin::User(user.clone())).unwrap();
+
+        let repo = PathBuf::from("/repo/.aicommit.toml");
+        settings.merge_toml("[http]\nproxy = \"http://evil:8080\"\nca_certs = [\"evil.pem\"]\ntimeout_secs = 30\n[egress]\nallow_remote = true\n",
+            SettingOrigin::Repository(repo)).unwrap();
+
+        assert_eq!(settings.get_str("http.proxy"), None);
+        assert!(settings.get_list("http.ca_certs").is_empty());
+        assert_eq!(settings.get_int("http.timeout_secs"), Some(30));
+        assert!(!settings.get_bool("egress.allow_remote"));
+        assert_eq!(settings.origin("egress.allow_remote"), Some(&SettingOrigin::User(user)));
+    }
+
+    #[test]
+    fn test_unknown_and_mistyped_settings_are_rejected() {
+        let mut settings = Settings::defaults();
+        assert!(settings.merge_toml("[git]\npsuh = true\n", SettingOrigin::Default).is_err());
+        assert!(settings.merge_toml("[git]\npush = \"yes\"\n", SettingOrigin::Default).is_err());
+        assert!(settings.merge_env(|name| (name == "AICOMMIT_LINT_MAX_SUBJECT_LENGTH").then(|| "long".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_env_list_parsing() {
+        let mut settings = Settings::defaults();
+        settings.merge_env(|name| (name == "AICOMMIT_IGNORE_PATTERNS").then(|| "*.snap, fixtures/**".to_string())).unwrap();
+        assert_eq!(settings.get_list("ignore.patterns"), vec!["*.snap".to_string(), "fixtures/**".to_string()]);
+    }
+}
@@ -0,0 +1,219 @@
+// Runtime state - model statistics that change on every run live in ~/.config/aicommit/state.json
+// instead of the user-edited ~/.aicommit.json. Every update is a locked read-modify-write so
+// concurrent aicommit processes (hooks, watchers, several terminals) don't lose each other's changes.
+
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::types::{Config, ModelStats, ProviderConfig, SimpleFreeOpenRouterConfig};
+use crate::settings::user_config_dir;
+use crate::utils::{with_file_lock, write_private_file};
+
+/// Runtime state for all providers, keyed by provider id
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct State {
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderState>,
+}
+
+/// Runtime state of a single provider
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderState {
+    #[serde(default)]
+    pub model_stats: HashMap<String, ModelStats>,
+    #[serde(default)]
+    pub last_used_model: Option<String>,
+    #[serde(default = "chrono::Utc::now")]
+    pub last_config_update: chrono::DateTime<chrono::Utc>,
+}
+
+impl Default for ProviderState {
+    fn default() -> Self {
+        Self {
+            model_stats: HashMap::new(),
+            last_used_model: None,
+            last_config_update: chrono::Utc::now(),
+        }
+    }
+}
+
+impl ProviderState {
+    fn apply_to(&self, config: &mut SimpleFreeOpenRouterConfig) {
+        config.model_stats = self.model_stats.clone();
+        config.last_used_model = self.last_used_model.clone();
+        config.last_config_update = self.last_config_update;
+    }
+}
+
+/// ~/.config/aicommit/state.json
+pub fn state_path() -> Result<PathBuf, String> {
+    user_config_dir()
+        .map(|dir| dir.join("state.json"))
+        .ok_or_else(|| "Could not find home directory".to_string())
+}
+
+impl State {
+    pub fn load() -> Result<Self, String> {
+        load_from(&state_path()?)
+    }
+}
+
+/// Fill the runtime fields of every Simple Free provider from the state file
+pub fn apply_state(config: &mut Config) -> Result<(), String> {
+    apply(&State::load()?, config);
+    Ok(())
+}
+
+/// `apply_state` for a config migrated in memory only: the runtime state taken out of the old
+/// file fills in for providers the state file doesn't know yet. Nothing is written.
+pub fn apply_state_with_legacy(config: &mut Config, runtime_state: serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+    let mut state = State::load()?;
+    merge_legacy(&mut state, runtime_state)?;
+    apply(&state, config);
+    Ok(())
+}
+
+fn apply(state: &State, config: &mut Config) {
+    for provider in &mut config.providers {
+        if let ProviderConfig::SimpleFreeOpenRouter(simple) = provider {
+            if let Some(provider_state) = state.providers.get(&simple.id) {
+                provider_state.apply_to(simple);
+            }
+        }
+    }
+}
+
+/// Move runtime state taken out of an old config file into the state file.
+/// Entries already present in the state file win.
+pub fn import_legacy_state(runtime_state: serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+    if runtime_state.is_empty() {
+        return Ok(());
+    }
+
+    update_state_at(&state_path()?, |state| merge_legacy(state, runtime_state))
+}
+
+fn merge_legacy(state: &mut State, runtime_state: serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+    for (id, value) in runtime_state {
+        if let btree_map::Entry::Vacant(entry) = state.providers.entry(id) {
+            let provider_state: ProviderState = serde_json::from_value(value)
+                .map_err(|e| format!("Failed to import model statistics for provider {}: {}", entry.key(), e))?;
+            entry.insert(provider_state);
+        }
+    }
+    Ok(())
+}
+
+/// Update one provider's state under the lock, then refresh `config` with the latest state
+/// (which may include changes made by other processes in the meantime)
+pub fn update_provider_state<T>(
+    config: &mut SimpleFreeOpenRouterConfig,
+    f: impl FnOnce(&mut ProviderState) -> T,
+) -> Result<T, String> {
+    let (result, latest) = update_state_at(&state_path()?, |state| {
+        let provider_state = state.providers.entry(config.id.clone()).or_default();
+        let result = f(provider_state);
+        Ok((result, provider_state.clone()))
+    })?;
+
+    latest.apply_to(config);
+    Ok(result)
+}
+
+/// Update the statistics of a single model, e.g. `update_model_stats(config, &model, record_model_failure)`
+pub fn update_model_stats(
+    config: &mut SimpleFreeOpenRouterConfig,
+    model: &str,
+    f: impl FnOnce(&mut ModelStats),
+) -> Result<(), String> {
+    update_provider_state(config, |state| f(state.model_stats.entry(model.to_string()).or_default()))
+}
+
+/// Move a provider's runtime state to a new id (`None` drops it)
+pub fn move_provider_state(id: &str, new_id: Option<&str>) -> Result<(), String> {
+    let path = state_path()?;
+    if !path.exists() {
+        return Ok(());
+    }
+
+    update_state_at(&path, |state| {
+        if let (Some(provider_state), Some(new_id)) = (state.providers.remove(id), new_id) {
+            state.providers.insert(new_id.to_string(), provider_state);
+        }
+        Ok(())
+    })
+}
+
+fn update_state_at<T>(path: &Path, f: impl FnOnce(&mut State) -> Result<T, String>) -> Result<T, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+
+    with_file_lock(path, || {
+        let mut state = load_from(path)?;
+        let result = f(&mut state)?;
+
+        let content = serde_json::to_string_pretty(&state)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+        write_private_file(path, &content)?;
+        Ok(result)
+    })
+}
+
+fn load_from(path: &Path) -> Result<State, String> {
+    if !path.exists() {
+        return Ok(State::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read state file {}: {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse state file {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("state.json");
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    update_state_at(&path, |state| {
+                        let provider = state.providers.entry("free".to_string()).or_default();
+                        provider.model_stats.entry("m:free".to_string()).or_default().success_count += 1;
+                        Ok(())
+                    }).unwrap();
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let state = load_from(&path).unwrap();
+        assert_eq!(state.providers["free"].model_stats["m:free"].success_count, 80);
+    }
+
+    #[test]
+    fn test_legacy_state_does_not_override_the_state_file() {
+        let mut state = State::default();
+        state.providers.insert("free".to_string(), ProviderState { last_used_model: Some("current".to_string()), ..ProviderState::default() });
+        let legacy = serde_json::json!({
+            "free": {"last_used_model": "old"},
+            "other": {"last_used_model": "legacy"}
+        });
+        merge_legacy(&mut state, legacy.as_object().unwrap().clone()).unwrap();
+        assert_eq!(state.providers["free"].last_used_model.as_deref(), Some("current"));
+        assert_eq!(state.providers["other"].last_used_model.as_deref(), Some("legacy"));
+    }
+}
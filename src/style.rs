@@ -0,0 +1,296 @@
+// Repository commit style - learned from `git log` by `aicommit style learn`, saved in the
+// repository root and used by the prompt and the lint rules
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use crate::settings::repository_root;
+use crate::utils::write_private_file;
+use crate::{STYLE_EXAMPLES, STYLE_MIN_SHARE};
+
+/// Name of the style profile in the repository root
+pub const STYLE_FILE_NAME: &str = ".aicommit-style.json";
+
+/// Subject conventions that `style learn` recognises
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Convention {
+    /// `feat(api): add login`
+    Conventional,
+    /// `:sparkles: Add login` or `✨ Add login`
+    Gitmoji,
+    /// `[api] Add login`
+    Component,
+    /// `PROJ-123 Add login`
+    Ticket,
+    Freeform,
+}
+
+impl Convention {
+    /// Checked in this order, the first one most subjects follow wins
+    const DETECTED: [Convention; 4] = [Convention::Conventional, Convention::Gitmoji, Convention::Component, Convention::Ticket];
+
+    /// Pattern of a subject following the convention; group 1 is the prefix, group 2 the description
+    pub fn pattern(self) -> Option<&'static Regex> {
+        static CONVENTIONAL: OnceLock<Regex> = OnceLock::new();
+        static GITMOJI: OnceLock<Regex> = OnceLock::new();
+        static COMPONENT: OnceLock<Regex> = OnceLock::new();
+        static TICKET: OnceLock<Regex> = OnceLock::new();
+        let (cell, pattern) = match self {
+            Convention::Conventional => (&CONVENTIONAL, r"^([a-z]+)(?:\([^)]+\))?!?: (\S.*)$"),
+            Convention::Gitmoji => (&GITMOJI, r"^(:[a-z0-9_+-]+:|\p{Extended_Pictographic}\x{FE0F}?) ?(\S.*)$"),
+            Convention::Component => (&COMPONENT, r"^\[([^\]]+)\] (\S.*)$"),
+            Convention::Ticket => (&TICKET, r"^([A-Z][A-Z0-9]+)-\d+:? (\S.*)$"),
+            Convention::Freeform => return None,
+        };
+        Some(cell.get_or_init(|| Regex::new(pattern).unwrap()))
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Convention::Conventional => "Conventional Commits subjects (type(scope): description)",
+            Convention::Gitmoji => "gitmoji subjects starting with an emoji",
+            Convention::Component => "subjects starting with the component in brackets ([component] Subject)",
+            Convention::Ticket => "subjects starting with the ticket ID (PROJ-123 Subject)",
+            Convention::Freeform => "plain subjects without a prefix",
+        }
+    }
+}
+
+/// How a repository writes its commit subjects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyleProfile {
+    pub convention: Convention,
+    /// Most used prefixes: types, emoji, components or ticket projects
+    pub prefixes: Vec<String>,
+    /// The description starts with a capital letter
+    pub capitalized: bool,
+    pub trailing_period: bool,
+    /// Median subject length in characters
+    pub typical_length: usize,
+    /// Length that 90% of the subjects stay within
+    pub max_length: usize,
+    /// ISO 639-1 code of the language the subjects are written in
+    pub language: String,
+    /// Subjects usually contain an emoji
+    pub emoji: bool,
+    /// Recent subjects that follow the convention
+    pub examples: Vec<String>,
+    pub commits_analysed: usize,
+}
+
+impl StyleProfile {
+    /// Infer the style of a list of commit subjects, newest first
+    pub fn learn(subjects: &[String]) -> Result<Self, String> {
+        let subjects: Vec<&str> = subjects.iter().map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+        if subjects.is_empty() {
+            return Err("No commits to learn a style from".to_string());
+        }
+        let share = |count: usize| count as f64 / subjects.len() as f64;
+
+        let convention = Convention::DETECTED.into_iter()
+            .find(|convention| {
+                let pattern = convention.pattern().unwrap();
+                share(subjects.iter().filter(|subject| pattern.is_match(subject)).count()) >= STYLE_MIN_SHARE
+            })
+            .unwrap_or(Convention::Freeform);
+
+        // Split each subject into prefix and description
+        let pattern = convention.pattern();
+        let parts: Vec<(Option<&str>, &str)> = subjects.iter().map(|subject| {
+            match pattern.as_ref().and_then(|pattern| pattern.captures(subject)) {
+                Some(captures) => (Some(captures.get(1).unwrap().as_str()), captures.get(2).unwrap().as_str()),
+                None => (None, *subject),
+            }
+        }).collect();
+
+        let mut prefix_counts: HashMap<&str, usize> = HashMap::new();
+        for prefix in parts.iter().filter_map(|(prefix, _)| *prefix) {
+            *prefix_counts.entry(prefix).or_default() += 1;
+        }
+        let mut prefixes: Vec<(&str, usize)> = prefix_counts.into_iter().collect();
+        prefixes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let capitalized = parts.iter().filter(|(_, description)| description.starts_with(char::is_uppercase)).count();
+        let trailing_period = subjects.iter().filter(|subject| subject.ends_with('.')).count();
+        static EMOJI: OnceLock<Regex> = OnceLock::new();
+        let emoji = EMOJI.get_or_init(|| Regex::new(r"\p{Extended_Pictographic}|:[a-z0-9_+-]+:").unwrap());
+        let with_emoji = subjects.iter().filter(|subject| emoji.is_match(subject)).count();
+
+        let mut lengths: Vec<usize> = subjects.iter().map(|subject| subject.chars().count()).collect();
+        lengths.sort_unstable();
+        let percentile = |p: f64| lengths[((lengths.len() - 1) as f64 * p).round() as usize];
+
+        Ok(Self {
+            convention,
+            prefixes: prefixes.into_iter().take(8).map(|(prefix, _)| prefix.to_string()).collect(),
+            capitalized: share(capitalized) >= 0.5,
+            trailing_period: share(trailing_period) >= 0.5,
+            typical_length: percentile(0.5),
+            max_length: percentile(0.9),
+            language: detect_language(&subjects.join("\n")).to_string(),
+            emoji: share(with_emoji) >= 0.5,
+            examples: parts.iter().zip(&subjects)
+                .filter(|((prefix, _), _)| prefix.is_some() || convention == Convention::Freeform)
+                .map(|(_, subject)| subject.to_string())
+                .take(STYLE_EXAMPLES)
+                .collect(),
+            commits_analysed: subjects.len(),
+        })
+    }
+
+    /// One sentence for the prompt
+    pub fn describe(&self) -> String {
+        let mut traits = vec![self.convention.describe().to_string()];
+        if !self.prefixes.is_empty() {
+            traits.push(format!("common prefixes: {}", self.prefixes.join(", ")));
+        }
+        traits.push(if self.capitalized { "the description starts with a capital letter" } else { "the description starts with a lowercase letter" }.to_string());
+        traits.push(if self.trailing_period { "subjects end with a period" } else { "no period at the end" }.to_string());
+        traits.push(format!("usually about {} characters, at most {}", self.typical_length, self.max_length));
+        if self.emoji && self.convention != Convention::Gitmoji {
+            traits.push("with an emoji".to_string());
+        }
+        traits.push(format!("written in {}", language_name(&self.language)));
+        traits.join("; ")
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        repository_root().map(|root| root.join(STYLE_FILE_NAME))
+    }
+
+    /// The profile of the current repository, if `style learn` saved one
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()?).ok()?;
+        serde_json::from_str(&content)
+            .map_err(|e| eprintln!("Warning: ignoring invalid {}: {}", STYLE_FILE_NAME, e))
+            .ok()
+    }
+}
+
+/// Guess the language of a text from its script and common words: "ru", "de" or "en"
+pub fn detect_language(text: &str) -> &'static str {
+    detect_language_strict(text).unwrap_or("en")
+}
+
+/// Like `detect_language`, but None when the text gives no hint either way
+pub fn detect_language_strict(text: &str) -> Option<&'static str> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let cyrillic = letters.iter().filter(|c| ('\u{0400}'..='\u{04FF}').contains(*c)).count();
+    if cyrillic * 3 >= letters.len() {
+        return Some("ru");
+    }
+
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()).collect();
+    let count = |list: &[&str]| words.iter().filter(|word| list.contains(word)).count();
+    let german = count(&["und", "der", "die", "das", "nicht", "für", "mit", "von", "beim", "hinzugefügt", "behoben", "entfernt", "aktualisiert"])
+        + lower.chars().filter(|c| "äöüß".contains(*c)).count();
+    let english = count(&["the", "and", "for", "with", "from", "add", "fix", "update", "remove", "added", "fixed"]);
+    match (german, english) {
+        (0, 0) => None,
+        (german, english) if german > english => Some("de"),
+        _ => Some("en"),
+    }
+}
+
+/// ISO 639-1 codes and English names of the languages commit messages are commonly written in
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("ru", "Russian"),
+    ("de", "German"),
+    ("fr", "French"),
+    ("es", "Spanish"),
+    ("it", "Italian"),
+    ("pt", "Portuguese"),
+    ("pl", "Polish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+    ("ja", "Japanese"),
+];
+
+pub fn language_name(code: &str) -> &str {
+    LANGUAGES.iter().find(|(known, _)| *known == code).map_or(code, |(_, name)| name)
+}
+
+fn recent_subjects(count: usize) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args(["log", "-n", &count.to_string(), "--no-merges", "--format=%s"])
+        .output()
+        .map_err(|e| format!("Failed to run git log: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git log failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+/// `aicommit style learn`: analyse the last `commits` commits and save the profile
+pub fn learn_style(commits: usize) -> Result<(), String> {
+    let profile = StyleProfile::learn(&recent_subjects(commits)?)?;
+    let path = StyleProfile::path().ok_or("Not inside a git repository")?;
+    let json = serde_json::to_string_pretty(&profile).map_err(|e| format!("Failed to serialize style profile: {}", e))?;
+    write_private_file(&path, &format!("{}\n", json))?;
+
+    println!("Learned the commit style from {} commits: {}", profile.commits_analysed, profile.describe());
+    println!("Saved to {}. Prompts and lint rules follow it from now on; delete the file to stop.", path.display());
+    Ok(())
+}
+
+/// `aicommit style show`
+pub fn show_style() -> Result<(), String> {
+    match StyleProfile::load() {
+        Some(profile) => {
+            println!("{}", profile.describe());
+            if !profile.examples.is_empty() {
+                println!("Examples:");
+                for example in &profile.examples {
+                    println!("  {}", example);
+                }
+            }
+        }
+        None => println!("No style profile in this repository (run 'aicommit style learn')"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subjects(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_learn_component_style() {
+        let profile = StyleProfile::learn(&subjects(&[
+            "[api] Add login endpoint",
+            "[ui] Fix button alignment",
+            "[api] Remove legacy token check",
+            "Merge fixes from release",
+        ])).unwrap();
+        assert_eq!(profile.convention, Convention::Component);
+        assert_eq!(profile.prefixes, vec!["api", "ui"]);
+        assert!(profile.capitalized && !profile.trailing_period && !profile.emoji);
+        assert_eq!(profile.language, "en");
+        assert_eq!(profile.examples.len(), 3);
+    }
+
+    #[test]
+    fn test_learn_gitmoji_and_languages() {
+        let profile = StyleProfile::learn(&subjects(&["✨ добавить вход", ":bug: исправить кнопку", "🔥 удалить старый код"])).unwrap();
+        assert_eq!(profile.convention, Convention::Gitmoji);
+        assert!(profile.emoji && !profile.capitalized);
+        assert_eq!(profile.language, "ru");
+
+        assert_eq!(detect_language("Fehler beim Speichern behoben und Tests für den Export hinzugefügt"), "de");
+        assert_eq!(detect_language("fix: handle empty diff"), "en");
+        assert_eq!(StyleProfile::learn(&subjects(&["feat: a", "fix(ui): b"])).unwrap().convention, Convention::Conventional);
+    }
+}
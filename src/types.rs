@@ -1,7 +1,7 @@
 // Types module - structures, enums, and implementations
 
 use serde::{Serialize, Deserialize};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use chrono;
 use tracing::info;
 use std::fs;
@@ -9,87 +9,178 @@ use std::env;
 use std::process::Command;
 use dialoguer::{Select, Input};
 use uuid::Uuid;
-use crate::providers::{setup_openrouter_provider, setup_openai_compatible_provider};
+use crate::providers::{setup_openrouter_provider, setup_openai_compatible_provider, prompt_api_key};
+use crate::utils::{with_file_lock, write_private_file};
+use crate::state;
+use crate::settings::Settings;
+use crate::schema::{self, CURRENT_SCHEMA_VERSION};
 
 // From: 000_struct_Cli.rs
 #[derive(Parser, Debug)]
 #[command(name = "aicommit")]
 #[command(about = "A CLI tool that generates concise and descriptive git commit messages using LLMs", long_about = None)]
-#[command(disable_help_flag = true)]
+#[command(version)]
 #[command(bin_name = "aicommit")]
+#[command(after_help = "Running 'aicommit' without a subcommand is the same as 'aicommit commit'.")]
 pub struct Cli {
-    /// Add a new provider (interactive mode)
-    #[arg(long = "add-provider")]
-    pub add_provider: bool,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
 
-    /// Automatically stage all changes before commit
-    #[arg(long = "add")]
-    pub add: bool,
+    #[command(flatten)]
+    pub commit: CommitArgs,
 
-    /// Add OpenRouter provider non-interactively
-    #[arg(long)]
-    pub add_openrouter: bool,
+    /// Display verbose information
+    #[arg(long = "verbose", global = true)]
+    pub verbose: bool,
 
-    /// OpenRouter API key
-    #[arg(long)]
-    pub openrouter_api_key: Option<String>,
+    /// Skip .gitignore check and creation
+    #[arg(long = "no-gitignore-check", global = true)]
+    pub no_gitignore_check: bool,
 
-    /// OpenRouter model name
-    #[arg(long, default_value = "mistralai/mistral-tiny")]
-    pub openrouter_model: String,
+    /// Skip .aicommitignore filtering (include all files in diff sent to LLM)
+    #[arg(long = "no-aicommitignore", global = true)]
+    pub no_aicommitignore: bool,
 
-    /// Add Simple Free OpenRouter provider (uses best available free models automatically)
-    #[arg(long)]
-    pub add_simple_free: bool,
+    /// Force the use of offline mode (uses fallback model list) for testing purposes
+    #[arg(long, hide = true, global = true)]
+    pub simulate_offline: bool,
 
-    /// Add Ollama provider non-interactively
-    #[arg(long)]
-    pub add_ollama: bool,
+    /// Fetch the OpenRouter model catalogue now instead of using the cached copy
+    #[arg(long = "refresh-models", global = true)]
+    pub refresh_models: bool,
 
-    /// Ollama API URL
-    #[arg(long, default_value = "http://localhost:11434")]
-    pub ollama_url: String,
+    #[command(flatten)]
+    pub legacy: LegacyFlags,
+}
 
-    /// Ollama model name
-    #[arg(long, default_value = "llama2")]
-    pub ollama_model: String,
+#[derive(Subcommand, Debug, Clone)]
+pub enum Commands {
+    /// Generate a commit message for the staged changes and commit (the default)
+    Commit(CommitArgs),
+    /// Watch for changes and commit automatically
+    Watch(WatchArgs),
+    /// Manage configured providers
+    Provider {
+        #[command(subcommand)]
+        action: ProviderCommand,
+    },
+    /// Inspect and manage the free models used by Simple Free mode
+    Models {
+        /// Simple Free provider to manage (defaults to the active one, then the first one)
+        #[arg(long, global = true)]
+        provider: Option<String>,
+        #[command(subcommand)]
+        action: ModelsCommand,
+    },
+    /// Manage the prepare-commit-msg git hook
+    Hook {
+        #[command(subcommand)]
+        action: HookCommand,
+    },
+    /// Non-interactive mode for CI (GitHub Actions): read a diff, print a message
+    Action(ActionArgs),
+    /// Explain which files are left out of the diff sent to the LLM
+    Ignore {
+        #[command(subcommand)]
+        action: IgnoreCommand,
+    },
+    /// Learn the repository's commit style from its history
+    Style {
+        #[command(subcommand)]
+        action: StyleCommand,
+    },
+    /// Inspect, validate and edit the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Print the aicommit man page (roff) to stdout
+    Man,
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    /// Print dynamic completion values, one per line (called by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        kind: CompletionKind,
+    },
+}
 
-    /// Add OpenAI compatible provider non-interactively (e.g., LM Studio, custom endpoints)
-    #[arg(long)]
-    pub add_openai_compatible: bool,
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+}
 
-    /// OpenAI compatible API key
-    #[arg(long)]
-    pub openai_compatible_api_key: Option<String>,
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum CompletionKind {
+    /// IDs of the configured providers
+    Providers,
+    /// Models currently jailed or blacklisted by Simple Free mode
+    JailedModels,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct CommitArgs {
+    /// Automatically stage all changes before commit
+    #[arg(long = "add")]
+    pub add: bool,
+
+    /// Print the generated message without committing
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
 
-    /// OpenAI compatible API URL
+    /// Set the git commit message without using AI (for CI/CD or offline use cases)
     #[arg(long)]
-    pub openai_compatible_api_url: Option<String>,
+    pub msg: Option<String>,
 
-    /// OpenAI compatible model name
-    #[arg(long, default_value = "gpt-3.5-turbo")]
-    pub openai_compatible_model: String,
+    /// Translate the last commit's message (or the --msg message) into this language and
+    /// amend it (or commit with it)
+    #[arg(long = "translate-to", value_name = "LANGUAGE")]
+    pub translate_to: Option<String>,
 
-    /// Max tokens for provider configuration
-    #[arg(long, default_value = "200")]
-    pub max_tokens: i32,
+    #[command(flatten)]
+    pub git: GitArgs,
 
-    /// Temperature for provider configuration
-    #[arg(long, default_value = "0.2")]
-    pub temperature: f32,
+    #[command(flatten)]
+    pub versioning: VersionArgs,
+}
 
-    /// List all providers
-    #[arg(long)]
-    pub list: bool,
+#[derive(Args, Debug, Clone, Default)]
+pub struct WatchArgs {
+    /// Wait for edit delay before committing (e.g. "30s" for 30 seconds)
+    #[arg(long = "wait-for-edit")]
+    pub wait_for_edit: Option<String>,
 
-    /// Set active provider
-    #[arg(long)]
-    pub set: Option<String>,
+    #[command(flatten)]
+    pub git: GitArgs,
 
-    /// Edit configuration file
-    #[arg(long)]
-    pub config: bool,
+    #[command(flatten)]
+    pub versioning: VersionArgs,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct GitArgs {
+    /// Pull changes after commit
+    #[arg(long = "pull")]
+    pub pull: bool,
 
+    /// Automatically push changes after commit
+    #[arg(long = "push")]
+    pub push: bool,
+
+    /// Push changes to all configured remotes (github, gitlab, etc.)
+    #[arg(long = "push-all")]
+    pub push_all: bool,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct VersionArgs {
     /// Path to version file
     #[arg(long = "version-file")]
     pub version_file: Option<String>,
@@ -109,108 +200,431 @@ pub struct Cli {
     /// Update version on GitHub
     #[arg(long = "version-github")]
     pub version_github: bool,
+}
 
-    /// Interactive commit message generation
-    #[arg(long = "dry-run")]
-    pub dry_run: bool,
-
-    /// Pull changes before commit
-    #[arg(long = "pull")]
-    pub pull: bool,
-
-    /// Watch for changes and auto-commit
-    #[arg(long = "watch")]
-    pub watch: bool,
+#[derive(Subcommand, Debug, Clone)]
+pub enum ProviderCommand {
+    /// Add a provider (interactive when no type is given)
+    Add(AddProviderArgs),
+    /// List all providers (* marks the active one)
+    List,
+    /// Set the active provider
+    Use {
+        id: String,
+    },
+    /// Remove a provider
+    Remove {
+        id: String,
+    },
+    /// Change the ID of a provider
+    Rename {
+        id: String,
+        new_id: String,
+    },
+    /// Print a provider's configuration with API keys redacted (defaults to the active provider)
+    Show {
+        id: Option<String>,
+    },
+    /// Edit a single provider in $EDITOR
+    Edit {
+        id: String,
+    },
+    /// Send a small canned diff and report latency and the model's answer (defaults to the active provider)
+    Test {
+        id: Option<String>,
+    },
+}
 
-    /// Wait for edit delay before committing (e.g. "30s" for 30 seconds)
-    #[arg(long = "wait-for-edit")]
-    pub wait_for_edit: Option<String>,
+#[derive(Args, Debug, Clone)]
+pub struct AddProviderArgs {
+    /// Provider type; omit to choose interactively
+    #[arg(value_enum)]
+    pub kind: Option<ProviderKind>,
 
-    /// Automatically push changes after commit
-    #[arg(long = "push")]
-    pub push: bool,
-
-    /// Push changes to all configured remotes (github, gitlab, etc.)
-    #[arg(long = "push-all")]
-    pub push_all: bool,
+    /// API key, or a reference (env:VAR, file:/path, cmd:..., keyring:account)
+    #[arg(long = "api-key")]
+    pub api_key: Option<String>,
 
-    /// Display help information
-    #[arg(long = "help")]
-    pub help: bool,
+    /// Model name (default depends on the provider type)
+    #[arg(long)]
+    pub model: Option<String>,
 
-    /// Display version information
-    #[arg(long = "version")]
-    pub version: bool,
+    /// API URL of an Ollama server or OpenAI compatible endpoint
+    #[arg(long)]
+    pub url: Option<String>,
 
-    /// Display verbose information
-    #[arg(long = "verbose")]
-    pub verbose: bool,
-    
-    /// Skip .gitignore check and creation
-    #[arg(long = "no-gitignore-check")]
-    pub no_gitignore_check: bool,
+    /// Max tokens for provider configuration
+    #[arg(long, default_value = "200")]
+    pub max_tokens: i32,
 
-    /// Skip .aicommitignore filtering (include all files in diff sent to LLM)
-    #[arg(long = "no-aicommitignore")]
-    pub no_aicommitignore: bool,
+    /// Temperature for provider configuration
+    #[arg(long, default_value = "0.2")]
+    pub temperature: f32,
+}
 
-    /// Set the git commit message without using AI. (For CI/CD or offline use cases)
-    #[arg(long)]
-    pub msg: Option<String>,
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    Openrouter,
+    SimpleFree,
+    Ollama,
+    OpenaiCompatible,
+}
 
-    /// Force the use of offline mode (uses fallback model list) for testing purposes
-    #[arg(long, hide = true)]
-    pub simulate_offline: bool,
-    
+#[derive(Subcommand, Debug, Clone)]
+pub enum ModelsCommand {
     /// Show status of all model jails and blacklists
-    #[arg(long = "jail-status")]
-    pub jail_status: bool,
-    
-    /// Release specific model from jail/blacklist (model ID as parameter)
-    #[arg(long = "unjail")]
-    pub unjail: Option<String>,
-    
-    /// Release all models from jail/blacklist
-    #[arg(long = "unjail-all")]
-    pub unjail_all: bool,
+    Status {
+        /// Print machine-readable JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Put a model in jail so it isn't used for a while
+    Jail {
+        /// Model ID, e.g. meta-llama/llama-4-maverick:free
+        model: String,
+        /// How long, e.g. 30m, 12h (defaults to jail.initial_hours)
+        #[arg(long = "for")]
+        duration: Option<String>,
+    },
+    /// Release a model (or all models) from jail/blacklist
+    Unjail {
+        /// Model ID, e.g. meta-llama/llama-4-maverick:free
+        #[arg(required_unless_present = "all")]
+        model: Option<String>,
+        /// Release all models
+        #[arg(long, conflicts_with = "model")]
+        all: bool,
+    },
+    /// Ban a model until it is unjailed (never retried automatically)
+    Blacklist {
+        /// Model ID, e.g. meta-llama/llama-4-maverick:free
+        model: String,
+        /// Lift the ban instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Always use a model while it is available
+    Pin {
+        /// Model ID, e.g. meta-llama/llama-4-maverick:free
+        #[arg(required_unless_present = "clear")]
+        model: Option<String>,
+        /// Remove the pin
+        #[arg(long, conflicts_with = "model")]
+        clear: bool,
+    },
+    /// Show the recent requests of one or all models
+    History {
+        /// Model ID (all models when omitted)
+        model: Option<String>,
+        /// Print machine-readable JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rebuild the free model preference list from OpenRouter's catalogue
+    Update,
+    /// List the models of the cached OpenRouter catalogue with context length and pricing
+    List {
+        /// Only show free models
+        #[arg(long)]
+        free: bool,
+    },
+}
 
-    /// Run in GitHub Action mode (non-interactive, reads from stdin or env vars)
-    #[arg(long = "github-action")]
-    pub github_action: bool,
+#[derive(Subcommand, Debug, Clone)]
+pub enum HookCommand {
+    /// Install prepare-commit-msg hook for automatic AI messages
+    Install,
+    /// Remove the installed hook
+    Uninstall,
+    /// Check if the hook is installed
+    Status,
+}
 
-    /// Input diff from file (for --github-action mode)
+#[derive(Args, Debug, Clone)]
+pub struct ActionArgs {
+    /// Input diff from file
     #[arg(long = "input-diff")]
     pub input_diff: Option<String>,
 
-    /// Read diff from stdin (for --github-action mode)
+    /// Read diff from stdin
     #[arg(long = "stdin")]
     pub stdin: bool,
 
-    /// Output format for --github-action mode (text, json, github)
-    #[arg(long = "output-format", default_value = "text")]
+    /// Analyze commits from GitHub event and suggest improved messages
+    #[arg(long = "analyze-commits")]
+    pub analyze_commits: bool,
+
+    /// Output format
+    #[arg(long = "output-format", default_value = "text", value_parser = ["text", "json", "github"])]
     pub output_format: String,
 
-    /// API key for GitHub Action mode (overrides config)
+    /// API key (overrides OPENROUTER_API_KEY / AICOMMIT_API_KEY)
     #[arg(long = "api-key")]
     pub api_key: Option<String>,
 
-    /// Provider type for GitHub Action mode (openrouter, simple-free, ollama, openai-compatible)
+    /// Provider type: openrouter, simple-free, ollama, openai-compatible (overrides AICOMMIT_PROVIDER)
     #[arg(long = "provider")]
     pub provider: Option<String>,
 
-    /// Model name for GitHub Action mode (overrides config)
+    /// Model name (overrides AICOMMIT_MODEL)
     #[arg(long = "model")]
     pub model: Option<String>,
 
-    /// Analyze commits from GitHub event and suggest improved messages
-    #[arg(long = "analyze-commits")]
-    pub analyze_commits: bool,
+    /// API URL for ollama or openai-compatible (OLLAMA_URL / OPENAI_COMPATIBLE_URL take precedence)
+    #[arg(long = "url")]
+    pub url: Option<String>,
+
+    /// Max tokens for the response
+    #[arg(long, default_value = "200")]
+    pub max_tokens: i32,
 
-    /// Manage Git hooks (install/uninstall prepare-commit-msg hook)
-    #[arg(long = "hook")]
+    /// Temperature for generation
+    #[arg(long, default_value = "0.2")]
+    pub temperature: f32,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Print every effective setting
+    Show {
+        /// Also print where each value came from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Check ~/.aicommit.json for unknown or invalid fields
+    Validate,
+    /// Open ~/.aicommit.json in $EDITOR
+    Edit,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum IgnoreCommand {
+    /// Report whether each path is excluded from the diff and which rule decided it
+    Check {
+        /// Paths relative to the current directory
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum StyleCommand {
+    /// Infer the commit convention from git log and save it to .aicommit-style.json
+    Learn {
+        /// Number of recent commits to analyse
+        #[arg(long, default_value_t = 200)]
+        commits: usize,
+    },
+    /// Print the style profile of the current repository
+    Show,
+}
+
+/// Flags from before the subcommand CLI. They still work but print a deprecation note.
+#[derive(Args, Debug, Clone)]
+pub struct LegacyFlags {
+    #[arg(long = "add-provider", hide = true)]
+    pub add_provider: bool,
+    #[arg(long, hide = true)]
+    pub add_openrouter: bool,
+    #[arg(long, hide = true)]
+    pub openrouter_api_key: Option<String>,
+    #[arg(long, hide = true)]
+    pub openrouter_model: Option<String>,
+    #[arg(long, hide = true)]
+    pub add_simple_free: bool,
+    #[arg(long, hide = true)]
+    pub add_ollama: bool,
+    #[arg(long, hide = true)]
+    pub ollama_url: Option<String>,
+    #[arg(long, hide = true)]
+    pub ollama_model: Option<String>,
+    #[arg(long, hide = true)]
+    pub add_openai_compatible: bool,
+    #[arg(long, hide = true)]
+    pub openai_compatible_api_key: Option<String>,
+    #[arg(long, hide = true)]
+    pub openai_compatible_api_url: Option<String>,
+    #[arg(long, hide = true)]
+    pub openai_compatible_model: Option<String>,
+    #[arg(long, hide = true, default_value = "200")]
+    pub max_tokens: i32,
+    #[arg(long, hide = true, default_value = "0.2")]
+    pub temperature: f32,
+    #[arg(long, hide = true)]
+    pub list: bool,
+    #[arg(long, hide = true)]
+    pub set: Option<String>,
+    #[arg(long, hide = true)]
+    pub config: bool,
+    #[arg(long, hide = true)]
+    pub watch: bool,
+    #[arg(long = "wait-for-edit", hide = true)]
+    pub wait_for_edit: Option<String>,
+    #[arg(long = "jail-status", hide = true)]
+    pub jail_status: bool,
+    #[arg(long = "unjail", hide = true)]
+    pub unjail: Option<String>,
+    #[arg(long = "unjail-all", hide = true)]
+    pub unjail_all: bool,
+    #[arg(long = "github-action", hide = true)]
+    pub github_action: bool,
+    #[arg(long = "input-diff", hide = true)]
+    pub input_diff: Option<String>,
+    #[arg(long = "stdin", hide = true)]
+    pub stdin: bool,
+    #[arg(long = "output-format", hide = true, default_value = "text")]
+    pub output_format: String,
+    #[arg(long = "api-key", hide = true)]
+    pub api_key: Option<String>,
+    #[arg(long = "provider", hide = true)]
+    pub provider: Option<String>,
+    #[arg(long = "model", hide = true)]
+    pub model: Option<String>,
+    #[arg(long = "analyze-commits", hide = true)]
+    pub analyze_commits: bool,
+    #[arg(long = "hook", hide = true)]
     pub hook: Option<String>,
 }
 
+/// A deprecated flag and the command that replaces it
+type DeprecatedFlag = (&'static str, &'static str);
+
+impl Cli {
+    /// Parse the command line, translating deprecated flags into the equivalent subcommand
+    pub fn parse_args() -> Result<Self, String> {
+        let mut cli = Self::parse();
+        if cli.command.is_none() {
+            let (command, deprecated) = cli.legacy_command()?;
+            if let Some((flag, replacement)) = deprecated {
+                eprintln!("Warning: {} is deprecated, use '{}' instead.", flag, replacement);
+            }
+            cli.command = Some(command);
+        }
+        Ok(cli)
+    }
+
+    /// Map the pre-subcommand flags to a subcommand, plus the deprecated flag and its replacement
+    fn legacy_command(&self) -> Result<(Commands, Option<DeprecatedFlag>), String> {
+        let legacy = &self.legacy;
+
+        let add_kind = if legacy.add_openrouter {
+            Some(ProviderKind::Openrouter)
+        } else if legacy.add_simple_free {
+            Some(ProviderKind::SimpleFree)
+        } else if legacy.add_ollama {
+            Some(ProviderKind::Ollama)
+        } else if legacy.add_openai_compatible {
+            Some(ProviderKind::OpenaiCompatible)
+        } else {
+            None
+        };
+
+        let command = if legacy.jail_status {
+            (Commands::Models { provider: None, action: ModelsCommand::Status { json: false } }, Some(("--jail-status", "aicommit models status")))
+        } else if let Some(model) = &legacy.unjail {
+            (Commands::Models { provider: None, action: ModelsCommand::Unjail { model: Some(model.clone()), all: false } }, Some(("--unjail", "aicommit models unjail <MODEL>")))
+        } else if legacy.unjail_all {
+            (Commands::Models { provider: None, action: ModelsCommand::Unjail { model: None, all: true } }, Some(("--unjail-all", "aicommit models unjail --all")))
+        } else if legacy.github_action {
+            (Commands::Action(self.legacy_action_args()), Some(("--github-action", "aicommit action")))
+        } else if let Some(hook) = &legacy.hook {
+            let action = match hook.as_str() {
+                "install" => HookCommand::Install,
+                "uninstall" => HookCommand::Uninstall,
+                "status" => HookCommand::Status,
+                _ => return Err(format!(
+                    "Unknown hook command: '{}'. Valid commands are: install, uninstall, status",
+                    hook
+                )),
+            };
+            (Commands::Hook { action }, Some(("--hook", "aicommit hook install|uninstall|status")))
+        } else if add_kind.is_some() || legacy.add_provider {
+            let (api_key, model, url) = match add_kind {
+                Some(ProviderKind::Openrouter) | Some(ProviderKind::SimpleFree) =>
+                    (legacy.openrouter_api_key.clone(), legacy.openrouter_model.clone(), None),
+                Some(ProviderKind::Ollama) =>
+                    (None, legacy.ollama_model.clone(), legacy.ollama_url.clone()),
+                Some(ProviderKind::OpenaiCompatible) =>
+                    (legacy.openai_compatible_api_key.clone(), legacy.openai_compatible_model.clone(), legacy.openai_compatible_api_url.clone()),
+                None => (None, None, None),
+            };
+            let args = AddProviderArgs {
+                kind: add_kind,
+                api_key,
+                model,
+                url,
+                max_tokens: legacy.max_tokens,
+                temperature: legacy.temperature,
+            };
+            (Commands::Provider { action: ProviderCommand::Add(args) }, Some(("--add-provider/--add-<type>", "aicommit provider add [TYPE]")))
+        } else if legacy.list {
+            (Commands::Provider { action: ProviderCommand::List }, Some(("--list", "aicommit provider list")))
+        } else if let Some(id) = &legacy.set {
+            (Commands::Provider { action: ProviderCommand::Use { id: id.clone() } }, Some(("--set", "aicommit provider use <ID>")))
+        } else if legacy.config {
+            (Commands::Config { action: ConfigCommand::Edit }, Some(("--config", "aicommit config edit")))
+        } else if legacy.watch && !self.commit.dry_run {
+            let args = WatchArgs {
+                wait_for_edit: legacy.wait_for_edit.clone(),
+                git: self.commit.git.clone(),
+                versioning: self.commit.versioning.clone(),
+            };
+            (Commands::Watch(args), Some(("--watch", "aicommit watch")))
+        } else {
+            (Commands::Commit(self.commit.clone()), None)
+        };
+
+        Ok(command)
+    }
+
+    fn legacy_action_args(&self) -> ActionArgs {
+        let legacy = &self.legacy;
+        let provider = legacy.provider.clone();
+        let (url, model) = match provider.as_deref() {
+            Some("ollama") => (legacy.ollama_url.clone(), legacy.model.clone().or_else(|| legacy.ollama_model.clone())),
+            Some("openai-compatible") => (legacy.openai_compatible_api_url.clone(), legacy.model.clone().or_else(|| legacy.openai_compatible_model.clone())),
+            _ => (None, legacy.model.clone()),
+        };
+
+        ActionArgs {
+            input_diff: legacy.input_diff.clone(),
+            stdin: legacy.stdin,
+            analyze_commits: legacy.analyze_commits,
+            output_format: legacy.output_format.clone(),
+            api_key: legacy.api_key.clone(),
+            provider,
+            model,
+            url,
+            max_tokens: legacy.max_tokens,
+            temperature: legacy.temperature,
+        }
+    }
+
+    /// Options of the commit command (the top-level ones when another command runs)
+    pub fn commit_args(&self) -> &CommitArgs {
+        match &self.command {
+            Some(Commands::Commit(args)) => args,
+            _ => &self.commit,
+        }
+    }
+
+    /// Pull/push options of the running commit or watch command
+    pub fn git_args(&self) -> &GitArgs {
+        match &self.command {
+            Some(Commands::Watch(args)) => &args.git,
+            _ => &self.commit_args().git,
+        }
+    }
+
+    /// Version bump options of the running commit or watch command
+    pub fn version_args(&self) -> &VersionArgs {
+        match &self.command {
+            Some(Commands::Watch(args)) => &args.versioning,
+            _ => &self.commit_args().versioning,
+        }
+    }
+}
+
 // From: 006_struct_OpenRouterConfig.rs
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenRouterConfig {
@@ -237,6 +651,56 @@ pub struct ModelStats {
     pub blacklisted: bool,
     #[serde(with = "chrono::serde::ts_seconds_option")]
     pub blacklisted_since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Most recent requests, oldest first (at most RECENT_SAMPLES)
+    #[serde(default)]
+    pub recent: Vec<ModelSample>,
+    /// Banned by hand (`models blacklist`); unlike `blacklisted` never retried automatically
+    #[serde(default)]
+    pub banned: bool,
+    /// Pinned by hand (`models pin`); used whenever it is available
+    #[serde(default)]
+    pub pinned: bool,
+    /// Short break after a rate limit, outage or content filter; unlike a jail term it never escalates
+    #[serde(default, with = "chrono::serde::ts_seconds_option", skip_serializing_if = "Option::is_none")]
+    pub cooldown_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Kind of the last failure ("rate limit", "outage", ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_failure_kind: Option<String>,
+}
+
+// From: 008_impl_impl_Default.rs
+impl Default for ModelStats {
+    fn default() -> Self {
+        Self {
+            success_count: 0,
+            failure_count: 0,
+            last_success: None,
+            last_failure: None,
+            jail_until: None,
+            jail_count: 0,
+            blacklisted: false,
+            blacklisted_since: None,
+            recent: Vec::new(),
+            banned: false,
+            pinned: false,
+            cooldown_until: None,
+            last_failure_kind: None,
+        }
+    }
+}
+
+/// Outcome of a single request to a model
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModelSample {
+    #[serde(default, with = "chrono::serde::ts_seconds_option", skip_serializing_if = "Option::is_none")]
+    pub at: Option<chrono::DateTime<chrono::Utc>>,
+    pub ok: bool,
+    /// Request latency, only measured for successful requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// 1.0 for a message without lint warnings, lower for each warning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lint_score: Option<f64>,
 }
 
 // From: 009_struct_SimpleFreeOpenRouterConfig.rs
@@ -247,13 +711,12 @@ pub struct SimpleFreeOpenRouterConfig {
     pub api_key: String,
     pub max_tokens: i32,
     pub temperature: f32,
-    #[serde(default)]
-    pub failed_models: Vec<String>,
-    #[serde(default)]
+    // Runtime state, persisted in the state file rather than ~/.aicommit.json (see state.rs)
+    #[serde(default, skip_serializing)]
     pub model_stats: std::collections::HashMap<String, ModelStats>,
-    #[serde(default)]
+    #[serde(default, skip_serializing)]
     pub last_used_model: Option<String>,
-    #[serde(default = "chrono::Utc::now")]
+    #[serde(default = "chrono::Utc::now", skip_serializing)]
     pub last_config_update: chrono::DateTime<chrono::Utc>,
 }
 
@@ -297,6 +760,8 @@ pub struct OpenCodeConfig {
 // From: 015_struct_Config.rs
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub schema_version: u32,
     pub providers: Vec<ProviderConfig>,
     pub active_provider: String,
     #[serde(default = "default_retry_attempts")]
@@ -350,20 +815,95 @@ pub enum ProviderConfig {
     OpenCode(OpenCodeConfig),
 }
 
-// From: 008_impl_impl_Default.rs
-impl Default for ModelStats {
-    fn default() -> Self {
-        Self {
-            success_count: 0,
-            failure_count: 0,
-            last_success: None,
-            last_failure: None,
-            jail_until: None,
-            jail_count: 0,
-            blacklisted: false,
-            blacklisted_since: None,
+impl ProviderConfig {
+    pub fn id(&self) -> &str {
+        match self {
+            ProviderConfig::OpenRouter(c) => &c.id,
+            ProviderConfig::Ollama(c) => &c.id,
+            ProviderConfig::OpenAICompatible(c) => &c.id,
+            ProviderConfig::SimpleFreeOpenRouter(c) => &c.id,
+            ProviderConfig::ClaudeCode(c) => &c.id,
+            ProviderConfig::OpenCode(c) => &c.id,
+        }
+    }
+
+    /// Provider type as stored in the config (`ollama`, `openrouter`, ...)
+    pub fn provider(&self) -> &str {
+        match self {
+            ProviderConfig::OpenRouter(c) => &c.provider,
+            ProviderConfig::Ollama(c) => &c.provider,
+            ProviderConfig::OpenAICompatible(c) => &c.provider,
+            ProviderConfig::SimpleFreeOpenRouter(c) => &c.provider,
+            ProviderConfig::ClaudeCode(c) => &c.provider,
+            ProviderConfig::OpenCode(c) => &c.provider,
         }
     }
+
+    /// Human readable provider type
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            ProviderConfig::OpenRouter(_) => "OpenRouter",
+            ProviderConfig::Ollama(_) => "Ollama",
+            ProviderConfig::OpenAICompatible(_) => "OpenAI Compatible",
+            ProviderConfig::SimpleFreeOpenRouter(_) => "Simple Free OpenRouter",
+            ProviderConfig::ClaudeCode(_) => "Claude Code",
+            ProviderConfig::OpenCode(_) => "OpenCode",
+        }
+    }
+
+    /// Configured model; None when the provider picks one itself
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            ProviderConfig::OpenRouter(c) => Some(&c.model),
+            ProviderConfig::Ollama(c) => Some(&c.model),
+            ProviderConfig::OpenAICompatible(c) => Some(&c.model),
+            ProviderConfig::SimpleFreeOpenRouter(_) | ProviderConfig::ClaudeCode(_) | ProviderConfig::OpenCode(_) => None,
+        }
+    }
+
+    /// Where requests go: an API URL or the local CLI tool that is invoked
+    pub fn endpoint(&self) -> &str {
+        match self {
+            ProviderConfig::OpenRouter(_) | ProviderConfig::SimpleFreeOpenRouter(_) => "https://openrouter.ai/api/v1/chat/completions",
+            ProviderConfig::Ollama(c) => &c.url,
+            ProviderConfig::OpenAICompatible(c) => &c.api_url,
+            ProviderConfig::ClaudeCode(_) => "claude CLI",
+            ProviderConfig::OpenCode(_) => "opencode CLI",
+        }
+    }
+
+    pub fn set_id(&mut self, id: &str) {
+        let slot = match self {
+            ProviderConfig::OpenRouter(c) => &mut c.id,
+            ProviderConfig::Ollama(c) => &mut c.id,
+            ProviderConfig::OpenAICompatible(c) => &mut c.id,
+            ProviderConfig::SimpleFreeOpenRouter(c) => &mut c.id,
+            ProviderConfig::ClaudeCode(c) => &mut c.id,
+            ProviderConfig::OpenCode(c) => &mut c.id,
+        };
+        *slot = id.to_string();
+    }
+
+    /// The configured API key value (plaintext or reference), if this provider uses one
+    pub fn api_key(&self) -> Option<&str> {
+        match self {
+            ProviderConfig::OpenRouter(c) => Some(&c.api_key),
+            ProviderConfig::OpenAICompatible(c) => Some(&c.api_key),
+            ProviderConfig::SimpleFreeOpenRouter(c) => Some(&c.api_key),
+            ProviderConfig::Ollama(_) | ProviderConfig::ClaudeCode(_) | ProviderConfig::OpenCode(_) => None,
+        }
+    }
+
+    /// Replace the API key value; providers without one are left alone
+    pub fn set_api_key(&mut self, api_key: &str) {
+        let slot = match self {
+            ProviderConfig::OpenRouter(c) => &mut c.api_key,
+            ProviderConfig::OpenAICompatible(c) => &mut c.api_key,
+            ProviderConfig::SimpleFreeOpenRouter(c) => &mut c.api_key,
+            ProviderConfig::Ollama(_) | ProviderConfig::ClaudeCode(_) | ProviderConfig::OpenCode(_) => return,
+        };
+        *slot = api_key.to_string();
+    }
 }
 
 // From: 016_function_default_retry_attempts.rs
@@ -375,16 +915,27 @@ fn default_retry_attempts() -> u32 {
 impl Config {
     pub fn new() -> Self {
         Config {
+            schema_version: CURRENT_SCHEMA_VERSION,
             providers: Vec::new(),
             active_provider: String::new(),
             retry_attempts: default_retry_attempts(),
         }
     }
 
-    pub fn load() -> Result<Self, String> {
-        let config_path = dirs::home_dir()
+    /// The provider to use: the `active_provider` setting if set, otherwise the one in ~/.aicommit.json
+    pub fn active_provider_config(&self, settings: &Settings) -> Option<&ProviderConfig> {
+        let active_id = settings.get_str("active_provider").unwrap_or(&self.active_provider);
+        self.providers.iter().find(|p| p.id() == active_id)
+    }
+
+    pub fn config_path() -> Result<std::path::PathBuf, String> {
+        Ok(dirs::home_dir()
             .ok_or_else(|| "Could not find home directory".to_string())?
-            .join(".aicommit.json");
+            .join(".aicommit.json"))
+    }
+
+    pub fn load() -> Result<Self, String> {
+        let config_path = Self::config_path()?;
 
         if !config_path.exists() {
             return Ok(Config::new());
@@ -393,21 +944,118 @@ impl Config {
         let content = fs::read_to_string(&config_path)
             .map_err(|e| format!("Failed to read config file: {}", e))?;
 
-        serde_json::from_str(&content)
-            .map_err(|e| format!("Failed to parse config file: {}", e))
+        let value: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+
+        // Files written by older versions are migrated under the config lock, re-read there in
+        // case another process migrated or changed the file in the meantime
+        if schema::schema_version(&value) < CURRENT_SCHEMA_VERSION {
+            return with_file_lock(&config_path, || Self::load_locked(&config_path));
+        }
+        Self::from_value(value)
     }
 
-    pub fn check_gitignore() -> Result<(), String> {
-        if !Cli::parse().no_gitignore_check {
-            // Check if .gitignore exists in current directory
-            if !std::path::Path::new(".gitignore").exists() {
-                // Get default gitignore content
-                let default_content = Self::get_default_gitignore()?;
-                fs::write(".gitignore", default_content)
-                    .map_err(|e| format!("Failed to create .gitignore: {}", e))?;
-                info!("Created default .gitignore file");
+    /// ~/.aicommit.json as `load` returns it, without writing anything: a file written by an
+    /// older version is migrated in memory only (for shell completion, which runs on every TAB)
+    pub fn load_read_only() -> Result<Self, String> {
+        let config_path = Self::config_path()?;
+        if !config_path.exists() {
+            return Ok(Config::new());
+        }
+
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| format!("Failed to read config file: {}", e))?;
+        let mut value: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+
+        let migration = schema::migrate(&mut value)?;
+        let mut config: Config = serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+        state::apply_state_with_legacy(&mut config, migration.runtime_state)?;
+        Ok(config)
+    }
+
+    /// Read ~/.aicommit.json and upgrade it if it was written by an older version, keeping a
+    /// backup of the original. The caller holds the config lock.
+    fn load_locked(config_path: &std::path::Path) -> Result<Self, String> {
+        if !config_path.exists() {
+            return Ok(Config::new());
+        }
+
+        let content = fs::read_to_string(config_path)
+            .map_err(|e| format!("Failed to read config file: {}", e))?;
+
+        let mut value: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse config file: {}", e))?;
+
+        let migration = schema::migrate(&mut value)?;
+        let from_version = migration.from_version;
+        if from_version < CURRENT_SCHEMA_VERSION {
+            let backup_path = config_path.with_extension(format!("json.v{}.bak", from_version));
+            if !backup_path.exists() {
+                write_private_file(&backup_path, &content)
+                    .map_err(|e| format!("Failed to back up config file before migration: {}", e))?;
+            }
+
+            state::import_legacy_state(migration.runtime_state)?;
+
+            let migrated = serde_json::to_string_pretty(&value)
+                .map_err(|e| format!("Failed to serialize migrated config: {}", e))?;
+            write_private_file(config_path, &migrated)
+                .map_err(|e| format!("Failed to write migrated config file: {}", e))?;
+
+            eprintln!("Migrated {} from schema v{} to v{} (backup: {})",
+                config_path.display(), from_version, CURRENT_SCHEMA_VERSION, backup_path.display());
+        }
+
+        Self::from_value(value)
+    }
+
+    /// A config from a current-schema document, with the runtime state from the state file
+    fn from_value(value: serde_json::Value) -> Result<Self, String> {
+        let mut config: Config = serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse config file: {} (run 'aicommit config validate' for details)", e))?;
+        state::apply_state(&mut config)?;
+        Ok(config)
+    }
+
+    /// Check ~/.aicommit.json and print any problems; returns an error if problems were found
+    pub fn validate() -> Result<(), String> {
+        let config_path = Self::config_path()?;
+        if !config_path.exists() {
+            println!("{} does not exist yet; nothing to validate.", config_path.display());
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&config_path)
+            .map_err(|e| format!("Failed to read config file: {}", e))?;
+
+        let issues = schema::validate_config(&content);
+        if issues.is_empty() {
+            println!("{} is valid.", config_path.display());
+            return Ok(());
+        }
+
+        for issue in &issues {
+            match issue.line {
+                Some(line) => println!("{}:{}: {}", config_path.display(), line, issue.message),
+                None => println!("{}: {}", config_path.display(), issue.message),
             }
         }
+        Err(format!("Found {} problem(s) in {}", issues.len(), config_path.display()))
+    }
+
+    /// Create a default .gitignore in the current directory if there is none.
+    /// Callers skip this when --no-gitignore-check is given.
+    pub fn check_gitignore() -> Result<(), String> {
+        // Check if .gitignore exists in current directory
+        if !std::path::Path::new(".gitignore").exists() {
+            // Get default gitignore content
+            let default_content = Self::get_default_gitignore()?;
+            fs::write(".gitignore", default_content)
+                .map_err(|e| format!("Failed to create .gitignore: {}", e))?;
+            info!("Created default .gitignore file");
+        }
         Ok(())
     }
 
@@ -571,8 +1219,6 @@ nbproject/
     }
 
     pub async fn setup_interactive() -> Result<Self, String> {
-        let mut config = Config::load().unwrap_or_else(|_| Config::new());
-
         info!("Setting up a provider");
         let provider_options = &["Free OpenRouter (recommended)", "OpenRouter", "Ollama", "OpenAI Compatible", "Claude Code", "OpenCode"];
         let provider_selection = Select::new()
@@ -584,12 +1230,9 @@ nbproject/
 
         let provider_id = Uuid::new_v4().to_string();
 
-        match provider_selection {
+        let provider = match provider_selection {
             0 => {
-                let api_key: String = Input::new()
-                    .with_prompt("Enter OpenRouter API key")
-                    .interact_text()
-                    .map_err(|e| format!("Failed to get API key: {}", e))?;
+                let api_key = prompt_api_key("Enter OpenRouter API key", &provider_id)?;
 
                 let max_tokens: String = Input::new()
                     .with_prompt("Enter max tokens")
@@ -613,20 +1256,17 @@ nbproject/
                     api_key,
                     max_tokens,
                     temperature,
-                    failed_models: Vec::new(),
                     model_stats: std::collections::HashMap::new(),
                     last_used_model: None,
                     last_config_update: chrono::Utc::now(),
                 };
 
-                config.providers.push(ProviderConfig::SimpleFreeOpenRouter(simple_free_config));
-                config.active_provider = provider_id;
+                ProviderConfig::SimpleFreeOpenRouter(simple_free_config)
             }
             1 => {
                 let mut openrouter_config = setup_openrouter_provider().await?;
                 openrouter_config.id = provider_id.clone();
-                config.providers.push(ProviderConfig::OpenRouter(openrouter_config));
-                config.active_provider = provider_id;
+                ProviderConfig::OpenRouter(openrouter_config)
             }
             2 => {
                 let url: String = Input::new()
@@ -657,65 +1297,70 @@ nbproject/
                 let temperature: f32 = temperature.parse()
                     .map_err(|e| format!("Failed to parse temperature: {}", e))?;
 
-                config.providers.push(ProviderConfig::Ollama(OllamaConfig {
+                ProviderConfig::Ollama(OllamaConfig {
                     id: provider_id.clone(),
                     provider: "ollama".to_string(),
                     model,
                     url,
                     max_tokens,
                     temperature,
-                }));
-                config.active_provider = provider_id;
+                })
             }
             3 => {
                 let mut openai_compatible_config = setup_openai_compatible_provider().await?;
                 openai_compatible_config.id = provider_id.clone();
-                config.providers.push(ProviderConfig::OpenAICompatible(openai_compatible_config));
-                config.active_provider = provider_id;
+                ProviderConfig::OpenAICompatible(openai_compatible_config)
             }
             4 => {
-                config.providers.push(ProviderConfig::ClaudeCode(ClaudeCodeConfig {
+                ProviderConfig::ClaudeCode(ClaudeCodeConfig {
                     id: provider_id.clone(),
                     provider: "claude_code".to_string(),
-                }));
-                config.active_provider = provider_id;
+                })
             }
             5 => {
-                config.providers.push(ProviderConfig::OpenCode(OpenCodeConfig {
+                ProviderConfig::OpenCode(OpenCodeConfig {
                     id: provider_id.clone(),
                     provider: "opencode".to_string(),
-                }));
-                config.active_provider = provider_id;
+                })
             }
             _ => return Err("Invalid provider selection".to_string()),
-        }
+        };
 
-        // Save the configuration
-        let config_path = dirs::home_dir()
-            .ok_or_else(|| "Could not find home directory".to_string())?
-            .join(".aicommit.json");
+        Self::add_provider(provider, provider_id)
+    }
 
-        let content = serde_json::to_string_pretty(&config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    /// Write the configuration to ~/.aicommit.json, readable only by the current user
+    pub fn save(&self) -> Result<(), String> {
+        let config_path = Self::config_path()?;
+        with_file_lock(&config_path, || self.write_to(&config_path))
+    }
 
-        fs::write(&config_path, content)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
+    /// Re-read ~/.aicommit.json, apply `f` and write it back while holding the config lock,
+    /// so changes made by other processes since this one started are not overwritten
+    pub fn update(f: impl FnOnce(&mut Config) -> Result<(), String>) -> Result<Config, String> {
+        let config_path = Self::config_path()?;
+        with_file_lock(&config_path, || {
+            let mut config = Self::load_locked(&config_path)?;
+            f(&mut config)?;
+            config.write_to(&config_path)?;
+            Ok(config)
+        })
+    }
 
-        Ok(config)
+    fn write_to(&self, path: &std::path::Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+
+        write_private_file(path, &content)
+            .map_err(|e| format!("Failed to write config file: {}", e))
     }
 
     pub fn edit() -> Result<(), String> {
         let editor = env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
-        let config_path = dirs::home_dir()
-            .ok_or_else(|| "Could not find home directory".to_string())?
-            .join(".aicommit.json");
+        let config_path = Self::config_path()?;
 
         if !config_path.exists() {
-            let default_config = Config::new();
-            let content = serde_json::to_string_pretty(&default_config)
-                .map_err(|e| format!("Failed to serialize default config: {}", e))?;
-            fs::write(&config_path, content)
-                .map_err(|e| format!("Failed to write default config: {}", e))?;
+            Config::new().save()?;
         }
 
         let status = Command::new(editor)
@@ -730,83 +1375,112 @@ nbproject/
         Ok(())
     }
 
-    pub async fn setup_non_interactive(cli: &Cli) -> Result<Self, String> {
-        let mut config = Config::load().unwrap_or_else(|_| Config::new());
+    pub async fn setup_non_interactive(args: &AddProviderArgs, kind: ProviderKind) -> Result<Self, String> {
         let provider_id = Uuid::new_v4().to_string();
 
-        if cli.add_openrouter {
-            let api_key = cli.openrouter_api_key.clone()
-                .ok_or_else(|| "OpenRouter API key is required".to_string())?;
+        let provider = match kind {
+            ProviderKind::Openrouter => {
+                let api_key = args.api_key.clone()
+                    .ok_or_else(|| "OpenRouter API key is required (--api-key)".to_string())?;
 
-            let openrouter_config = OpenRouterConfig {
-                id: provider_id.clone(),
-                provider: "openrouter".to_string(),
-                api_key,
-                model: cli.openrouter_model.clone(),
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
-            };
-            config.providers.push(ProviderConfig::OpenRouter(openrouter_config));
-            config.active_provider = provider_id;
-        } else if cli.add_simple_free {
-            let api_key = cli.openrouter_api_key.clone()
-                .ok_or_else(|| "OpenRouter API key is required".to_string())?;
+                let openrouter_config = OpenRouterConfig {
+                    id: provider_id.clone(),
+                    provider: "openrouter".to_string(),
+                    api_key,
+                    model: args.model.clone().unwrap_or_else(|| "mistralai/mistral-tiny".to_string()),
+                    max_tokens: args.max_tokens,
+                    temperature: args.temperature,
+                };
+                ProviderConfig::OpenRouter(openrouter_config)
+            }
+            ProviderKind::SimpleFree => {
+                let api_key = args.api_key.clone()
+                    .ok_or_else(|| "OpenRouter API key is required (--api-key)".to_string())?;
 
-            let simple_free_config = SimpleFreeOpenRouterConfig {
-                id: provider_id.clone(),
-                provider: "simple_free_openrouter".to_string(),
-                api_key,
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
-                failed_models: Vec::new(),
-                model_stats: std::collections::HashMap::new(),
-                last_used_model: None,
-                last_config_update: chrono::Utc::now(),
-            };
-            config.providers.push(ProviderConfig::SimpleFreeOpenRouter(simple_free_config));
-            config.active_provider = provider_id;
-        } else if cli.add_ollama {
-            let ollama_config = OllamaConfig {
-                id: provider_id.clone(),
-                provider: "ollama".to_string(),
-                model: cli.ollama_model.clone(),
-                url: cli.ollama_url.clone(),
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
-            };
-            config.providers.push(ProviderConfig::Ollama(ollama_config));
-            config.active_provider = provider_id;
-        } else if cli.add_openai_compatible {
-            let api_key = cli.openai_compatible_api_key.clone()
-                .ok_or_else(|| "OpenAI compatible API key is required".to_string())?;
-            let api_url = cli.openai_compatible_api_url.clone()
-                .ok_or_else(|| "OpenAI compatible API URL is required".to_string())?;
-
-            let openai_compatible_config = OpenAICompatibleConfig {
-                id: provider_id.clone(),
-                provider: "openai_compatible".to_string(),
-                api_key,
-                api_url,
-                model: cli.openai_compatible_model.clone(),
-                max_tokens: cli.max_tokens,
-                temperature: cli.temperature,
-            };
-            config.providers.push(ProviderConfig::OpenAICompatible(openai_compatible_config));
+                let simple_free_config = SimpleFreeOpenRouterConfig {
+                    id: provider_id.clone(),
+                    provider: "simple_free_openrouter".to_string(),
+                    api_key,
+                    max_tokens: args.max_tokens,
+                    temperature: args.temperature,
+                    model_stats: std::collections::HashMap::new(),
+                    last_used_model: None,
+                    last_config_update: chrono::Utc::now(),
+                };
+                ProviderConfig::SimpleFreeOpenRouter(simple_free_config)
+            }
+            ProviderKind::Ollama => {
+                let ollama_config = OllamaConfig {
+                    id: provider_id.clone(),
+                    provider: "ollama".to_string(),
+                    model: args.model.clone().unwrap_or_else(|| "llama2".to_string()),
+                    url: args.url.clone().unwrap_or_else(|| "http://localhost:11434".to_string()),
+                    max_tokens: args.max_tokens,
+                    temperature: args.temperature,
+                };
+                ProviderConfig::Ollama(ollama_config)
+            }
+            ProviderKind::OpenaiCompatible => {
+                let api_key = args.api_key.clone()
+                    .ok_or_else(|| "OpenAI compatible API key is required (--api-key)".to_string())?;
+                let api_url = args.url.clone()
+                    .ok_or_else(|| "OpenAI compatible API URL is required (--url)".to_string())?;
+
+                let openai_compatible_config = OpenAICompatibleConfig {
+                    id: provider_id.clone(),
+                    provider: "openai_compatible".to_string(),
+                    api_key,
+                    api_url,
+                    model: args.model.clone().unwrap_or_else(|| "gpt-3.5-turbo".to_string()),
+                    max_tokens: args.max_tokens,
+                    temperature: args.temperature,
+                };
+                ProviderConfig::OpenAICompatible(openai_compatible_config)
+            }
+        };
+
+        Self::add_provider(provider, provider_id)
+    }
+
+    /// Add a provider and make it the active one under the config lock. The setup prompts run
+    /// before it, so a slow answer doesn't hold the lock.
+    fn add_provider(provider: ProviderConfig, provider_id: String) -> Result<Self, String> {
+        Self::update(|config| {
+            config.providers.push(provider);
             config.active_provider = provider_id;
-        }
+            Ok(())
+        })
+    }
+}
 
-        // Save the configuration
-        let config_path = dirs::home_dir()
-            .ok_or_else(|| "Could not find home directory".to_string())?
-            .join(".aicommit.json");
 
-        let content = serde_json::to_string_pretty(&config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
 
-        fs::write(&config_path, content)
-            .map_err(|e| format!("Failed to write config file: {}", e))?;
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
 
-        Ok(config)
+    #[test]
+    fn test_legacy_flags_map_to_subcommands() {
+        let resolve = |args: &[&str]| Cli::try_parse_from(args).unwrap().legacy_command().unwrap();
+
+        let (command, deprecated) = resolve(&["aicommit", "--add", "--push"]);
+        assert!(matches!(command, Commands::Commit(ref args) if args.add && args.git.push));
+        assert!(deprecated.is_none());
+
+        let (command, deprecated) = resolve(&["aicommit", "--watch", "--wait-for-edit", "30s", "--push"]);
+        assert!(matches!(command, Commands::Watch(ref args) if args.wait_for_edit.as_deref() == Some("30s") && args.git.push));
+        assert_eq!(deprecated.map(|(flag, _)| flag), Some("--watch"));
+
+        let (command, _) = resolve(&["aicommit", "--add-ollama", "--ollama-model", "llama3"]);
+        assert!(matches!(command, Commands::Provider { action: ProviderCommand::Add(ref args) }
+            if args.kind == Some(ProviderKind::Ollama) && args.model.as_deref() == Some("llama3")));
+
+        let (command, _) = resolve(&["aicommit", "--unjail-all"]);
+        assert!(matches!(command, Commands::Models { action: ModelsCommand::Unjail { all: true, .. }, .. }));
     }
 }
-
@@ -1,7 +1,12 @@
 // Utility functions
 
 use std::fs;
-use crate::types::*;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fs2::FileExt;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 
 // From: 016_function_default_retry_attempts.rs
 // Used by serde(default) in types.rs
@@ -62,30 +67,64 @@ pub fn parse_duration(duration_str: &str) -> Result<std::time::Duration, String>
     }
 }
 
-// From: 045_function_save_simple_free_config.rs
-pub fn save_simple_free_config(config: &SimpleFreeOpenRouterConfig) -> Result<(), String> {
-    let config_path = dirs::home_dir()
-        .ok_or_else(|| "Could not find home directory".to_string())?
-        .join(".aicommit.json");
-        
-    let mut full_config = Config::load()?;
-    
-    // Update the provider in the full config
-    for provider in &mut full_config.providers {
-        if let ProviderConfig::SimpleFreeOpenRouter(simple_config) = provider {
-            if simple_config.id == config.id {
-                *simple_config = config.clone();
-                break;
-            }
-        }
+/// Write a file that may contain credentials, readable only by the current user (0600 on Unix).
+/// The content goes to a temporary file in the same directory which is then renamed over the
+/// target, so readers never see a half-written file.
+pub fn write_private_file(path: &Path, content: &str) -> Result<(), String> {
+    static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path.file_name()
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.{}.tmp",
+        file_name, std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+    let result = (|| {
+        let mut file = open_private(&temp_path)?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", temp_path.display(), e))?;
+        file.sync_all()
+            .map_err(|e| format!("Failed to flush {}: {}", temp_path.display(), e))?;
+        fs::rename(&temp_path, path)
+            .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
     }
-    
-    let content = serde_json::to_string_pretty(&full_config)
-        .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        
-    fs::write(&config_path, content)
-        .map_err(|e| format!("Failed to write config file: {}", e))?;
-    
-    Ok(())
+    result
 }
 
+/// Run `f` while holding an exclusive advisory lock on `<path>.lock`.
+/// The lock lives in a separate file because `write_private_file` replaces the target's inode.
+pub fn with_file_lock<T>(path: &Path, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let file_name = path.file_name()
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?
+        .to_string_lossy();
+    let lock_path = path.with_file_name(format!("{}.lock", file_name));
+
+    let lock_file = open_private(&lock_path)?;
+    lock_file.lock_exclusive()
+        .map_err(|e| format!("Failed to lock {}: {}", lock_path.display(), e))?;
+
+    let result = f();
+    let _ = FileExt::unlock(&lock_file);
+    result
+}
+
+fn open_private(path: &Path) -> Result<fs::File, String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let file = options.open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+    // The mode above only applies to newly created files, so tighten existing ones too
+    #[cfg(unix)]
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to set permissions on {}: {}", path.display(), e))?;
+
+    Ok(file)
+}